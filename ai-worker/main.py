@@ -5,10 +5,40 @@ import os
 from datetime import datetime
 from typing import Any, Dict, Optional
 
+import msgpack
 import redis.asyncio as redis
 from ai_providers import AIProvider, OpenAIProvider, AnthropicProvider
 from recipe_extractor import RecipeExtractor
 
+AI_QUEUE = "queue:ai_processing"
+
+
+def decode_message_fields(fields: Dict[bytes, bytes]) -> Dict[str, Any]:
+    """Decode a raw queue message read without decode_responses.
+    
+    video_data is JSON unless video_data_format names another encoding
+    (AI_QUEUE_FORMAT on the video worker), so it stays bytes until its
+    format is known; every other field is UTF-8 text. The decoded
+    video_data is returned as a dict, or None when the message has none.
+    """
+    text: Dict[str, Any] = {
+        key.decode(): value.decode() for key, value in fields.items() if key != b"video_data"
+    }
+    
+    raw = fields.get(b"video_data")
+    if not raw:
+        text["video_data"] = None
+        return text
+    
+    video_data_format = text.get("video_data_format", "json")
+    if video_data_format == "msgpack":
+        text["video_data"] = msgpack.unpackb(raw, raw=False)
+    elif video_data_format == "json":
+        text["video_data"] = json.loads(raw.decode())
+    else:
+        raise ValueError(f"Unsupported video_data_format: {video_data_format}")
+    return text
+
 
 class AIWorker:
     """Worker that processes video data and extracts recipes using AI."""
@@ -16,6 +46,8 @@ class AIWorker:
     def __init__(self):
         self.redis_url = os.getenv("REDIS_URL", "redis://localhost:6379")
         self.redis: Optional[redis.Redis] = None
+        # Reads the AI queue as bytes, since MessagePack payloads aren't UTF-8
+        self.stream_redis: Optional[redis.Redis] = None
         self.group_name = "ai-workers"
         self.consumer_name = f"consumer-{os.getpid()}"
         self.ai_provider = self._create_ai_provider()
@@ -39,11 +71,12 @@ class AIWorker:
     async def connect(self):
         """Connect to Redis."""
         self.redis = redis.from_url(self.redis_url, decode_responses=True)
+        self.stream_redis = redis.from_url(self.redis_url)
         
         # Create consumer group
         try:
             await self.redis.xgroup_create(
-                "queue:ai_processing",
+                AI_QUEUE,
                 self.group_name,
                 id="$",
                 mkstream=True
@@ -56,6 +89,8 @@ class AIWorker:
         """Close Redis connection."""
         if self.redis:
             await self.redis.close()
+        if self.stream_redis:
+            await self.stream_redis.close()
     
     async def run(self):
         """Main worker loop."""
@@ -74,10 +109,10 @@ class AIWorker:
     async def process_next_job(self) -> bool:
         """Process the next job from the queue. Returns True if a job was processed."""
         # Read from stream
-        messages = await self.redis.xreadgroup(
+        messages = await self.stream_redis.xreadgroup(
             groupname=self.group_name,
             consumername=self.consumer_name,
-            streams={"queue:ai_processing": ">"},
+            streams={AI_QUEUE: ">"},
             count=1,
             block=5000,
         )
@@ -86,20 +121,26 @@ class AIWorker:
             return False
         
         stream_name, stream_messages = messages[0]
-        message_id, fields = stream_messages[0]
+        stream_name = stream_name.decode()
+        message_id, raw_fields = stream_messages[0]
+        message_id = message_id.decode()
+        job_id = None
         
         try:
+            fields = decode_message_fields(raw_fields)
             job_id = fields.get("job_id")
-            video_data_json = fields.get("video_data")
+            video_data = fields["video_data"]
             
-            if not job_id or not video_data_json:
+            if not job_id or video_data is None:
                 print(f"Invalid message format: {fields}")
                 await self._ack_message(stream_name, message_id)
                 return True
             
             print(f"Processing job {job_id}")
             
-            video_data = json.loads(video_data_json)
+            # Sent alongside video_data since schema_version 1 of the message
+            if fields.get("source_url"):
+                video_data.setdefault("source_url", fields["source_url"])
             
             # Extract recipe using AI
             recipe = await self.recipe_extractor.extract_recipe(job_id, video_data)
@@ -133,7 +174,7 @@ class AIWorker:
             job["status"] = status
             job["progress"] = progress
             job["updated_at"] = datetime.utcnow().isoformat()
-            await self.redis.set(job_key, json.dumps(job))
+            await self.redis.set(job_key, json.dumps(job), keepttl=True)
     
     async def _fail_job(self, job_id: str, error: str):
         """Mark job as failed."""
@@ -145,7 +186,7 @@ class AIWorker:
         if job_data:
             job = json.loads(job_data)
             job["error_message"] = error
-            await self.redis.set(job_key, json.dumps(job))
+            await self.redis.set(job_key, json.dumps(job), keepttl=True)
     
     async def _store_recipe(self, job_id: str, recipe: Dict[str, Any]):
         """Store extracted recipe in Redis."""
@@ -104,10 +104,10 @@ Respond in valid JSON format matching the Recipe schema."""
         
         for frame in frames:
             if frame.get('ocr_text'):
-                timestamp = frame.get('timestamp', 0)
+                timestamp = frame.get('formatted_timestamp') or f"{frame.get('timestamp', 0)}s"
                 text = frame['ocr_text'].strip()
                 if text and len(text) > 3:  # Filter out very short text
-                    ocr_texts.append(f"[{timestamp}s] {text}")
+                    ocr_texts.append(f"[{timestamp}] {text}")
         
         if ocr_texts:
             parts.extend(ocr_texts[:50])  # Limit to 50 frames
@@ -38,6 +38,7 @@ class JobResponse(BaseModel):
 class FrameData(BaseModel):
     """Extracted frame data."""
     timestamp: float = Field(..., description="Timestamp in seconds")
+    formatted_timestamp: str = Field("", description="Timestamp as HH:MM:SS.mmm")
     frame_path: str = Field(..., description="Path to extracted frame image")
     ocr_text: Optional[str] = Field(None, description="Text extracted from this frame via OCR")
     is_keyframe: bool = Field(False, description="Whether this is a scene change keyframe")
@@ -46,7 +47,8 @@ class FrameData(BaseModel):
 class VideoData(BaseModel):
     """Processed video data from Rust worker."""
     job_id: str
-    video_path: str
+    media_type: str = Field("video", description="\"video\", or \"images\" for an image post")
+    video_path: str = Field(..., description="Downloaded video; empty for an image post")
     duration_seconds: Optional[float] = None
     resolution: Optional[dict] = None
     fps: Optional[float] = None
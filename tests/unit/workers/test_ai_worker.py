@@ -1,7 +1,11 @@
 """Unit tests for the AI recipe extractor."""
+import json
+
+import msgpack
 import pytest
 from unittest.mock import AsyncMock, MagicMock
 
+from ai_worker.main import decode_message_fields
 from ai_worker.recipe_extractor import RecipeExtractor
 
 
@@ -154,3 +158,50 @@ class TestRecipeExtractor:
         assert "failed" in result["title"].lower()
         assert result["confidence_score"] == 0.0
         assert result["source_url"] == "https://example.com/video"
+
+
+class TestDecodeMessageFields:
+    """Tests for decoding raw AI queue messages."""
+    
+    VIDEO_DATA = {"job_id": "job-123", "frames": [{"timestamp": 1.5, "ocr_text": "2 cups flour"}]}
+    
+    def test_json_payload(self):
+        """Messages without video_data_format carry JSON."""
+        fields = decode_message_fields({
+            b"job_id": b"job-123",
+            b"source_url": b"https://example.com/reel",
+            b"video_data": json.dumps(self.VIDEO_DATA).encode(),
+        })
+        
+        assert fields["job_id"] == "job-123"
+        assert fields["source_url"] == "https://example.com/reel"
+        assert fields["video_data"] == self.VIDEO_DATA
+    
+    def test_msgpack_payload(self):
+        """MessagePack payloads are decoded from bytes that aren't valid UTF-8."""
+        raw = msgpack.packb(self.VIDEO_DATA)
+        with pytest.raises(UnicodeDecodeError):
+            raw.decode()
+        
+        fields = decode_message_fields({
+            b"job_id": b"job-123",
+            b"video_data_format": b"msgpack",
+            b"video_data": raw,
+        })
+        
+        assert fields["video_data"] == self.VIDEO_DATA
+    
+    def test_unknown_format(self):
+        """An encoding this worker can't read is an error, not a guess."""
+        with pytest.raises(ValueError):
+            decode_message_fields({
+                b"job_id": b"job-123",
+                b"video_data_format": b"protobuf",
+                b"video_data": b"\x0a\x07job-123",
+            })
+    
+    def test_missing_payload(self):
+        """A message without video_data decodes with video_data None."""
+        fields = decode_message_fields({b"job_id": b"job-123"})
+        
+        assert fields["video_data"] is None
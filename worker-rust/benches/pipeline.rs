@@ -0,0 +1,184 @@
+//! Benchmarks for the OCR and frame-selection stages.
+//!
+//! Run with `cargo bench`. OCR benches need Tesseract with "eng" data.
+//! The fixtures in benches/fixtures are small rendered text cards so OCR
+//! numbers are comparable across runs. `ocr_format` compares reading each
+//! fixture as a lossless PNG and as a JPEG frame. `ocr_batch` compares
+//! reading frames one by one against stacking them into OCR_BATCH_SIZE
+//! composites.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::path::PathBuf;
+
+use worker_rust::{dedupe_frames, limit_frames, process_frames, FrameData, OcrOptions, DEDUPE_DELTA_SECS};
+
+const FIXTURES: &[&str] = &["ingredients.png", "bake.png", "blank.png"];
+
+/// Fixtures with text, for comparing OCR across frame formats
+const TEXT_FIXTURES: &[&str] = &["ingredients.png", "bake.png"];
+
+/// Roughly what ffmpeg's `-q:v 2` (the FRAME_JPEG_QUALITY default) produces
+const JPEG_QUALITY: u8 = 90;
+
+fn fixture(name: &str) -> String {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("benches/fixtures")
+        .join(name)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn frame(timestamp: f64, is_keyframe: bool) -> FrameData {
+    FrameData {
+        timestamp,
+        // Never created; dedupe/limit only try to delete dropped frames
+        frame_path: format!("/nonexistent/bench_{}.jpg", (timestamp * 1000.0) as u64),
+        thumb_path: None,
+        ocr_text: None,
+        ocr_text_until: None,
+        ocr_boxes: None,
+        lang: None,
+        subtitle_text: None,
+        sharpness: None,
+        brightness: None,
+        is_keyframe,
+    }
+}
+
+/// OCR one image through the library entry point, as a one-frame reel
+async fn read_image(path: &str, options: &OcrOptions) -> Option<String> {
+    let image = FrameData {
+        frame_path: path.to_string(),
+        ..frame(0.0, true)
+    };
+    let (frames, _) = process_frames(vec![image], options.clone()).await.ok()?;
+    frames.into_iter().next()?.ocr_text
+}
+
+/// A 60s reel: regular frames every 2s plus a scene keyframe every ~1.3s
+fn synthetic_frames() -> Vec<FrameData> {
+    let mut frames: Vec<FrameData> = (0..30).map(|i| frame(i as f64 * 2.0, false)).collect();
+    frames.extend((0..45).map(|i| frame(0.4 + i as f64 * 1.3, true)));
+    frames.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    frames
+}
+
+fn bench_ocr(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("ocr");
+    group.sample_size(20);
+
+    for name in FIXTURES {
+        let path = fixture(name);
+        for (label, options) in [
+            ("text", OcrOptions::default()),
+            (
+                "boxes",
+                OcrOptions {
+                    word_boxes: true,
+                    ..OcrOptions::default()
+                },
+            ),
+        ] {
+            group.bench_with_input(BenchmarkId::new(label, name), &path, |b, path| {
+                b.to_async(&rt).iter(|| async { black_box(read_image(path, &options).await) });
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// Re-encode a PNG fixture as JPEG in `dir`, like a frame written with
+/// FRAME_FORMAT=jpeg
+fn jpeg_copy(png: &str, dir: &std::path::Path) -> String {
+    let rgb = image::open(png).unwrap().to_rgb8();
+    let path = dir.join(PathBuf::from(png).with_extension("jpg").file_name().unwrap());
+    let mut file = std::fs::File::create(&path).unwrap();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut file, JPEG_QUALITY)
+        .encode_image(&rgb)
+        .unwrap();
+    path.to_string_lossy().to_string()
+}
+
+fn bench_ocr_format(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let options = OcrOptions::default();
+    let mut group = c.benchmark_group("ocr_format");
+    group.sample_size(20);
+
+    for name in TEXT_FIXTURES {
+        let png = fixture(name);
+        let jpeg = jpeg_copy(&png, dir.path());
+
+        for (label, path) in [("png", &png), ("jpeg", &jpeg)] {
+            group.bench_with_input(BenchmarkId::new(label, name), path, |b, path| {
+                b.to_async(&rt).iter(|| async { black_box(read_image(path, &options).await) });
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// The text fixtures repeated into `count` frames, as a reel's worth of OCR
+fn fixture_frames(count: usize) -> Vec<FrameData> {
+    (0..count)
+        .map(|i| FrameData {
+            frame_path: fixture(TEXT_FIXTURES[i % TEXT_FIXTURES.len()]),
+            ..frame(i as f64 * 2.0, true)
+        })
+        .collect()
+}
+
+fn bench_ocr_batch(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let frames = fixture_frames(16);
+    let mut group = c.benchmark_group("ocr_batch");
+    group.sample_size(10);
+
+    for batch_size in [1, 4, 16] {
+        let options = OcrOptions {
+            batch_size,
+            // Repeated fixtures would otherwise be stripped as watermarks
+            watermark_threshold: None,
+            ..OcrOptions::default()
+        };
+        group.bench_with_input(BenchmarkId::new("frames_16", batch_size), &options, |b, options| {
+            b.to_async(&rt).iter(|| async {
+                black_box(process_frames(frames.clone(), options.clone()).await.ok())
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_frame_selection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frames");
+
+    group.bench_function("dedupe", |b| {
+        b.iter_batched(
+            synthetic_frames,
+            |frames| black_box(dedupe_frames(frames, DEDUPE_DELTA_SECS)),
+            criterion::BatchSize::SmallInput,
+        );
+    });
+
+    group.bench_function("dedupe_and_limit_30", |b| {
+        b.iter_batched(
+            synthetic_frames,
+            |frames| {
+                let frames = dedupe_frames(frames, DEDUPE_DELTA_SECS);
+                black_box(limit_frames(frames, 30))
+            },
+            criterion::BatchSize::SmallInput,
+        );
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_ocr, bench_ocr_format, bench_ocr_batch, bench_frame_selection);
+criterion_main!(benches);
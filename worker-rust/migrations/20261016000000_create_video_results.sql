@@ -0,0 +1,13 @@
+-- One row per job that finished the video pipeline
+CREATE TABLE IF NOT EXISTS video_results (
+    job_id        TEXT PRIMARY KEY,
+    url           TEXT NOT NULL,
+    status        TEXT NOT NULL,
+    video_info    JSONB NOT NULL,
+    transcription TEXT NOT NULL DEFAULT '',
+    frame_count   INTEGER NOT NULL DEFAULT 0,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+    completed_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS video_results_completed_at_idx ON video_results (completed_at);
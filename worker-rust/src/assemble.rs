@@ -0,0 +1,440 @@
+use serde::{Deserialize, Serialize};
+
+use crate::audio::TranscriptSegment;
+use crate::video::FrameData;
+
+/// Where a timeline entry came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSource {
+    /// Text read off a frame
+    Ocr,
+    /// A transcribed speech segment
+    Speech,
+}
+
+/// One piece of text placed on the video timeline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    /// Seconds from the start of the video
+    pub timestamp: f64,
+    pub source: EventSource,
+    pub text: String,
+}
+
+/// Interleave frame OCR text and speech segments by timestamp.
+///
+/// Frames without OCR text are skipped. Speech is placed at the start of its
+/// segment; on equal timestamps OCR comes first.
+pub fn build_timeline(frames: &[FrameData], segments: &[TranscriptSegment]) -> Vec<TimelineEvent> {
+    let ocr = frames.iter().filter_map(|frame| {
+        let text = frame.ocr_text.as_deref()?.trim();
+        if text.is_empty() {
+            return None;
+        }
+        Some(TimelineEvent {
+            timestamp: frame.timestamp,
+            source: EventSource::Ocr,
+            text: text.to_string(),
+        })
+    });
+    
+    let speech = segments.iter().map(|segment| TimelineEvent {
+        timestamp: segment.start,
+        source: EventSource::Speech,
+        text: segment.text.clone(),
+    });
+    
+    let mut events: Vec<TimelineEvent> = ocr.chain(speech).collect();
+    // Stable sort keeps OCR ahead of speech at the same instant
+    events.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    events
+}
+
+/// One proposed recipe within a compilation video, in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Sensitivity of compilation splitting; lower values split more eagerly
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentOptions {
+    /// Minimum pause in speech, in seconds, that can separate two recipes
+    pub min_gap_secs: f64,
+    /// Segments shorter than this are merged into their neighbour
+    pub min_segment_secs: f64,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        Self {
+            min_gap_secs: 1.5,
+            min_segment_secs: 8.0,
+        }
+    }
+}
+
+impl SegmentOptions {
+    /// Read SEGMENT_MIN_GAP_SECS and SEGMENT_MIN_LENGTH_SECS when
+    /// SPLIT_SEGMENTS is enabled; None when splitting is off
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("SPLIT_SEGMENTS")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+        
+        let defaults = Self::default();
+        let secs = |name: &str, default: f64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .unwrap_or(default)
+        };
+        
+        Some(Self {
+            min_gap_secs: secs("SEGMENT_MIN_GAP_SECS", defaults.min_gap_secs),
+            min_segment_secs: secs("SEGMENT_MIN_LENGTH_SECS", defaults.min_segment_secs),
+        })
+    }
+}
+
+/// Seconds a scene change may sit outside a speech pause and still count
+const GAP_TOLERANCE_SECS: f64 = 0.5;
+
+/// Propose recipe boundaries for compilation videos.
+///
+/// A boundary is a scene-change keyframe that falls inside a pause in speech
+/// of at least `min_gap_secs`. Without a transcript there is nothing to
+/// corroborate a cut with, so the whole video is one segment. Always returns
+/// at least one segment covering `[0, duration]`.
+pub fn detect_segments(
+    frames: &[FrameData],
+    segments: &[TranscriptSegment],
+    duration: f64,
+    options: &SegmentOptions,
+) -> Vec<Segment> {
+    let mut speech: Vec<&TranscriptSegment> = segments.iter().collect();
+    speech.sort_by(|a, b| a.start.total_cmp(&b.start));
+    
+    let pauses: Vec<(f64, f64)> = speech
+        .windows(2)
+        .map(|pair| (pair[0].end, pair[1].start))
+        .filter(|(start, end)| end - start >= options.min_gap_secs)
+        .collect();
+    
+    let mut cuts: Vec<f64> = frames
+        .iter()
+        .filter(|frame| frame.is_keyframe)
+        .map(|frame| frame.timestamp)
+        .filter(|t| {
+            pauses
+                .iter()
+                .any(|(start, end)| *t >= start - GAP_TOLERANCE_SECS && *t <= end + GAP_TOLERANCE_SECS)
+        })
+        .collect();
+    cuts.sort_by(f64::total_cmp);
+    
+    let mut result = Vec::new();
+    let mut start = 0.0;
+    for cut in cuts {
+        // Both sides of the cut must be long enough to be a recipe
+        if cut - start >= options.min_segment_secs && duration - cut >= options.min_segment_secs {
+            result.push(Segment { start, end: cut });
+            start = cut;
+        }
+    }
+    result.push(Segment { start, end: duration });
+    
+    result
+}
+
+/// One recipe step: a run of narration and the frame that best shows it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    /// Seconds from the start of the video
+    pub start: f64,
+    pub end: f64,
+    /// Narration for the step, its speech segments joined
+    pub text: String,
+    /// Sharpest frame within the step, or the nearest one when none falls inside
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_timestamp: Option<f64>,
+}
+
+/// Heuristics for grouping speech into steps
+#[derive(Debug, Clone, Copy)]
+pub struct StepOptions {
+    /// A pause in speech at least this long always starts a new step
+    pub min_pause_secs: f64,
+    /// Start a new step at a sentence that opens with a cooking verb
+    /// ("Add...", "Then stir..."), even without a pause
+    pub split_on_imperative: bool,
+    /// Steps shorter than this are not split on imperatives, so a quick
+    /// "Salt. Pepper. Stir." stays one step
+    pub min_step_secs: f64,
+}
+
+impl Default for StepOptions {
+    fn default() -> Self {
+        Self {
+            min_pause_secs: 1.0,
+            split_on_imperative: true,
+            min_step_secs: 3.0,
+        }
+    }
+}
+
+impl StepOptions {
+    /// Read STEP_MIN_PAUSE_SECS, STEP_SPLIT_ON_IMPERATIVE, and STEP_MIN_LENGTH_SECS
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let secs = |name: &str, default: f64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .unwrap_or(default)
+        };
+        
+        Self {
+            min_pause_secs: secs("STEP_MIN_PAUSE_SECS", defaults.min_pause_secs),
+            split_on_imperative: std::env::var("STEP_SPLIT_ON_IMPERATIVE")
+                .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+                .unwrap_or(defaults.split_on_imperative),
+            min_step_secs: secs("STEP_MIN_LENGTH_SECS", defaults.min_step_secs),
+        }
+    }
+}
+
+/// Verbs that open an instruction in recipe narration
+const IMPERATIVE_VERBS: &[&str] = &[
+    "add", "bake", "beat", "blend", "boil", "bring", "broil", "brush", "chop", "combine", "cook",
+    "cover", "cut", "dice", "drain", "drizzle", "flip", "fold", "fry", "garnish", "grate", "grill",
+    "heat", "knead", "let", "marinate", "mince", "mix", "place", "pour", "preheat", "put", "reduce",
+    "remove", "roast", "roll", "season", "serve", "simmer", "slice", "spread", "sprinkle", "stir",
+    "strain", "take", "toss", "transfer", "whisk",
+];
+
+/// Filler that can precede the verb without changing the sentence's role
+const STEP_LEAD_INS: &[&str] = &["and", "finally", "next", "now", "once", "so", "then"];
+
+/// True when `text` reads as an instruction: its first word, after any
+/// lead-ins like "then" or "now", is a cooking verb
+fn is_imperative(text: &str) -> bool {
+    let words = text
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .filter(|w| !w.is_empty());
+    for word in words {
+        if STEP_LEAD_INS.contains(&word.as_str()) {
+            continue;
+        }
+        return IMPERATIVE_VERBS.contains(&word.as_str());
+    }
+    false
+}
+
+/// True when `text` finishes a sentence, so the next segment starts one
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end().ends_with(['.', '!', '?'])
+}
+
+/// Group speech segments into ordered steps and attach the best frame to each.
+///
+/// A new step starts after a pause of at least `min_pause_secs`, or (with
+/// `split_on_imperative`) at a sentence opening with a cooking verb once the
+/// current step is `min_step_secs` long. Without speech there are no steps.
+pub fn build_steps(frames: &[FrameData], segments: &[TranscriptSegment], options: &StepOptions) -> Vec<Step> {
+    let mut speech: Vec<&TranscriptSegment> = segments.iter().filter(|s| !s.text.trim().is_empty()).collect();
+    speech.sort_by(|a, b| a.start.total_cmp(&b.start));
+    
+    let mut groups: Vec<Vec<&TranscriptSegment>> = Vec::new();
+    for segment in speech {
+        // Groups are never empty: each one is created with its first segment
+        let split = match groups.last() {
+            None => true,
+            Some(group) => {
+                let (first, last) = (group[0], group[group.len() - 1]);
+                segment.start - last.end >= options.min_pause_secs
+                    || (options.split_on_imperative
+                        && segment.start - first.start >= options.min_step_secs
+                        && ends_sentence(&last.text)
+                        && is_imperative(&segment.text))
+            }
+        };
+        if split {
+            groups.push(vec![segment]);
+        } else if let Some(group) = groups.last_mut() {
+            group.push(segment);
+        }
+    }
+    
+    groups
+        .into_iter()
+        .map(|group| {
+            let start = group[0].start;
+            let end = group.iter().map(|s| s.end).fold(start, f64::max);
+            let text = group.iter().map(|s| s.text.trim()).collect::<Vec<_>>().join(" ");
+            let frame = best_frame(frames, start, end);
+            Step {
+                start,
+                end,
+                text,
+                frame_path: frame.map(|f| f.frame_path.clone()),
+                frame_timestamp: frame.map(|f| f.timestamp),
+            }
+        })
+        .collect()
+}
+
+/// The sharpest frame inside `[start, end]`, preferring keyframes and then
+/// the one nearest the middle; falls back to the frame nearest the range
+fn best_frame(frames: &[FrameData], start: f64, end: f64) -> Option<&FrameData> {
+    let middle = (start + end) / 2.0;
+    let inside = frames.iter().filter(|f| f.timestamp >= start && f.timestamp <= end);
+    let best = inside.max_by(|a, b| {
+        a.sharpness
+            .unwrap_or(0.0)
+            .total_cmp(&b.sharpness.unwrap_or(0.0))
+            .then(a.is_keyframe.cmp(&b.is_keyframe))
+            .then((b.timestamp - middle).abs().total_cmp(&(a.timestamp - middle).abs()))
+    });
+    
+    best.or_else(|| {
+        let distance = |f: &FrameData| (start - f.timestamp).max(0.0) + (f.timestamp - end).max(0.0);
+        frames.iter().min_by(|a, b| distance(a).total_cmp(&distance(b)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn frame(timestamp: f64, is_keyframe: bool, sharpness: f64) -> FrameData {
+        serde_json::from_value(json!({
+            "timestamp": timestamp,
+            "frame_path": format!("frame_{}.jpg", timestamp),
+            "is_keyframe": is_keyframe,
+            "sharpness": sharpness,
+        }))
+        .unwrap()
+    }
+    
+    fn speech(start: f64, end: f64, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start,
+            end,
+            text: text.to_string(),
+            no_speech_prob: None,
+            avg_logprob: None,
+        }
+    }
+    
+    #[test]
+    fn imperatives_are_recognised_after_lead_ins() {
+        assert!(is_imperative("Add the garlic."));
+        assert!(is_imperative("Then, stir well"));
+        assert!(is_imperative("and now WHISK the eggs"));
+        assert!(!is_imperative("The sauce thickens."));
+        assert!(!is_imperative("then"));
+        assert!(!is_imperative(""));
+    }
+    
+    #[test]
+    fn a_pause_starts_a_new_step() {
+        let segments = [
+            speech(0.0, 2.0, "Chop the onions"),
+            speech(2.2, 3.0, "really fine."),
+            speech(4.5, 6.0, "The pan should be hot."),
+        ];
+        let steps = build_steps(&[], &segments, &StepOptions::default());
+        
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].text, "Chop the onions really fine.");
+        assert_eq!((steps[0].start, steps[0].end), (0.0, 3.0));
+        assert_eq!(steps[1].text, "The pan should be hot.");
+        assert_eq!(steps[1].frame_path, None);
+    }
+    
+    #[test]
+    fn imperative_split_waits_for_min_step_secs() {
+        let segments = [
+            speech(0.0, 1.0, "Salt the pan."),
+            // An imperative, but the step is only a second old
+            speech(1.0, 2.0, "Stir it."),
+            speech(2.0, 3.2, "It smells great."),
+            speech(3.2, 5.0, "Add the garlic."),
+        ];
+        let steps = build_steps(&[], &segments, &StepOptions::default());
+        let texts: Vec<&str> = steps.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["Salt the pan. Stir it. It smells great.", "Add the garlic."]);
+        
+        let options = StepOptions {
+            split_on_imperative: false,
+            ..StepOptions::default()
+        };
+        assert_eq!(build_steps(&[], &segments, &options).len(), 1);
+    }
+    
+    #[test]
+    fn best_frame_prefers_the_sharpest_inside_the_step() {
+        let frames = [frame(1.0, false, 10.0), frame(2.0, false, 90.0), frame(3.0, true, 50.0), frame(8.0, true, 100.0)];
+        
+        assert_eq!(best_frame(&frames, 0.0, 4.0).map(|f| f.timestamp), Some(2.0));
+        // Nothing inside: the frame nearest the range
+        assert_eq!(best_frame(&frames, 4.0, 5.0).map(|f| f.timestamp), Some(3.0));
+        assert!(best_frame(&[], 0.0, 4.0).is_none());
+        
+        let steps = build_steps(&frames, &[speech(0.5, 3.5, "Fold in the flour.")], &StepOptions::default());
+        assert_eq!(steps[0].frame_path.as_deref(), Some("frame_2.jpg"));
+        assert_eq!(steps[0].frame_timestamp, Some(2.0));
+    }
+    
+    fn segment(start: f64, end: f64) -> Segment {
+        Segment { start, end }
+    }
+    
+    #[test]
+    fn a_scene_change_in_a_speech_pause_splits_the_video() {
+        let narration = [speech(0.0, 19.0, "First dish."), speech(22.0, 40.0, "Second dish.")];
+        let frames = [frame(5.0, true, 0.0), frame(20.5, true, 0.0), frame(30.0, false, 0.0)];
+        
+        let segments = detect_segments(&frames, &narration, 40.0, &SegmentOptions::default());
+        assert_eq!(segments, vec![segment(0.0, 20.5), segment(20.5, 40.0)]);
+        
+        // Just outside the pause still counts, within the tolerance
+        let segments = detect_segments(&[frame(18.6, true, 0.0)], &narration, 40.0, &SegmentOptions::default());
+        assert_eq!(segments, vec![segment(0.0, 18.6), segment(18.6, 40.0)]);
+        let segments = detect_segments(&[frame(18.4, true, 0.0)], &narration, 40.0, &SegmentOptions::default());
+        assert_eq!(segments, vec![segment(0.0, 40.0)]);
+    }
+    
+    #[test]
+    fn short_pauses_and_short_segments_do_not_split() {
+        let options = SegmentOptions::default();
+        let frames = [frame(20.5, true, 0.0)];
+        
+        // A one-second pause is under min_gap_secs
+        let narration = [speech(0.0, 20.0, "Stir."), speech(21.0, 40.0, "Serve.")];
+        assert_eq!(detect_segments(&frames, &narration, 40.0, &options), vec![segment(0.0, 40.0)]);
+        
+        // The remainder after the cut would be shorter than min_segment_secs
+        let narration = [speech(0.0, 19.0, "Stir."), speech(22.0, 25.0, "Serve.")];
+        assert_eq!(detect_segments(&frames, &narration, 25.0, &options), vec![segment(0.0, 25.0)]);
+    }
+    
+    #[test]
+    fn without_a_transcript_the_video_is_one_segment() {
+        let frames = [frame(10.0, true, 0.0), frame(20.0, true, 0.0)];
+        assert_eq!(detect_segments(&frames, &[], 30.0, &SegmentOptions::default()), vec![segment(0.0, 30.0)]);
+        assert_eq!(detect_segments(&[], &[], 0.0, &SegmentOptions::default()), vec![segment(0.0, 0.0)]);
+    }
+}
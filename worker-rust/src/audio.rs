@@ -1,27 +1,248 @@
-use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::path::Path;
-use tracing::{info, warn};
+use tracing::{info, instrument, warn};
 
-/// Extract audio from video file
-pub async fn extract_audio(video_path: &str, output_dir: &str, job_id: &str) -> Result<String> {
+use crate::error::{Result, WorkerError};
+use crate::pipeline;
+use crate::timecode;
+use crate::tools;
+use crate::transcribe::{self, Transcriber};
+use crate::video::VideoInfo;
+
+/// Clips shorter than this hold too little speech to be worth transcribing
+pub const MIN_AUDIO_SECS: f64 = 1.0;
+
+/// Whisper's default no_speech_threshold
+const DEFAULT_NO_SPEECH_THRESHOLD: f64 = 0.6;
+
+/// Whisper's default logprob_threshold
+const DEFAULT_LOGPROB_THRESHOLD: f64 = -1.0;
+
+/// A timed span of speech
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+    /// Whisper's probability that the span holds no speech at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_speech_prob: Option<f64>,
+    /// Mean token log probability; low values mean Whisper was guessing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_logprob: Option<f64>,
+}
+
+/// Full transcription plus its timed segments
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    pub text: String,
+    pub segments: Vec<TranscriptSegment>,
+    /// Segments dropped as likely hallucinations
+    pub filtered_segments: usize,
+}
+
+/// Drops segments Whisper likely hallucinated over silence or music.
+///
+/// Uses Whisper's own rule for treating a window as silent: a high
+/// no-speech probability together with a low average log probability.
+/// Either signal alone also fires on quiet but real narration. Segments
+/// without the scores (non-Whisper backends) are always kept.
+#[derive(Debug, Clone, Copy)]
+pub struct HallucinationFilter {
+    pub no_speech_threshold: f64,
+    pub logprob_threshold: f64,
+}
+
+impl Default for HallucinationFilter {
+    fn default() -> Self {
+        Self {
+            no_speech_threshold: DEFAULT_NO_SPEECH_THRESHOLD,
+            logprob_threshold: DEFAULT_LOGPROB_THRESHOLD,
+        }
+    }
+}
+
+impl HallucinationFilter {
+    /// Read TRANSCRIPT_NO_SPEECH_THRESHOLD and TRANSCRIPT_LOGPROB_THRESHOLD
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: f64| threshold(std::env::var(name).ok().as_deref(), default);
+        Self {
+            no_speech_threshold: read("TRANSCRIPT_NO_SPEECH_THRESHOLD", DEFAULT_NO_SPEECH_THRESHOLD),
+            logprob_threshold: read("TRANSCRIPT_LOGPROB_THRESHOLD", DEFAULT_LOGPROB_THRESHOLD),
+        }
+    }
+    
+    pub fn is_hallucination(&self, segment: &TranscriptSegment) -> bool {
+        match (segment.no_speech_prob, segment.avg_logprob) {
+            (Some(no_speech), Some(logprob)) => {
+                no_speech > self.no_speech_threshold && logprob < self.logprob_threshold
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A threshold setting, or `default` when it is unset or not a finite number
+fn threshold(raw: Option<&str>, default: f64) -> f64 {
+    raw.and_then(|v| v.trim().parse::<f64>().ok()).filter(|v| v.is_finite()).unwrap_or(default)
+}
+
+/// Level adjustment applied while exporting audio for Whisper
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioNormalization {
+    Off,
+    /// EBU R128 loudness normalization
+    Loudnorm,
+    /// Dynamic range compression; gentler on noisy sources
+    Compress,
+}
+
+impl AudioNormalization {
+    /// Read AUDIO_NORMALIZE: "loudnorm" (or "true"), "compress", or off (default).
+    ///
+    /// Normalization helps quiet narration under music but also amplifies
+    /// background noise, so it stays opt-in.
+    pub fn from_env() -> Self {
+        match std::env::var("AUDIO_NORMALIZE")
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "loudnorm" | "true" | "1" | "yes" => AudioNormalization::Loudnorm,
+            "compress" => AudioNormalization::Compress,
+            _ => AudioNormalization::Off,
+        }
+    }
+
+    fn filter(&self) -> Option<&'static str> {
+        match self {
+            AudioNormalization::Off => None,
+            AudioNormalization::Loudnorm => Some("loudnorm=I=-16:TP=-1.5:LRA=11"),
+            AudioNormalization::Compress => {
+                Some("acompressor=threshold=-21dB:ratio=4:attack=5:release=50:makeup=2")
+            }
+        }
+    }
+}
+
+/// An audio stream in the source video
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioTrack {
+    /// Position among the audio streams (ffmpeg `0:a:N`)
+    pub index: usize,
+    /// ISO 639 language tag from the container, if any
+    pub language: Option<String>,
+    pub codec: String,
+    pub channels: u32,
+}
+
+/// Which audio stream to transcribe
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackSelector {
+    /// First audio stream (ffmpeg's default pick for most containers)
+    First,
+    /// Nth audio stream, zero based
+    Index(usize),
+    /// First stream tagged with this language, e.g. "eng"
+    Language(String),
+}
+
+impl TrackSelector {
+    /// Read AUDIO_TRACK: a stream index ("1") or a language tag ("spa").
+    /// Unset selects the first stream.
+    pub fn from_env() -> Self {
+        match std::env::var("AUDIO_TRACK").ok().map(|v| v.trim().to_lowercase()) {
+            Some(v) if v.is_empty() => TrackSelector::First,
+            Some(v) => match v.parse() {
+                Ok(index) => TrackSelector::Index(index),
+                Err(_) => TrackSelector::Language(v),
+            },
+            None => TrackSelector::First,
+        }
+    }
+}
+
+/// The audio streams process_video found, without probing the video again
+pub fn audio_tracks(video_info: &VideoInfo) -> Vec<AudioTrack> {
+    video_info
+        .audio_streams
+        .iter()
+        .enumerate()
+        .map(|(index, stream)| AudioTrack {
+            index,
+            language: stream.language.clone(),
+            codec: stream.codec.clone(),
+            channels: stream.channels,
+        })
+        .collect()
+}
+
+/// Pick the audio stream to transcribe; None when the video has no audio.
+///
+/// An index or language that doesn't match falls back to the first stream.
+pub fn select_track(video_info: &VideoInfo, selector: &TrackSelector) -> Option<AudioTrack> {
+    let tracks = audio_tracks(video_info);
+
+    let chosen = match selector {
+        TrackSelector::First => None,
+        TrackSelector::Index(index) => tracks.get(*index),
+        TrackSelector::Language(language) => tracks
+            .iter()
+            .find(|t| t.language.as_deref() == Some(language.as_str())),
+    };
+    
+    if chosen.is_none() && *selector != TrackSelector::First && !tracks.is_empty() {
+        warn!("No audio stream matches {:?}, using the first", selector);
+    }
+    
+    let track = chosen.or_else(|| tracks.first()).cloned();
+    if let Some(track) = &track {
+        info!(
+            "Using audio stream {} of {} (language: {})",
+            track.index,
+            tracks.len(),
+            track.language.as_deref().unwrap_or("unknown")
+        );
+    }
+    
+    track
+}
+
+/// Extract audio from video file, from `track` if given
+#[instrument(skip_all, fields(job_id = %job_id))]
+pub async fn extract_audio(
+    video_path: &str,
+    job_dir: &str,
+    job_id: &str,
+    track: Option<&AudioTrack>,
+    normalization: AudioNormalization,
+) -> Result<String> {
     info!("Extracting audio from {}", video_path);
     
-    let output_path = Path::new(output_dir).join(format!("{}_audio.wav", job_id));
+    let output_path = Path::new(job_dir).join("audio.wav");
     let output_str = output_path.to_string_lossy();
     
-    let output = tokio::process::Command::new("ffmpeg")
-        .args(&[
-            "-i", video_path,
-            "-vn", // No video
-            "-acodec", "pcm_s16le",
-            "-ar", "16000", // 16kHz for Whisper
-            "-ac", "1", // Mono
-            "-y", // Overwrite
-            &output_str,
-        ])
-        .output()
+    let mut command = tokio::process::Command::new(tools::ffmpeg());
+    command.kill_on_drop(true);
+    command.args(&["-i", video_path]);
+    if let Some(track) = track {
+        command.args(&["-map", &format!("0:a:{}", track.index)]);
+    }
+    if let Some(filter) = normalization.filter() {
+        command.args(&["-af", filter]);
+    }
+    
+    command.args(&[
+        "-vn", // No video
+        "-acodec", "pcm_s16le",
+        "-ar", "16000", // 16kHz for Whisper
+        "-ac", "1", // Mono
+        "-y", // Overwrite
+        &output_str,
+    ]);
+    let output = tools::output_retrying(&mut command)
         .await
-        .context("Failed to execute ffmpeg for audio extraction")?;
+        .map_err(|e| WorkerError::AudioExtraction(format!("failed to execute ffmpeg: {}", e)))?;
     
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -33,45 +254,247 @@ pub async fn extract_audio(video_path: &str, output_dir: &str, job_id: &str) ->
         info!("Audio extracted to {}", output_str);
         Ok(output_str.to_string())
     } else {
-        anyhow::bail!("Audio file was not created")
-    }
-}
-
-/// Transcribe audio using Whisper
-pub async fn transcribe_audio(audio_path: &str) -> Result<String> {
-    info!("Transcribing audio: {}", audio_path);
-    
-    // For now, we'll use the whisper command-line tool
-    // In production, you'd use whisper-rs with a loaded model
-    let output = tokio::process::Command::new("whisper")
-        .args(&[
-            audio_path,
-            "--model", "base",
-            "--language", "en",
-            "--output_format", "txt",
-            "--output_dir", "/tmp",
-        ])
-        .output()
-        .await;
-    
-    match output {
-        Ok(output) => {
-            if output.status.success() {
-                // Read the transcription file
-                let txt_path = format!("{}.txt", audio_path.trim_end_matches(".wav"));
-                if Path::new(&txt_path).exists() {
-                    let text = tokio::fs::read_to_string(&txt_path).await?;
-                    info!("Transcription complete: {} characters", text.len());
-                    return Ok(text);
-                }
+        Err(WorkerError::AudioExtraction("audio file was not created".to_string()))
+    }
+}
+
+/// Transcribe audio with the configured backend (TRANSCRIBE_BACKEND) and
+/// save the filtered transcript next to the audio for load_transcript
+#[instrument(skip_all)]
+pub async fn transcribe_audio(audio_path: &str) -> Result<Transcript> {
+    let backend = transcribe::backend();
+    info!("Transcribing audio with {}: {}", backend.name(), audio_path);
+    
+    let filter = HallucinationFilter::from_env();
+    let (dropped, segments): (Vec<_>, Vec<_>) = backend
+        .transcribe(audio_path)
+        .await?
+        .into_iter()
+        .partition(|s| filter.is_hallucination(s));
+    for segment in &dropped {
+        info!(
+            "Dropping likely hallucinated segment at {:.1}s: {:?}",
+            segment.start, segment.text
+        );
+    }
+    
+    let transcript = Transcript {
+        text: segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" "),
+        segments,
+        filtered_segments: dropped.len(),
+    };
+    info!(
+        "Transcription complete: {} characters in {} segments ({} filtered)",
+        transcript.text.len(),
+        transcript.segments.len(),
+        transcript.filtered_segments
+    );
+    
+    // Same shape as whisper's own output, whichever backend produced it
+    let json_path = transcript_json_path(audio_path);
+    let saved = serde_json::json!({
+        "text": transcript.text,
+        "segments": transcript.segments,
+        "filtered_segments": transcript.filtered_segments,
+    });
+    if let Err(e) = pipeline::write_atomic(Path::new(&json_path), saved.to_string().as_bytes()) {
+        warn!("Failed to save transcript to {}: {}", json_path, e);
+    }
+    
+    Ok(transcript)
+}
+
+/// Where whisper writes its JSON output for `audio_path`
+fn whisper_json_path(audio_path: &str) -> String {
+    format!("{}.json", audio_path.trim_end_matches(".wav"))
+}
+
+/// Where transcribe_audio saves the filtered transcript for `audio_path`,
+/// apart from whisper's own output
+fn transcript_json_path(audio_path: &str) -> String {
+    format!("{}.transcript.json", audio_path.trim_end_matches(".wav"))
+}
+
+/// The transcript an earlier run left next to `audio_path`, if any. Job
+/// directories from before the transcript had its own file kept it in
+/// whisper's.
+pub fn load_transcript(audio_path: &str) -> Result<Option<Transcript>> {
+    match read_transcript(&transcript_json_path(audio_path))? {
+        Some(transcript) => Ok(Some(transcript)),
+        None => read_transcript(&whisper_json_path(audio_path)),
+    }
+}
+
+/// The unfiltered transcript the whisper CLI wrote for `audio_path`, if any
+pub fn load_whisper_output(audio_path: &str) -> Result<Option<Transcript>> {
+    read_transcript(&whisper_json_path(audio_path))
+}
+
+fn read_transcript(json_path: &str) -> Result<Option<Transcript>> {
+    match std::fs::read_to_string(json_path) {
+        Ok(raw) => parse_whisper_json(&raw).map(Some),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(WorkerError::Transcription(format!("failed to read {}: {}", json_path, e))),
+    }
+}
+
+impl Transcript {
+    /// Render the segments as a WebVTT caption file
+    pub fn to_webvtt(&self) -> String {
+        let mut vtt = String::from("WEBVTT\n");
+        
+        for segment in &self.segments {
+            // A blank line ends a cue, so drop empty lines inside the text
+            let text: Vec<String> = segment
+                .text
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(escape_cue_text)
+                .collect();
+            if text.is_empty() {
+                continue;
             }
-            // If whisper CLI fails or isn't available, return empty string
-            warn!("Whisper transcription failed or not available");
-            Ok(String::new())
-        }
-        Err(e) => {
-            warn!("Whisper not available: {}", e);
-            Ok(String::new())
+            
+            vtt.push_str(&format!(
+                "\n{} --> {}\n{}\n",
+                timecode::format_timestamp(segment.start),
+                timecode::format_timestamp(segment.end.max(segment.start)),
+                text.join("\n")
+            ));
         }
+        
+        vtt
+    }
+}
+
+/// Escape the characters WebVTT treats as markup; this also keeps a literal
+/// "-->" from being read as a timing line
+fn escape_cue_text(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Parse Whisper's JSON output: `{"text": ..., "segments": [{"start", "end", "text"}, ...]}`,
+/// plus the `filtered_segments` count transcribe_audio adds when saving
+fn parse_whisper_json(raw: &str) -> Result<Transcript> {
+    #[derive(Deserialize)]
+    struct WhisperOutput {
+        text: String,
+        #[serde(default)]
+        segments: Vec<TranscriptSegment>,
+        #[serde(default)]
+        filtered_segments: usize,
+    }
+    
+    let parsed: WhisperOutput = serde_json::from_str(raw)
+        .map_err(|e| WorkerError::Transcription(format!("invalid whisper output: {}", e)))?;
+    
+    let segments = parsed
+        .segments
+        .into_iter()
+        .map(|s| TranscriptSegment {
+            text: s.text.trim().to_string(),
+            ..s
+        })
+        .filter(|s| !s.text.is_empty())
+        .collect();
+    
+    Ok(Transcript {
+        text: parsed.text.trim().to_string(),
+        segments,
+        filtered_segments: parsed.filtered_segments,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: f64, end: f64, text: &str) -> TranscriptSegment {
+        TranscriptSegment { start, end, text: text.to_string(), no_speech_prob: None, avg_logprob: None }
+    }
+
+    #[test]
+    fn webvtt_has_one_cue_per_spoken_segment() {
+        let transcript = Transcript {
+            segments: vec![
+                segment(0.0, 2.5, "Chop the onions."),
+                segment(2.5, 3.0, "  \n "),
+                segment(3.0, 2.0, "Fry them\n\nin butter."),
+            ],
+            ..Transcript::default()
+        };
+
+        assert_eq!(
+            transcript.to_webvtt(),
+            "WEBVTT\n\n00:00:00.000 --> 00:00:02.500\nChop the onions.\n\n00:00:03.000 --> 00:00:03.000\nFry them\nin butter.\n"
+        );
+        assert_eq!(Transcript::default().to_webvtt(), "WEBVTT\n");
+    }
+
+    #[test]
+    fn cue_text_markup_is_escaped() {
+        assert_eq!(escape_cue_text("salt & <b>pepper</b>"), "salt &amp; &lt;b&gt;pepper&lt;/b&gt;");
+        assert_eq!(escape_cue_text("stir --> serve"), "stir --&gt; serve");
+        assert_eq!(escape_cue_text("plain text"), "plain text");
     }
-}
\ No newline at end of file
+
+    fn scored(text: &str, no_speech_prob: f64, avg_logprob: f64) -> TranscriptSegment {
+        TranscriptSegment { no_speech_prob: Some(no_speech_prob), avg_logprob: Some(avg_logprob), ..segment(0.0, 2.0, text) }
+    }
+
+    #[test]
+    fn hallucinations_need_both_scores_past_their_thresholds() {
+        let filter = HallucinationFilter::default();
+        assert!(filter.is_hallucination(&scored("Thanks for watching!", 0.61, -1.01)));
+        // At a threshold is not past it
+        assert!(!filter.is_hallucination(&scored("Thanks for watching!", 0.6, -1.2)));
+        assert!(!filter.is_hallucination(&scored("Thanks for watching!", 0.9, -1.0)));
+        // Quiet but confident narration, and loud but unsure narration, stay
+        assert!(!filter.is_hallucination(&scored("Let it simmer.", 0.95, -0.3)));
+        assert!(!filter.is_hallucination(&scored("Let it simmer.", 0.1, -1.8)));
+        // Backends without scores keep everything
+        assert!(!filter.is_hallucination(&segment(0.0, 1.0, "Thanks for watching!")));
+    }
+
+    fn video_info(languages: &[Option<&str>]) -> VideoInfo {
+        let streams: Vec<serde_json::Value> = languages
+            .iter()
+            .map(|language| serde_json::json!({ "codec": "aac", "channels": 2, "language": language }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "duration_seconds": 30.0,
+            "width": 720,
+            "height": 1280,
+            "fps": 30.0,
+            "codec": "h264",
+            "audio_streams": streams,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn tracks_are_picked_from_the_probed_streams() {
+        let info = video_info(&[Some("eng"), None, Some("spa")]);
+        let pick = |selector: TrackSelector| select_track(&info, &selector).map(|t| t.index);
+
+        assert_eq!(pick(TrackSelector::First), Some(0));
+        assert_eq!(pick(TrackSelector::Index(1)), Some(1));
+        assert_eq!(pick(TrackSelector::Language("spa".to_string())), Some(2));
+        // No match falls back to the first stream
+        assert_eq!(pick(TrackSelector::Index(7)), Some(0));
+        assert_eq!(pick(TrackSelector::Language("fra".to_string())), Some(0));
+
+        assert!(select_track(&video_info(&[]), &TrackSelector::First).is_none());
+    }
+
+    #[test]
+    fn thresholds_fall_back_to_the_defaults() {
+        assert_eq!(threshold(None, 0.6), 0.6);
+        assert_eq!(threshold(Some(" 0.8 "), 0.6), 0.8);
+        assert_eq!(threshold(Some("-2"), -1.0), -2.0);
+        assert_eq!(threshold(Some("high"), 0.6), 0.6);
+        assert_eq!(threshold(Some("NaN"), 0.6), 0.6);
+        assert_eq!(threshold(Some("inf"), -1.0), -1.0);
+    }
+}
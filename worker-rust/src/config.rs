@@ -0,0 +1,125 @@
+use anyhow::{bail, Context, Result};
+use figment::providers::{Env, Format, Serialized, Toml};
+use figment::Figment;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::download::DEFAULT_MAX_CONCURRENT_DOWNLOADS;
+use crate::tools::Binaries;
+use crate::video::DEFAULT_MAX_FRAMES;
+use crate::worker::DEFAULT_MAX_JOB_RETRIES;
+
+/// Environment variables that override the matching config file key
+/// (lowercased, e.g. MAX_FRAMES sets `max_frames`)
+const ENV_KEYS: &[&str] = &[
+    "REDIS_URL",
+    "OUTPUT_DIR",
+    "MAX_FRAMES",
+    "MAX_JOB_RETRIES",
+    "MAX_CONCURRENT_DOWNLOADS",
+    "DLP_BINARY",
+    "GALLERY_DL_BINARY",
+    "FFMPEG_BINARY",
+    "FFPROBE_BINARY",
+    "WHISPER_BINARY",
+];
+
+/// Settings shared by every command.
+///
+/// Built once in main from, lowest precedence first: the defaults below, the
+/// `--config` TOML file, the environment variables in ENV_KEYS, then CLI flags.
+/// Knobs not listed here are still read from the environment by their module.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub redis_url: String,
+    /// Root for per-job directories; unset means the command's own default
+    pub output_dir: Option<String>,
+    /// Frames kept per video after scene detection and dedupe
+    pub max_frames: usize,
+    /// Times a job failing with a transient error is requeued
+    pub max_job_retries: u64,
+    /// yt-dlp processes allowed at once across all jobs
+    pub max_concurrent_downloads: usize,
+    pub dlp_binary: String,
+    pub gallery_dl_binary: String,
+    pub ffmpeg_binary: String,
+    pub ffprobe_binary: String,
+    pub whisper_binary: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let binaries = Binaries::default();
+        Self {
+            redis_url: "redis://localhost:6379".to_string(),
+            output_dir: None,
+            max_frames: DEFAULT_MAX_FRAMES,
+            max_job_retries: DEFAULT_MAX_JOB_RETRIES,
+            max_concurrent_downloads: DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+            dlp_binary: binaries.ytdlp,
+            gallery_dl_binary: binaries.gallery_dl,
+            ffmpeg_binary: binaries.ffmpeg,
+            ffprobe_binary: binaries.ffprobe,
+            whisper_binary: binaries.whisper,
+        }
+    }
+}
+
+impl Config {
+    /// Layer the file at `path` (if any) and the environment over the defaults
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut figment = Figment::from(Serialized::defaults(Config::default()));
+
+        if let Some(path) = path {
+            // Toml::file quietly skips a missing file; an explicit --config shouldn't
+            if !path.is_file() {
+                bail!("config file {:?} not found", path);
+            }
+            figment = figment.merge(Toml::file(path));
+        }
+
+        let config: Config = figment
+            .merge(Env::raw().only(ENV_KEYS))
+            .extract()
+            .context("Invalid configuration")?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.max_frames == 0 {
+            bail!("max_frames must be at least 1");
+        }
+        if self.max_concurrent_downloads == 0 {
+            bail!("max_concurrent_downloads must be at least 1");
+        }
+        Ok(())
+    }
+
+    /// `output_dir`, or `default` when neither the file nor OUTPUT_DIR set it
+    pub fn output_dir_or(&self, default: &str) -> String {
+        self.output_dir.clone().unwrap_or_else(|| default.to_string())
+    }
+
+    /// External tool paths, with blank values meaning "look up on PATH"
+    pub fn binaries(&self) -> Binaries {
+        let defaults = Binaries::default();
+        let pick = |value: &str, default: String| {
+            let value = value.trim();
+            if value.is_empty() {
+                default
+            } else {
+                value.to_string()
+            }
+        };
+        Binaries {
+            ytdlp: pick(&self.dlp_binary, defaults.ytdlp),
+            gallery_dl: pick(&self.gallery_dl_binary, defaults.gallery_dl),
+            ffmpeg: pick(&self.ffmpeg_binary, defaults.ffmpeg),
+            ffprobe: pick(&self.ffprobe_binary, defaults.ffprobe),
+            whisper: pick(&self.whisper_binary, defaults.whisper),
+        }
+    }
+}
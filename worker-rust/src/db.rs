@@ -0,0 +1,74 @@
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use tracing::info;
+
+use crate::error::{Result, WorkerError};
+use crate::result::ProcessResult;
+
+/// Postgres sink recording one row per processed video
+pub struct ResultStore {
+    pool: PgPool,
+    /// Fail the job when the insert fails instead of just logging
+    pub strict: bool,
+}
+
+impl ResultStore {
+    /// Connect to DATABASE_URL and apply pending migrations.
+    /// Returns None when DATABASE_URL is unset. DATABASE_STRICT=true makes
+    /// write failures fail the job.
+    pub async fn from_env() -> Result<Option<Self>> {
+        let url = match std::env::var("DATABASE_URL") {
+            Ok(url) if !url.is_empty() => url,
+            _ => return Ok(None),
+        };
+
+        let strict = std::env::var("DATABASE_STRICT")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+
+        let pool = PgPoolOptions::new()
+            .max_connections(4)
+            .connect(&url)
+            .await
+            .map_err(|e| WorkerError::Database(format!("failed to connect: {}", e)))?;
+
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .map_err(|e| WorkerError::Database(format!("migration failed: {}", e)))?;
+
+        info!("Postgres result sink enabled (strict={})", strict);
+
+        Ok(Some(Self { pool, strict }))
+    }
+
+    /// Insert or refresh the row for a job
+    pub async fn record(&self, url: &str, status: &str, result: &ProcessResult) -> Result<()> {
+        let video_info = serde_json::to_value(&result.video_info)
+            .map_err(|e| WorkerError::Database(format!("failed to encode video_info: {}", e)))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO video_results (job_id, url, status, video_info, transcription, frame_count)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (job_id) DO UPDATE SET
+                url = EXCLUDED.url,
+                status = EXCLUDED.status,
+                video_info = EXCLUDED.video_info,
+                transcription = EXCLUDED.transcription,
+                frame_count = EXCLUDED.frame_count,
+                completed_at = now()
+            "#,
+        )
+        .bind(&result.job_id)
+        .bind(url)
+        .bind(status)
+        .bind(video_info)
+        .bind(&result.transcription)
+        .bind(result.frames.len() as i32)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| WorkerError::Database(format!("insert failed for {}: {}", result.job_id, e)))?;
+
+        Ok(())
+    }
+}
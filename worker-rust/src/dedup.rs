@@ -0,0 +1,97 @@
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use tracing::info;
+
+use crate::download;
+use crate::result::ProcessResult;
+
+/// `results:by_url:{url hash}` -> most recent job_id that completed the URL,
+/// expiring with the result it points at
+const URL_INDEX_PREFIX: &str = "results:by_url:";
+
+/// Default lifetime of a reusable result (7 days)
+const DEFAULT_TTL_SECS: u64 = 7 * 86_400;
+
+/// Reuses the result of an earlier job for the same video
+pub struct Dedup {
+    ttl_secs: u64,
+}
+
+impl Dedup {
+    /// Enabled by DEDUP_RESULTS; DEDUP_TTL_SECS sets how long results stay reusable
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("DEDUP_RESULTS")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+
+        let ttl_secs = std::env::var("DEDUP_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|s| *s > 0)
+            .unwrap_or(DEFAULT_TTL_SECS);
+
+        info!("Result deduplication enabled (ttl {}s)", ttl_secs);
+
+        Some(Self { ttl_secs })
+    }
+
+    /// The job_id and stored result of a recent completed job for `url`
+    pub async fn lookup(&self, conn: &mut ConnectionManager, url: &str) -> Result<Option<(String, ProcessResult)>> {
+        let prior: Option<String> = redis::cmd("GET")
+            .arg(url_key(url))
+            .query_async(conn)
+            .await?;
+        let Some(prior) = prior else {
+            return Ok(None);
+        };
+
+        // The result can expire or be evicted first; a dangling index entry
+        // is a miss, as is a result stored in a layout this version can't read
+        let data: Option<String> = redis::cmd("GET")
+            .arg(result_key(&prior))
+            .query_async(conn)
+            .await?;
+
+        Ok(data
+            .and_then(|d| serde_json::from_str(&d).ok())
+            .map(|result| (prior, result)))
+    }
+
+    /// Remember `video_data` as the latest result for `url`
+    pub async fn record(
+        &self,
+        conn: &mut ConnectionManager,
+        url: &str,
+        job_id: &str,
+        video_data: &ProcessResult,
+    ) -> Result<()> {
+        let _: () = redis::pipe()
+            .cmd("SET")
+            .arg(result_key(job_id))
+            .arg(serde_json::to_string(video_data)?)
+            .arg("EX")
+            .arg(self.ttl_secs)
+            .ignore()
+            .cmd("SET")
+            .arg(url_key(url))
+            .arg(job_id)
+            .arg("EX")
+            .arg(self.ttl_secs)
+            .ignore()
+            .query_async(conn)
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn url_key(url: &str) -> String {
+    format!("{}{}", URL_INDEX_PREFIX, download::url_hash(url))
+}
+
+fn result_key(job_id: &str) -> String {
+    format!("video_result:{}", job_id)
+}
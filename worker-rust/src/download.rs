@@ -1,44 +1,1473 @@
-use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use tracing::{info, warn};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{info, instrument, warn};
 use uuid::Uuid;
 
-/// Download video from URL using yt-dlp
-pub async fn download_video(url: &str, output_dir: &str, job_id: &str) -> Result<String> {
-    let output_path = Path::new(output_dir).join(format!("{}_video.%(ext)s", job_id));
+use crate::error::{DownloadFailure, Result, WorkerError};
+use crate::ratelimit;
+use crate::tools;
+
+/// yt-dlp format selector; part of the cache key
+const DOWNLOAD_FORMAT: &str = "best[height<=1080]";
+
+/// Default parallel fragment downloads for HLS/DASH sources
+const DEFAULT_CONCURRENT_FRAGMENTS: u32 = 4;
+
+/// Default number of yt-dlp processes allowed to run at once across all jobs
+pub(crate) const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 2;
+
+/// Default cache entry lifetime (24h)
+const DEFAULT_CACHE_TTL_SECS: u64 = 86_400;
+
+/// Lowercased stderr fragments for each failure category, checked in order:
+/// geo and age messages often start with "Video unavailable" too, and a
+/// 404 arrives as "Unable to download webpage: HTTP Error 404"
+const FAILURE_PATTERNS: &[(DownloadFailure, &[&str])] = &[
+    (
+        DownloadFailure::GeoBlocked,
+        &[
+            "available in your country",
+            "not available in your location",
+            "geo restriction",
+            "geo-restricted",
+            "georestricted",
+            "blocked it in your country",
+        ],
+    ),
+    (
+        DownloadFailure::AgeRestricted,
+        &[
+            "confirm your age",
+            "age-restricted",
+            "age restricted",
+            "inappropriate for some users",
+        ],
+    ),
+    (
+        DownloadFailure::Private,
+        &["private video", "video is private", "account is private", "this post is private"],
+    ),
+    (
+        DownloadFailure::Unavailable,
+        &[
+            "video unavailable",
+            "has been removed",
+            "no longer available",
+            "does not exist",
+            "account associated with this video has been terminated",
+            "http error 404",
+            "http error 410",
+            "unsupported url",
+        ],
+    ),
+    (
+        DownloadFailure::NetworkTransient,
+        &[
+            "timed out",
+            "connection reset",
+            "connection refused",
+            "connection aborted",
+            "temporary failure in name resolution",
+            "name or service not known",
+            "network is unreachable",
+            "remote end closed connection",
+            "incompleteread",
+            "too many requests",
+            "http error 429",
+            "http error 500",
+            "http error 502",
+            "http error 503",
+            "http error 504",
+        ],
+    ),
+];
+
+/// Hosts SponsorBlock has segment data for
+const SPONSORBLOCK_HOSTS: &[&str] = &["youtube.com", "youtu.be"];
+
+/// Lowercased yt-dlp errors for posts without a video stream it can fetch,
+/// such as Instagram carousels and TikTok photo-mode posts
+const GALLERY_POST_PATTERNS: &[&str] = &[
+    "no video formats found",
+    "there is no video in this post",
+    "unable to extract webpage video data",
+];
+
+/// Seconds each image is shown when a gallery post becomes a slideshow, and
+/// the spacing of an image post's frame timestamps
+pub const SLIDE_SECS: u32 = 3;
+
+/// Directory in the job dir holding an image post's images
+const IMAGES_DIR: &str = "images";
+
+/// An image post's caption, kept beside its images for re-runs
+const CAPTION_FILE: &str = "caption.txt";
+
+/// gallery-dl metadata fields holding a post's caption, by extractor:
+/// Instagram, TikTok, then generic ones
+const CAPTION_FIELDS: &[&str] = &["description", "desc", "content", "caption", "title"];
+
+/// Slideshow canvas; images are scaled to fit and padded, since a post can
+/// mix sizes and one encode needs a single frame size
+const SLIDESHOW_SIZE: (u32, u32) = (1080, 1920);
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "aac", "ogg", "opus", "wav"];
+
+/// A downloaded video file
+#[derive(Debug, Clone)]
+pub struct DownloadedVideo {
+    pub path: String,
+    /// SponsorBlock segments were cut out, so timestamps no longer match the
+    /// original video
+    pub sponsorblock_trimmed: bool,
+    /// Set once the file has passed verify_download
+    pub integrity: Option<FileIntegrity>,
+    /// Range of the original video the file holds; timestamps measured on
+    /// the file are relative to its start
+    pub section: Option<Section>,
+    /// Set when the post had images but no video or audio, whether yt-dlp
+    /// handed back a still or gallery-dl a carousel; `path` is then the
+    /// directory holding the images
+    pub image_post: Option<ImagePost>,
+}
+
+/// A post made only of images, such as an Instagram carousel
+#[derive(Debug, Clone)]
+pub struct ImagePost {
+    /// Image files in post order
+    pub images: Vec<String>,
+    /// The post's caption, which stands in for the transcript
+    pub caption: Option<String>,
+}
+
+/// Part of a video to process, in seconds from the start of the original
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Section {
+    pub fn new(start: f64, end: f64) -> std::result::Result<Self, String> {
+        if !start.is_finite() || !end.is_finite() || start < 0.0 {
+            return Err(format!("invalid section {}-{}", start, end));
+        }
+        if start >= end {
+            return Err(format!("section start {} must be before its end {}", start, end));
+        }
+        Ok(Self { start, end })
+    }
+
+    /// Parse `start-end`, each in seconds or `[h:]m:ss`, e.g. "90-150" or "1:30-2:30"
+    pub fn parse(range: &str) -> std::result::Result<Self, String> {
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("section '{}' must look like start-end", range))?;
+        Self::new(parse_timestamp(start)?, parse_timestamp(end)?)
+    }
+
+    /// The `section` field of a queued job: `{"start": 90, "end": 150}` or a
+    /// "start-end" string. Missing means the whole video.
+    pub fn from_job(job_data: &serde_json::Value) -> std::result::Result<Option<Self>, String> {
+        match &job_data["section"] {
+            serde_json::Value::Null => Ok(None),
+            serde_json::Value::String(range) => Self::parse(range).map(Some),
+            serde_json::Value::Object(range) => {
+                let bound = |name: &str| {
+                    range
+                        .get(name)
+                        .and_then(|v| v.as_f64())
+                        .ok_or_else(|| format!("section {} must be a number of seconds", name))
+                };
+                Self::new(bound("start")?, bound("end")?).map(Some)
+            }
+            _ => Err("section must be {\"start\", \"end\"} or a start-end string".to_string()),
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+}
+
+/// Seconds from `ss`, `m:ss`, or `h:mm:ss`
+fn parse_timestamp(value: &str) -> std::result::Result<f64, String> {
+    let value = value.trim();
+    value
+        .split(':')
+        .try_fold(0.0, |total, part| part.parse::<f64>().ok().map(|p| total * 60.0 + p))
+        .filter(|secs| secs.is_finite())
+        .ok_or_else(|| format!("invalid timestamp '{}'", value))
+}
+
+/// Size and content hash of a downloaded file that passed verification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileIntegrity {
+    pub size_bytes: u64,
+    pub sha256: String,
+}
+
+/// Download video from URL using yt-dlp, or only `section` of it
+#[instrument(skip_all, fields(job_id = %job_id))]
+pub async fn download_video(url: &str, job_dir: &str, job_id: &str, section: Option<Section>) -> Result<DownloadedVideo> {
+    validate_url(url)?;
+
+    let cache_dir = std::env::var("CACHE_DIR").ok().filter(|d| !d.is_empty());
+    let downloaded = match (section, cache_dir) {
+        // SponsorBlock cuts would shift a range the caller chose on the
+        // original timeline, and partial downloads aren't worth caching
+        (Some(section), _) => download_section(url, job_dir, section).await,
+        (None, Some(cache_dir)) => {
+            download_cached(url, &cache_dir, sponsorblock_categories(url).as_deref()).await
+        }
+        (None, None) => run_ytdlp(url, job_dir, "video", sponsorblock_categories(url).as_deref(), None).await,
+    };
+    let video = match downloaded {
+        Err(WorkerError::DownloadRejected(_, reason))
+            if section.is_none() && gallery_dl_fallback_enabled() && is_gallery_post(&reason) =>
+        {
+            warn!("yt-dlp found no video ({}), falling back to gallery-dl", reason);
+            download_gallery(url, job_dir).await?
+        }
+        downloaded => downloaded?,
+    };
+    // Branch on what came back rather than on which tool fetched it
+    let mut video = as_image_post(video, Path::new(job_dir))?;
+    // Images are decoded (and skipped if unreadable) as frames later
+    if video.image_post.is_some() {
+        return Ok(video);
+    }
+
+    match verify_download(Path::new(&video.path)).await {
+        Ok(integrity) => {
+            info!("Verified download: {} bytes, sha256 {}", integrity.size_bytes, integrity.sha256);
+            video.integrity = Some(integrity);
+            Ok(video)
+        }
+        Err(e) => {
+            // Otherwise the retry (or the next job, for a cache entry) would
+            // pick up the same broken file instead of downloading again
+            warn!("Removing corrupt download {}: {}", video.path, e);
+            let _ = std::fs::remove_file(&video.path);
+            Err(e)
+        }
+    }
+}
+
+/// Check that yt-dlp actually left a usable file: non-empty, and ffprobe
+/// can demux at least one packet. A truncated or corrupt file fails as a
+/// (retriable) download error rather than obscurely in a later stage.
+pub async fn verify_download(path: &Path) -> Result<FileIntegrity> {
+    let size_bytes = std::fs::metadata(path)?.len();
+    if size_bytes == 0 {
+        return Err(WorkerError::Download("downloaded file is empty".to_string()));
+    }
+
+    let output = tools::output_retrying(
+        tokio::process::Command::new(tools::ffprobe())
+            .kill_on_drop(true)
+            .args(&[
+                "-v", "error",
+                // Stop after the first packet; this is a sanity check, not a full decode
+                "-read_intervals", "%+#1",
+                "-show_entries", "packet=pts_time",
+                "-of", "csv=p=0",
+            ])
+            .arg(path),
+    )
+    .await
+    .map_err(|e| WorkerError::Download(format!("failed to execute ffprobe: {}", e)))?;
+
+    let has_packet = !String::from_utf8_lossy(&output.stdout).trim().is_empty();
+    if !output.status.success() || !has_packet {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(WorkerError::Download(format!(
+            "downloaded file is unreadable ({} bytes): {}",
+            size_bytes,
+            stderr.trim()
+        )));
+    }
+
+    let path = path.to_path_buf();
+    let sha256 = tokio::task::spawn_blocking(move || file_sha256(&path))
+        .await
+        .map_err(|e| WorkerError::Download(format!("hashing task failed: {}", e)))??;
+
+    Ok(FileIntegrity { size_bytes, sha256 })
+}
+
+/// Hex SHA-256 of a file, read in chunks
+fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// SPONSORBLOCK_CATEGORIES (e.g. "sponsor,intro,outro") for YouTube URLs;
+/// None for other platforms or when unset
+fn sponsorblock_categories(url: &str) -> Option<String> {
+    let categories = std::env::var("SPONSORBLOCK_CATEGORIES")
+        .ok()
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())?;
+
+    let host = reqwest::Url::parse(url.trim()).ok()?.host_str()?.to_lowercase();
+    let supported = SPONSORBLOCK_HOSTS
+        .iter()
+        .any(|h| host == *h || host.ends_with(&format!(".{}", h)));
+
+    supported.then_some(categories)
+}
+
+/// Sidecar file marking a cached download as SponsorBlock-trimmed
+fn trimmed_marker(dir: &str, file_stem: &str) -> PathBuf {
+    Path::new(dir).join(format!("{}.sponsorblock", file_stem))
+}
+
+/// Download through the URL-keyed cache, reusing a fresh entry if present
+async fn download_cached(url: &str, cache_dir: &str, sponsorblock: Option<&str>) -> Result<DownloadedVideo> {
+    std::fs::create_dir_all(cache_dir)?;
+
+    let key = cache_key(url, sponsorblock);
+    let ttl = std::env::var("CACHE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(DEFAULT_CACHE_TTL_SECS));
+
+    // Serialize downloads of the same URL so concurrent jobs share one fetch
+    let lock = url_lock(&key);
+    let _guard = lock.lock().await;
+
+    if let Some(path) = find_file(cache_dir, &key)? {
+        let age = std::fs::metadata(&path)?
+            .modified()?
+            .elapsed()
+            .unwrap_or_default();
+
+        if age <= ttl {
+            info!("Cache hit for {}: {:?}", url, path);
+            return Ok(DownloadedVideo {
+                path: path.to_string_lossy().to_string(),
+                sponsorblock_trimmed: trimmed_marker(cache_dir, &key).exists(),
+                integrity: None,
+                section: None,
+                image_post: None,
+            });
+        }
+
+        info!("Cache entry for {} expired, re-downloading", url);
+        std::fs::remove_file(&path)?;
+        let _ = std::fs::remove_file(trimmed_marker(cache_dir, &key));
+    }
+
+    let video = run_ytdlp(url, cache_dir, &key, sponsorblock, None).await?;
+    if video.sponsorblock_trimmed {
+        std::fs::write(trimmed_marker(cache_dir, &key), b"")?;
+    }
+
+    Ok(video)
+}
+
+/// Slack allowed between a section download's length and the requested
+/// range before yt-dlp is assumed to have ignored the range
+const SECTION_TOLERANCE_SECS: f64 = 2.0;
+
+/// Download just `section` with `--download-sections`. Extractors that can't
+/// fetch a range either fail or hand back the whole video; both fall back to
+/// trimming a full download with ffmpeg.
+async fn download_section(url: &str, job_dir: &str, section: Section) -> Result<DownloadedVideo> {
+    info!("Downloading section {:.2}s-{:.2}s", section.start, section.end);
+
+    let full = match run_ytdlp(url, job_dir, "video", None, Some(section)).await {
+        Ok(video) => match media_duration(Path::new(&video.path)).await {
+            Some(duration) if duration <= section.duration() + SECTION_TOLERANCE_SECS => {
+                return Ok(DownloadedVideo {
+                    section: Some(section),
+                    ..video
+                });
+            }
+            _ => {
+                warn!("yt-dlp ignored the requested section, trimming with ffmpeg");
+                PathBuf::from(video.path)
+            }
+        },
+        // Geo blocks, private videos, and network errors would fail the
+        // full download just the same
+        Err(WorkerError::DownloadRejected(DownloadFailure::Unknown, reason)) => {
+            warn!("Section download failed ({}), downloading the full video to trim", reason);
+            let video = run_ytdlp(url, job_dir, "full", None, None).await?;
+            PathBuf::from(video.path)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let trimmed = trim_to_section(&full, Path::new(job_dir), section).await?;
+    Ok(DownloadedVideo {
+        path: trimmed.to_string_lossy().to_string(),
+        sponsorblock_trimmed: false,
+        integrity: None,
+        section: Some(section),
+        image_post: None,
+    })
+}
+
+/// Length of the media at `path` from ffprobe, or None if it can't be read
+async fn media_duration(path: &Path) -> Option<f64> {
+    let output = tokio::process::Command::new(tools::ffprobe())
+        .kill_on_drop(true)
+        .args(&["-v", "error", "-show_entries", "format=duration", "-of", "csv=p=0"])
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Cut `section` out of `input` into `{dir}/video.mp4`, replacing `input`.
+/// Re-encodes so the cut lands exactly on `section.start` rather than the
+/// nearest keyframe, keeping timestamps aligned.
+async fn trim_to_section(input: &Path, dir: &Path, section: Section) -> Result<PathBuf> {
+    if let Some(duration) = media_duration(input).await {
+        if section.start >= duration {
+            return Err(WorkerError::InvalidJob(format!(
+                "section starts at {:.2}s but the video is {:.2}s long",
+                section.start, duration
+            )));
+        }
+    }
+
+    let staging = dir.join("video.trimmed.mp4");
+    let output = tokio::process::Command::new(tools::ffmpeg())
+        .kill_on_drop(true)
+        .args(&["-v", "error", "-ss", &format!("{:.3}", section.start)])
+        .arg("-i")
+        .arg(input)
+        .args(&[
+            "-t", &format!("{:.3}", section.duration()),
+            "-c:v", "libx264",
+            "-preset", "veryfast",
+            "-c:a", "aac",
+            "-movflags", "+faststart",
+            "-y",
+        ])
+        .arg(&staging)
+        .output()
+        .await
+        .map_err(|e| WorkerError::Download(format!("failed to execute ffmpeg: {}", e)))?;
+
+    // The full download was only needed for this cut; left behind, find_file
+    // could pick it up as the job's video
+    let _ = std::fs::remove_file(input);
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&staging);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(WorkerError::Download(format!("trimming to section failed: {}", stderr.trim())));
+    }
+
+    let path = dir.join("video.mp4");
+    std::fs::rename(&staging, &path)?;
+    Ok(path)
+}
+
+async fn run_ytdlp(
+    url: &str,
+    output_dir: &str,
+    file_stem: &str,
+    sponsorblock: Option<&str>,
+    section: Option<Section>,
+) -> Result<DownloadedVideo> {
+    let output_path = Path::new(output_dir).join(format!("{}.%(ext)s", file_stem));
     let output_template = output_path.to_string_lossy();
-    
+
+    // Wait out the per-site rate limit before taking a slot, so a throttled
+    // site doesn't hold up downloads from other sites
+    ratelimit::wait_for_download(url).await;
+    // Held until yt-dlp exits so concurrent jobs share the egress budget
+    let _slot = download_slot().await;
+
     info!("Downloading video to {}", output_template);
+
+    let mut command = tokio::process::Command::new(tools::ytdlp());
+    command.kill_on_drop(true).args(&[
+        "--format", DOWNLOAD_FORMAT,
+        "--output", &output_template,
+        "--concurrent-fragments", &concurrent_fragments().to_string(),
+        "--no-playlist",
+        // Resume a .part file left by an earlier attempt at the same job
+        "--continue",
+        "--quiet",
+        "--no-warnings",
+    ]);
+    if let Some(categories) = sponsorblock {
+        info!("Removing SponsorBlock segments: {}", categories);
+        // Print the removed segments once the file is in place so we know
+        // whether anything was actually cut
+        command.args(&[
+            "--sponsorblock-remove", categories,
+            "--print", "after_move:%(sponsorblock_chapters)j",
+        ]);
+    }
+    if let Some(section) = section {
+        command.args(&[
+            "--download-sections", &format!("*{:.3}-{:.3}", section.start, section.end),
+            // Cut exactly at the range instead of the surrounding keyframes,
+            // so frame 0 of the file is section.start
+            "--force-keyframes-at-cuts",
+        ]);
+    }
+
+    let output = command
+        // Everything after this is positional, so the URL can't be read as a flag
+        .args(&["--", url])
+        .output()
+        .await
+        .map_err(|e| WorkerError::Download(format!("failed to execute yt-dlp: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(ytdlp_error(&output.stderr));
+    }
+
+    let sponsorblock_trimmed = sponsorblock.is_some() && removed_segments(&output.stdout) > 0;
+    if sponsorblock_trimmed {
+        info!("SponsorBlock segments removed from {}", url);
+    }
+
+    // Find the downloaded file; for some image posts yt-dlp saves the
+    // picture itself, which download_video turns into an image post
+    let path = match find_file(output_dir, file_stem)? {
+        Some(path) => path,
+        None => find_image(output_dir, file_stem)?
+            .ok_or_else(|| WorkerError::Download("downloaded video file not found".to_string()))?,
+    };
+    Ok(DownloadedVideo {
+        path: path.to_string_lossy().to_string(),
+        sponsorblock_trimmed,
+        integrity: None,
+        section: None,
+        image_post: None,
+    })
+}
+
+/// Whether `path` has an image extension
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+}
+
+/// `{file_stem}.{ext}` for a known image format in `dir`, if yt-dlp saved one
+fn find_image(dir: &str, file_stem: &str) -> Result<Option<PathBuf>> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_stem().is_some_and(|s| s.to_string_lossy() == file_stem) && is_image(&path) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// Turn a download that is a single image into an image post kept in
+/// `{job_dir}/images`; anything else is returned unchanged. An image from
+/// the download cache is copied, so the cache entry stays usable.
+fn as_image_post(video: DownloadedVideo, job_dir: &Path) -> Result<DownloadedVideo> {
+    let source = Path::new(&video.path);
+    if video.image_post.is_some() || !is_image(source) {
+        return Ok(video);
+    }
+    
+    let images_dir = job_dir.join(IMAGES_DIR);
+    std::fs::create_dir_all(&images_dir)?;
+    let ext = source.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    let image = images_dir.join(format!("image_001.{}", ext));
+    if source.parent() == Some(job_dir) {
+        std::fs::rename(source, &image)?;
+    } else {
+        std::fs::copy(source, &image)?;
+    }
+    info!("Download is a single image, processing it as an image post");
+    
+    Ok(DownloadedVideo {
+        path: images_dir.to_string_lossy().to_string(),
+        image_post: Some(ImagePost {
+            images: vec![image.to_string_lossy().to_string()],
+            caption: None,
+        }),
+        ..video
+    })
+}
+
+/// Whether posts yt-dlp finds no video in are retried with gallery-dl
+/// (GALLERY_DL_FALLBACK, off by default since gallery-dl must be installed)
+fn gallery_dl_fallback_enabled() -> bool {
+    std::env::var("GALLERY_DL_FALLBACK")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Whether a yt-dlp failure reason means the post has no video stream it
+/// can fetch, as opposed to the post being gone or the network failing
+fn is_gallery_post(reason: &str) -> bool {
+    let reason = reason.to_lowercase();
+    GALLERY_POST_PATTERNS.iter().any(|p| reason.contains(p))
+}
+
+/// Files gallery-dl saved for one post, each list in post order
+#[derive(Debug, Default)]
+struct GalleryMedia {
+    videos: Vec<PathBuf>,
+    images: Vec<PathBuf>,
+    audio: Vec<PathBuf>,
+}
+
+/// Sort what gallery-dl wrote to `dir` by kind.
+///
+/// Names depend on the extractor ("{shortcode}_{num}.jpg" for Instagram,
+/// "{id}_{num}.jpg" plus a separate audio file for TikTok), so files are
+/// classified by extension and ordered by name length first, which keeps
+/// "_9" ahead of "_10" without parsing each extractor's scheme.
+fn gallery_media(dir: &Path) -> Result<GalleryMedia> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort_by_key(|path| post_order(path));
+
+    let mut media = GalleryMedia::default();
+    for path in files {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if VIDEO_EXTENSIONS.contains(&ext.as_str()) {
+            media.videos.push(path);
+        } else if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+            media.images.push(path);
+        } else if AUDIO_EXTENSIONS.contains(&ext.as_str()) {
+            media.audio.push(path);
+        }
+    }
+    Ok(media)
+}
+
+/// Sort key putting gallery-dl's numbered files (`{id}_{num}.{ext}`) in post
+/// order: by the name before its first dot, shorter first, so `_9` comes
+/// before `_10` whatever either file's extension
+fn post_order(path: &Path) -> (usize, String) {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = name.split('.').next().unwrap_or_default().to_string();
+    (stem.len(), stem)
+}
+
+/// The caption from the first gallery-dl metadata file in `dir` that has
+/// one, trying CAPTION_FIELDS in order
+fn gallery_caption(dir: &Path) -> Option<String> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort_by_key(|path| post_order(path));
     
-    let output = tokio::process::Command::new("yt-dlp")
+    files.iter().find_map(|path| {
+        let metadata: serde_json::Value = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+        CAPTION_FIELDS
+            .iter()
+            .filter_map(|field| metadata[*field].as_str())
+            .map(str::trim)
+            .find(|caption| !caption.is_empty())
+            .map(str::to_string)
+    })
+}
+
+/// Move a post's images into `{dir}/images`, numbered in post order, and
+/// save its caption beside them
+fn keep_images(media: &GalleryMedia, gallery_dir: &Path, dir: &Path) -> Result<ImagePost> {
+    let images_dir = dir.join(IMAGES_DIR);
+    std::fs::create_dir_all(&images_dir)?;
+    
+    let mut images = Vec::with_capacity(media.images.len());
+    for (i, image) in media.images.iter().enumerate() {
+        let ext = image.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+        let path = images_dir.join(format!("image_{:03}.{}", i + 1, ext));
+        std::fs::rename(image, &path)?;
+        images.push(path.to_string_lossy().to_string());
+    }
+    
+    let caption = gallery_caption(gallery_dir);
+    if let Some(caption) = &caption {
+        std::fs::write(images_dir.join(CAPTION_FILE), caption)?;
+    }
+    Ok(ImagePost { images, caption })
+}
+
+/// The image post an earlier run kept in `{job_dir}/images`, if any
+fn existing_image_post(job_dir: &Path) -> Option<ImagePost> {
+    let images_dir = job_dir.join(IMAGES_DIR);
+    let mut images: Vec<String> = std::fs::read_dir(&images_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| is_image(path))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    if images.is_empty() {
+        return None;
+    }
+    images.sort();
+    
+    let caption = std::fs::read_to_string(images_dir.join(CAPTION_FILE)).ok();
+    Some(ImagePost { images, caption })
+}
+
+/// ffconcat script showing each image for SLIDE_SECS. The last image is
+/// listed twice; otherwise the concat demuxer ignores its duration.
+fn slideshow_script(images: &[PathBuf]) -> String {
+    let quote = |path: &PathBuf| path.to_string_lossy().replace('\'', "'\\''");
+    let mut script = String::from("ffconcat version 1.0\n");
+    for image in images {
+        script.push_str(&format!("file '{}'\nduration {}\n", quote(image), SLIDE_SECS));
+    }
+    if let Some(last) = images.last() {
+        script.push_str(&format!("file '{}'\n", quote(last)));
+    }
+    script
+}
+
+/// Fetch a post with gallery-dl and turn it into `{job_dir}/video.*`: the
+/// post's first video if it has one, else a slideshow of its images over
+/// its audio track (TikTok photo mode). A post with images but no audio (a
+/// carousel) is kept as an image post in `{job_dir}/images` instead.
+async fn download_gallery(url: &str, job_dir: &str) -> Result<DownloadedVideo> {
+    let gallery_dir = Path::new(job_dir).join("gallery");
+    std::fs::create_dir_all(&gallery_dir)?;
+
+    ratelimit::wait_for_download(url).await;
+    let _slot = download_slot().await;
+
+    info!("Downloading {} with gallery-dl", url);
+    let output = tokio::process::Command::new(tools::gallery_dl())
+        .kill_on_drop(true)
+        .arg("--directory")
+        .arg(&gallery_dir)
+        .arg("--write-metadata")
+        .args(&["--", url])
+        .output()
+        .await
+        .map_err(|e| WorkerError::Download(format!("failed to execute gallery-dl: {}", e)))?;
+
+    if !output.status.success() {
+        let _ = std::fs::remove_dir_all(&gallery_dir);
+        return Err(ytdlp_error(&output.stderr));
+    }
+
+    let media = gallery_media(&gallery_dir)?;
+    let result = match (media.videos.first(), media.images.is_empty(), media.audio.is_empty()) {
+        (Some(video), _, _) => {
+            let ext = video.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+            let path = Path::new(job_dir).join(format!("video.{}", ext));
+            std::fs::rename(video, &path).map(|_| (path, None)).map_err(WorkerError::from)
+        }
+        (None, false, true) => keep_images(&media, &gallery_dir, Path::new(job_dir))
+            .map(|post| (Path::new(job_dir).join(IMAGES_DIR), Some(post))),
+        (None, false, false) => build_slideshow(&media, &gallery_dir, Path::new(job_dir))
+            .await
+            .map(|path| (path, None)),
+        (None, true, _) => Err(WorkerError::Download("gallery-dl saved no video or images".to_string())),
+    };
+    let _ = std::fs::remove_dir_all(&gallery_dir);
+    
+    let (path, image_post) = result?;
+    match &image_post {
+        Some(post) => info!("gallery-dl fallback kept {} images as an image post", post.images.len()),
+        None => info!("gallery-dl fallback produced {:?}", path),
+    }
+    Ok(DownloadedVideo {
+        path: path.to_string_lossy().to_string(),
+        sponsorblock_trimmed: false,
+        integrity: None,
+        section: None,
+        image_post,
+    })
+}
+
+/// Render the post's images, with its first audio file if any, into
+/// `{dir}/video.mp4`
+async fn build_slideshow(media: &GalleryMedia, gallery_dir: &Path, dir: &Path) -> Result<PathBuf> {
+    info!("Building a slideshow from {} images", media.images.len());
+
+    let script = gallery_dir.join("slideshow.ffconcat");
+    std::fs::write(&script, slideshow_script(&media.images))?;
+
+    let (width, height) = SLIDESHOW_SIZE;
+    let filter = format!(
+        "scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps=30,format=yuv420p",
+        w = width,
+        h = height
+    );
+    let path = dir.join("video.mp4");
+
+    let mut command = tokio::process::Command::new(tools::ffmpeg());
+    command
+        .kill_on_drop(true)
+        .args(&["-v", "error", "-f", "concat", "-safe", "0", "-i"])
+        .arg(&script);
+    if let Some(audio) = media.audio.first() {
+        command.arg("-i").arg(audio).args(&["-c:a", "aac"]);
+    }
+    let output = command
+        .args(&[
+            "-vf", &filter,
+            "-t", &(SLIDE_SECS as usize * media.images.len()).to_string(),
+            "-c:v", "libx264",
+            "-preset", "veryfast",
+            "-movflags", "+faststart",
+            "-y",
+        ])
+        .arg(&path)
+        .output()
+        .await
+        .map_err(|e| WorkerError::Download(format!("failed to execute ffmpeg: {}", e)))?;
+
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&path);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(WorkerError::Download(format!("building slideshow failed: {}", stderr.trim())));
+    }
+    Ok(path)
+}
+
+/// Whether probe-only runs should try reading metadata from the remote
+/// stream before downloading (STREAM_PROBE, on unless set to false)
+pub fn stream_probe_enabled() -> bool {
+    std::env::var("STREAM_PROBE")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(true)
+}
+
+/// Direct media URL for `url` from `yt-dlp -g`, for reading metadata without
+/// downloading. Fails when the platform only serves a merged or DRM stream.
+#[instrument(skip_all)]
+pub async fn stream_url(url: &str) -> Result<String> {
+    validate_url(url)?;
+    ratelimit::wait_for_download(url).await;
+    
+    let output = tokio::process::Command::new(tools::ytdlp())
+        .kill_on_drop(true)
         .args(&[
-            "--format", "best[height<=1080]",
-            "--output", &output_template,
+            "--get-url",
+            "--format", DOWNLOAD_FORMAT,
             "--no-playlist",
             "--quiet",
             "--no-warnings",
-            url,
+            "--", url,
         ])
         .output()
         .await
-        .context("Failed to execute yt-dlp")?;
+        .map_err(|e| WorkerError::Download(format!("failed to execute yt-dlp: {}", e)))?;
     
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("yt-dlp failed: {}", stderr);
+        return Err(ytdlp_error(&output.stderr));
     }
     
-    // Find the downloaded file
-    let dir = std::fs::read_dir(output_dir)?;
-    for entry in dir {
+    // One line per selected format; a single-file format gives exactly one
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let urls: Vec<&str> = stdout.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    match urls[..] {
+        [stream] => Ok(stream.to_string()),
+        [] => Err(WorkerError::Download("yt-dlp returned no stream URL".to_string())),
+        _ => Err(WorkerError::Download("format has separate audio/video streams".to_string())),
+    }
+}
+
+/// Error for a failed yt-dlp run, categorised from its stderr so the worker
+/// can tell a retriable network blip from a video it will never get
+fn ytdlp_error(stderr: &[u8]) -> WorkerError {
+    let stderr = String::from_utf8_lossy(stderr);
+    WorkerError::DownloadRejected(classify_failure(&stderr), failure_reason(&stderr))
+}
+
+/// Category of a yt-dlp failure from its stderr
+pub fn classify_failure(stderr: &str) -> DownloadFailure {
+    let stderr = stderr.to_lowercase();
+    FAILURE_PATTERNS
+        .iter()
+        .find(|(_, patterns)| patterns.iter().any(|p| stderr.contains(p)))
+        .map(|(failure, _)| *failure)
+        .unwrap_or(DownloadFailure::Unknown)
+}
+
+/// The last `ERROR:` line without its prefix, which is what yt-dlp means to
+/// tell the user; falls back to the last non-empty line
+fn failure_reason(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    lines
+        .iter()
+        .rev()
+        .find_map(|l| l.strip_prefix("ERROR:"))
+        .or_else(|| lines.last().copied())
+        .map(|l| l.trim().to_string())
+        .unwrap_or_else(|| "yt-dlp exited without an error message".to_string())
+}
+
+/// The video an earlier run downloaded into `job_dir`, for re-running later
+/// stages without downloading again. `recorded` is the video_path of that
+/// run's result, used when the download isn't in `job_dir` (CACHE_DIR keeps
+/// it in the cache instead).
+pub fn existing_video(job_dir: &str, recorded: Option<&str>) -> Result<DownloadedVideo> {
+    if let Some(post) = existing_image_post(Path::new(job_dir)) {
+        info!("Reusing {} images of an earlier image post", post.images.len());
+        return Ok(DownloadedVideo {
+            path: Path::new(job_dir).join(IMAGES_DIR).to_string_lossy().to_string(),
+            sponsorblock_trimmed: false,
+            integrity: None,
+            section: None,
+            image_post: Some(post),
+        });
+    }
+
+    let found = find_file(job_dir, "video")
+        .ok()
+        .flatten()
+        .or_else(|| recorded.map(PathBuf::from).filter(|path| path.is_file()));
+    match found {
+        Some(path) => {
+            info!("Reusing earlier download {:?}", path);
+            Ok(DownloadedVideo {
+                path: path.to_string_lossy().to_string(),
+                sponsorblock_trimmed: false,
+                integrity: None,
+                section: None,
+                image_post: None,
+            })
+        }
+        None => Err(WorkerError::InvalidJob(format!(
+            "download stage skipped but {} has no earlier download",
+            job_dir
+        ))),
+    }
+}
+
+/// Delete yt-dlp's partial downloads (`.part`, fragment, and `.ytdl` resume
+/// files) from `dir`, returning how many were removed. Call once a download
+/// has failed for good; until then they let a retry resume.
+pub fn remove_partial_downloads(dir: &Path) -> usize {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    let mut removed = 0;
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if !(name.contains(".part") || name.ends_with(".ytdl")) {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Failed to remove partial download {:?}: {}", path, e),
+        }
+    }
+
+    if removed > 0 {
+        info!("Removed {} partial download file(s) from {:?}", removed, dir);
+    }
+    removed
+}
+
+/// Count the segments in yt-dlp's printed `sponsorblock_chapters` JSON
+/// ("NA" when SponsorBlock had nothing for the video)
+fn removed_segments(stdout: &[u8]) -> usize {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line.trim()).ok())
+        .filter_map(|v| v.as_array().map(|a| a.len()))
+        .sum()
+}
+
+/// Reject anything that isn't a plain http(s) URL, or whose host isn't in
+/// ALLOWED_HOSTS (comma separated; subdomains match) when that is set.
+fn validate_url(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url.trim())
+        .map_err(|e| WorkerError::InvalidUrl(format!("{}: {}", url, e)))?;
+
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(WorkerError::InvalidUrl(format!(
+            "unsupported scheme '{}'",
+            parsed.scheme()
+        )));
+    }
+
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| WorkerError::InvalidUrl(format!("{}: missing host", url)))?
+        .to_lowercase();
+
+    if let Some(allowed) = std::env::var("ALLOWED_HOSTS").ok().filter(|h| !h.trim().is_empty()) {
+        let permitted = allowed
+            .split(',')
+            .map(|h| h.trim().to_lowercase())
+            .filter(|h| !h.is_empty())
+            .any(|h| host == h || host.ends_with(&format!(".{}", h)));
+
+        if !permitted {
+            return Err(WorkerError::InvalidUrl(format!("host '{}' is not allowed", host)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Fragments yt-dlp fetches in parallel (DLP_CONCURRENT_FRAGMENTS).
+///
+/// Only affects fragmented formats (HLS/DASH); single-file downloads ignore it.
+/// Higher values trade more simultaneous bandwidth for faster downloads.
+fn concurrent_fragments() -> u32 {
+    std::env::var("DLP_CONCURRENT_FRAGMENTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_CONCURRENT_FRAGMENTS)
+}
+
+/// Download slots shared by every job in this process
+static DOWNLOAD_SLOTS: OnceLock<Semaphore> = OnceLock::new();
+
+/// Allow `limit` yt-dlp processes at once (MAX_CONCURRENT_DOWNLOADS via the
+/// config), independent of how many jobs run in parallel. Call before the
+/// first download; later calls are ignored.
+pub fn set_max_concurrent_downloads(limit: usize) {
+    if DOWNLOAD_SLOTS.set(Semaphore::new(limit.max(1))).is_err() {
+        warn!("Download limit already set, ignoring new limit of {}", limit);
+    }
+}
+
+/// Wait for a free download slot, logging when the limit makes a job queue
+async fn download_slot() -> SemaphorePermit<'static> {
+    let slots = DOWNLOAD_SLOTS.get_or_init(|| Semaphore::new(DEFAULT_MAX_CONCURRENT_DOWNLOADS));
+
+    if let Ok(permit) = slots.try_acquire() {
+        return permit;
+    }
+
+    info!("All download slots in use, waiting for one to free up");
+    let started = Instant::now();
+    // The semaphore is never closed, so acquire can't fail
+    let permit = slots.acquire().await.expect("download semaphore closed");
+    info!("Got a download slot after {:.1}s", started.elapsed().as_secs_f64());
+    permit
+}
+
+/// Containers yt-dlp can leave behind after merging/remuxing
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "mov", "m4v", "flv", "3gp"];
+
+/// Find `{file_stem}.{ext}` for a known video container, newest first.
+///
+/// A reused job_id or an earlier run with a different container can leave
+/// stale matches, and yt-dlp's `.part`/`.f137.mp4` intermediates share the
+/// stem, so only exact names count and the most recently modified wins.
+fn find_file(dir: &str, file_stem: &str) -> Result<Option<PathBuf>> {
+    let mut newest: Option<(std::time::SystemTime, PathBuf)> = None;
+
+    for entry in std::fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        if let Some(name) = path.file_stem() {
-            if name.to_string_lossy().starts_with(&format!("{}_video", job_id)) {
-                return Ok(path.to_string_lossy().to_string());
+
+        let stem_matches = path.file_stem().is_some_and(|s| s.to_string_lossy() == file_stem);
+        let is_video = path
+            .extension()
+            .is_some_and(|e| VIDEO_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str()));
+        if !stem_matches || !is_video {
+            continue;
+        }
+
+        let modified = entry.metadata()?.modified()?;
+        let is_newer = match &newest {
+            Some((newest_time, _)) => modified > *newest_time,
+            None => true,
+        };
+        if is_newer {
+            newest = Some((modified, path));
+        }
+    }
+
+    Ok(newest.map(|(_, path)| path))
+}
+
+/// Stable cache key for a URL + download format (+ SponsorBlock categories,
+/// since trimmed and untrimmed downloads differ)
+fn cache_key(url: &str, sponsorblock: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(normalize_url(url).as_bytes());
+    hasher.update(b"\n");
+    hasher.update(DOWNLOAD_FORMAT.as_bytes());
+    if let Some(categories) = sponsorblock {
+        hasher.update(b"\nsponsorblock:");
+        hasher.update(categories.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Share/tracking query parameters that don't change which video a URL points at
+const TRACKING_PARAMS: &[&str] = &[
+    "igsh", "igshid", "si", "feature", "fbclid", "gclid", "mibextid", "ref", "ref_src",
+    "is_from_webapp", "sender_device", "_r", "_t",
+];
+
+fn is_tracking_param(pair: &str) -> bool {
+    let name = pair.split('=').next().unwrap_or(pair).to_lowercase();
+    name.starts_with("utm_") || TRACKING_PARAMS.contains(&name.as_str())
+}
+
+/// Lowercase scheme/host, drop the fragment, trailing slash, and tracking
+/// parameters, so share links for the same video compare equal
+pub fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let url = url.split('#').next().unwrap_or(url);
+
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (scheme.to_lowercase(), rest),
+        None => ("https".to_string(), url),
+    };
+
+    let (host, path) = match rest.find(['/', '?']) {
+        Some(pos) => rest.split_at(pos),
+        None => (rest, ""),
+    };
+
+    let path = match path.split_once('?') {
+        Some((p, query)) => {
+            let kept: Vec<&str> = query
+                .split('&')
+                .filter(|pair| !pair.is_empty() && !is_tracking_param(pair))
+                .collect();
+            if kept.is_empty() {
+                p.trim_end_matches('/').to_string()
+            } else {
+                format!("{}?{}", p.trim_end_matches('/'), kept.join("&"))
             }
         }
+        None => path.trim_end_matches('/').to_string(),
+    };
+
+    format!("{}://{}{}", scheme, host.to_lowercase(), path)
+}
+
+/// Stable identifier for the video behind `url`, after normalization
+pub fn url_hash(url: &str) -> String {
+    hex::encode(Sha256::digest(normalize_url(url).as_bytes()))
+}
+
+fn url_lock(key: &str) -> Arc<tokio::sync::Mutex<()>> {
+    static LOCKS: OnceLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+
+    let mut locks = LOCKS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+
+    // Drop locks nobody else is holding so the map doesn't grow unbounded
+    locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+
+    locks
+        .entry(key.to_string())
+        .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(())))
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    // Captured yt-dlp stderr, one per category
+    const GEO_BLOCKED: &str = "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. The uploader has not made this video available in your country\n";
+    const AGE_RESTRICTED: &str = "ERROR: [youtube] 7ZbXzlc4gfs: Sign in to confirm your age. This video may be inappropriate for some users. Use --cookies-from-browser or --cookies for the authentication.\n";
+    const PRIVATE: &str = "ERROR: [youtube] Xy1aB2cD3eF: Private video. Sign in if you've been granted access to this video. Use --cookies-from-browser or --cookies for the authentication.\n";
+    const REMOVED: &str = "ERROR: [youtube] 0AbCdEfGhIj: Video unavailable. This video has been removed by the uploader\n";
+    const NOT_FOUND: &str = "ERROR: [TikTok] 7301234567890123456: Unable to download webpage: HTTP Error 404: Not Found (caused by <HTTPError 404: Not Found>)\n";
+    const DNS: &str = "WARNING: [generic] Falling back on generic information extractor\nERROR: [generic] Unable to download webpage: <urlopen error [Errno -3] Temporary failure in name resolution> (caused by TransportError('<urlopen error [Errno -3] Temporary failure in name resolution>'))\n";
+    const RATE_LIMITED: &str = "ERROR: [Instagram] C1a2B3c4D5e: Unable to download webpage: HTTP Error 429: Too Many Requests (caused by <HTTPError 429: Too Many Requests>)\n";
+    const TIMEOUT: &str = "ERROR: [download] Got error: The read operation timed out\n";
+    const UNRECOGNISED: &str = "ERROR: [TikTok] 7301234567890123456: Unable to extract webpage video data; please report this issue on  https://github.com/yt-dlp/yt-dlp/issues?q= , filling out the appropriate issue template. Confirm you are on the latest version using  yt-dlp -U\n";
+
+    #[test]
+    fn classify_failure_recognises_captured_stderr() {
+        let cases = [
+            (GEO_BLOCKED, DownloadFailure::GeoBlocked),
+            (AGE_RESTRICTED, DownloadFailure::AgeRestricted),
+            (PRIVATE, DownloadFailure::Private),
+            (REMOVED, DownloadFailure::Unavailable),
+            (NOT_FOUND, DownloadFailure::Unavailable),
+            (DNS, DownloadFailure::NetworkTransient),
+            (RATE_LIMITED, DownloadFailure::NetworkTransient),
+            (TIMEOUT, DownloadFailure::NetworkTransient),
+            (UNRECOGNISED, DownloadFailure::Unknown),
+            ("", DownloadFailure::Unknown),
+        ];
+        for (stderr, expected) in cases {
+            assert_eq!(classify_failure(stderr), expected, "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn only_network_and_unknown_failures_are_retried() {
+        for stderr in [GEO_BLOCKED, AGE_RESTRICTED, PRIVATE, REMOVED, NOT_FOUND] {
+            assert!(!ytdlp_error(stderr.as_bytes()).is_transient(), "{}", stderr);
+        }
+        for stderr in [DNS, RATE_LIMITED, TIMEOUT, UNRECOGNISED] {
+            assert!(ytdlp_error(stderr.as_bytes()).is_transient(), "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn ytdlp_error_keeps_the_last_error_line() {
+        let error = ytdlp_error(DNS.as_bytes());
+        assert_eq!(error.kind(), "network");
+        assert!(error.to_string().starts_with("Download failed (network error): [generic] Unable to download webpage"));
+
+        let error = ytdlp_error(GEO_BLOCKED.as_bytes());
+        assert_eq!(error.kind(), "geo_blocked");
+        assert_eq!(
+            error.to_string(),
+            "Download failed (geo-blocked): [youtube] dQw4w9WgXcQ: Video unavailable. The uploader has not made this video available in your country"
+        );
+
+        assert_eq!(failure_reason("Traceback (most recent call last):\n  boom\n"), "boom");
+        assert_eq!(failure_reason(""), "yt-dlp exited without an error message");
+    }
+
+    #[tokio::test]
+    async fn verify_download_rejects_empty_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("video.mp4");
+        std::fs::write(&path, b"").unwrap();
+
+        let error = verify_download(&path).await.unwrap_err();
+        assert_eq!(error.to_string(), "Download failed: downloaded file is empty");
+        assert!(error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn verify_download_rejects_truncated_files() {
+        // An MP4 cut off after its ftyp box and the start of mdat: yt-dlp
+        // exiting 0 on a dropped connection leaves files like this
+        let mut truncated = Vec::new();
+        truncated.extend_from_slice(&[0, 0, 0, 0x18]);
+        truncated.extend_from_slice(b"ftypisom\0\0\x02\0isomiso2");
+        truncated.extend_from_slice(&[0, 0x10, 0, 0]);
+        truncated.extend_from_slice(b"mdat");
+        truncated.extend_from_slice(&[0xAB; 512]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("video.mp4");
+        std::fs::write(&path, &truncated).unwrap();
+
+        let error = verify_download(&path).await.unwrap_err();
+        assert!(matches!(error, WorkerError::Download(_)), "{}", error);
+        assert!(error.is_transient());
+    }
+
+    #[test]
+    fn file_sha256_hashes_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("video.mp4");
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(
+            file_sha256(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn gallery_fallback_only_for_posts_without_video() {
+        const CAROUSEL: &str = "[Instagram] C1a2B3c4D5e: There is no video in this post";
+        const PHOTO_MODE: &str = "[TikTok] 7301234567890123456: No video formats found!; please report this issue on  https://github.com/yt-dlp/yt-dlp/issues?q= , filling out the appropriate issue template.";
+
+        assert!(is_gallery_post(CAROUSEL));
+        assert!(is_gallery_post(PHOTO_MODE));
+        assert!(is_gallery_post(&failure_reason(UNRECOGNISED)));
+        // A site yt-dlp doesn't know is not a photo post
+        assert!(!is_gallery_post("ERROR: Unsupported URL: https://example.com/recipes/42"));
+        assert!(!is_gallery_post(&failure_reason(PRIVATE)));
+        assert!(!is_gallery_post(&failure_reason(RATE_LIMITED)));
+    }
+
+    #[test]
+    fn gallery_media_sorts_files_by_kind_in_post_order() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["7301_10.jpg", "7301_2.jpg", "7301_9.webp", "7301_audio.mp3", "7301_1.jpg.part"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let media = gallery_media(dir.path()).unwrap();
+        let names = |paths: &[PathBuf]| -> Vec<String> {
+            paths.iter().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).collect()
+        };
+        assert!(media.videos.is_empty());
+        assert_eq!(names(&media.images), ["7301_2.jpg", "7301_9.webp", "7301_10.jpg"]);
+        assert_eq!(names(&media.audio), ["7301_audio.mp3"]);
+    }
+
+    #[test]
+    fn gallery_caption_reads_the_first_metadata_with_one() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("7301_2.jpg.json"), r#"{"description": "second"}"#).unwrap();
+        std::fs::write(dir.path().join("7301_1.jpg.json"), r#"{"description": "  ", "num": 1}"#).unwrap();
+        std::fs::write(dir.path().join("7301_10.jpg.json"), r#"{"desc": "tenth"}"#).unwrap();
+        assert_eq!(gallery_caption(dir.path()).as_deref(), Some("second"));
+        
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(gallery_caption(empty.path()), None);
+    }
+    
+    #[test]
+    fn kept_images_are_found_again_for_reruns() {
+        let job = tempfile::tempdir().unwrap();
+        let gallery = job.path().join("gallery");
+        std::fs::create_dir_all(&gallery).unwrap();
+        for name in ["post_2.jpg", "post_10.webp"] {
+            std::fs::write(gallery.join(name), b"").unwrap();
+        }
+        std::fs::write(gallery.join("post_2.jpg.json"), r#"{"description": "Miso pasta"}"#).unwrap();
+        
+        let post = keep_images(&gallery_media(&gallery).unwrap(), &gallery, job.path()).unwrap();
+        let names: Vec<&str> = post.images.iter().map(|p| p.rsplit('/').next().unwrap()).collect();
+        assert_eq!(names, ["image_001.jpg", "image_002.webp"]);
+        assert_eq!(post.caption.as_deref(), Some("Miso pasta"));
+        
+        let reused = existing_video(&job.path().to_string_lossy(), None).unwrap();
+        let reused_post = reused.image_post.unwrap();
+        assert_eq!(reused_post.images, post.images);
+        assert_eq!(reused_post.caption, post.caption);
+    }
+    
+    #[test]
+    fn a_downloaded_still_becomes_an_image_post() {
+        let job = tempfile::tempdir().unwrap();
+        std::fs::write(job.path().join("video.webp"), b"").unwrap();
+        let job_dir = job.path().to_string_lossy().to_string();
+        
+        let still = find_image(&job_dir, "video").unwrap().unwrap();
+        let downloaded = DownloadedVideo {
+            path: still.to_string_lossy().to_string(),
+            sponsorblock_trimmed: false,
+            integrity: None,
+            section: None,
+            image_post: None,
+        };
+        let video = as_image_post(downloaded, job.path()).unwrap();
+        
+        let post = video.image_post.unwrap();
+        assert_eq!(post.images.len(), 1);
+        assert!(post.images[0].ends_with("images/image_001.webp"));
+        assert!(!still.exists());
+        assert!(existing_video(&job_dir, None).unwrap().image_post.is_some());
     }
     
-    anyhow::bail!("Downloaded video file not found")
-}
\ No newline at end of file
+    #[test]
+    fn slideshow_script_repeats_the_last_image() {
+        let images = [PathBuf::from("/job/gallery/a.jpg"), PathBuf::from("/job/gallery/chef's.jpg")];
+        assert_eq!(
+            slideshow_script(&images),
+            "ffconcat version 1.0\n\
+             file '/job/gallery/a.jpg'\nduration 3\n\
+             file '/job/gallery/chef'\\''s.jpg'\nduration 3\n\
+             file '/job/gallery/chef'\\''s.jpg'\n"
+        );
+    }
+
+    #[test]
+    fn find_file_prefers_the_newest_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let stale = dir.path().join("job1_video.webm");
+        let fresh = dir.path().join("job1_video.mp4");
+        std::fs::write(&stale, b"old").unwrap();
+        std::fs::write(&fresh, b"new").unwrap();
+        std::fs::write(dir.path().join("job1_video.f137.mp4"), b"fragment").unwrap();
+        std::fs::write(dir.path().join("job1_video.mp4.part"), b"partial").unwrap();
+
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+
+        let found = find_file(dir.path().to_str().unwrap(), "job1_video").unwrap();
+        assert_eq!(found, Some(fresh));
+    }
+
+    #[test]
+    fn section_parses_seconds_and_clock_times() {
+        assert_eq!(Section::parse("90-150"), Ok(Section { start: 90.0, end: 150.0 }));
+        assert_eq!(Section::parse("1:30-2:30.5"), Ok(Section { start: 90.0, end: 150.5 }));
+        assert_eq!(Section::parse("1:00:00-1:00:10").map(|s| s.start), Ok(3600.0));
+        assert!(Section::parse("150-90").is_err());
+        assert!(Section::parse("90").is_err());
+        assert!(Section::parse("a-b").is_err());
+    }
+
+    #[test]
+    fn section_from_job_accepts_objects_and_strings() {
+        assert_eq!(Section::from_job(&serde_json::json!({})), Ok(None));
+        assert_eq!(
+            Section::from_job(&serde_json::json!({ "section": { "start": 10, "end": 25.5 } })),
+            Ok(Some(Section { start: 10.0, end: 25.5 }))
+        );
+        assert_eq!(
+            Section::from_job(&serde_json::json!({ "section": "0:10-0:20" })),
+            Ok(Some(Section { start: 10.0, end: 20.0 }))
+        );
+        assert!(Section::from_job(&serde_json::json!({ "section": { "start": 20, "end": 10 } })).is_err());
+        assert!(Section::from_job(&serde_json::json!({ "section": 5 })).is_err());
+    }
+
+    #[test]
+    fn find_file_ignores_non_video_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("job1_video.info.json"), b"{}").unwrap();
+        std::fs::write(dir.path().join("job1_video.ytdl"), b"").unwrap();
+
+        let found = find_file(dir.path().to_str().unwrap(), "job1_video").unwrap();
+        assert_eq!(found, None);
+    }
+
+    #[test]
+    fn normalize_url_strips_tracking_params() {
+        assert_eq!(
+            normalize_url("https://www.Instagram.com/reel/abc123/?igsh=xyz&utm_source=ig_web#top"),
+            "https://www.instagram.com/reel/abc123"
+        );
+        assert_eq!(
+            normalize_url("https://www.youtube.com/watch?v=dQw4w9WgXcQ&si=share&feature=youtu.be"),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+    }
+
+    #[test]
+    fn url_hash_matches_share_variants() {
+        assert_eq!(
+            url_hash("https://youtu.be/dQw4w9WgXcQ?si=abc"),
+            url_hash("https://youtu.be/dQw4w9WgXcQ/")
+        );
+        assert_ne!(
+            url_hash("https://www.youtube.com/watch?v=one"),
+            url_hash("https://www.youtube.com/watch?v=two")
+        );
+    }
+}
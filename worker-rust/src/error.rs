@@ -0,0 +1,146 @@
+use thiserror::Error;
+
+/// Errors produced by the video pipeline
+#[derive(Debug, Error)]
+pub enum WorkerError {
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("Invalid job: {0}")]
+    InvalidJob(String),
+
+    #[error("Download failed: {0}")]
+    Download(String),
+
+    /// yt-dlp ran and reported why it couldn't fetch the video
+    #[error("Download failed ({0}): {1}")]
+    DownloadRejected(DownloadFailure, String),
+
+    #[error("Video probe failed: {0}")]
+    Probe(String),
+
+    #[error("No video stream found")]
+    NoVideoStream,
+
+    #[error("Unsupported video format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Frame extraction failed: {0}")]
+    FrameExtraction(String),
+
+    #[error("OCR failed: {0}")]
+    Ocr(String),
+
+    #[error("Audio extraction failed: {0}")]
+    AudioExtraction(String),
+
+    #[error("Transcription failed: {0}")]
+    Transcription(String),
+
+    #[error("Storage upload failed: {0}")]
+    Storage(String),
+
+    #[error("Database write failed: {0}")]
+    Database(String),
+    
+    /// Publishing the finished job to the AI queue failed
+    #[error("AI queue enqueue failed: {0}")]
+    Enqueue(String),
+
+    #[error("Job was cancelled")]
+    Cancelled,
+
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl WorkerError {
+    /// Whether retrying the job could plausibly succeed.
+    ///
+    /// Network and infrastructure failures are transient; problems with the
+    /// media itself will fail the same way every time.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            WorkerError::Download(_) | WorkerError::Enqueue(_) | WorkerError::Redis(_) | WorkerError::Io(_)
+        ) || matches!(self, WorkerError::DownloadRejected(failure, _) if failure.is_transient())
+    }
+
+    /// Short machine-readable name for the error category
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WorkerError::InvalidUrl(_) => "invalid_url",
+            WorkerError::InvalidJob(_) => "invalid_job",
+            WorkerError::Download(_) => "download",
+            WorkerError::DownloadRejected(failure, _) => failure.kind(),
+            WorkerError::Probe(_) => "probe",
+            WorkerError::NoVideoStream => "no_video_stream",
+            WorkerError::UnsupportedFormat(_) => "unsupported_format",
+            WorkerError::FrameExtraction(_) => "frame_extraction",
+            WorkerError::Ocr(_) => "ocr",
+            WorkerError::AudioExtraction(_) => "audio_extraction",
+            WorkerError::Transcription(_) => "transcription",
+            WorkerError::Storage(_) => "storage",
+            WorkerError::Database(_) => "database",
+            WorkerError::Enqueue(_) => "enqueue",
+            WorkerError::Cancelled => "cancelled",
+            WorkerError::Redis(_) => "redis",
+            WorkerError::Io(_) => "io",
+        }
+    }
+}
+
+/// Why yt-dlp couldn't download a video, as far as its stderr tells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadFailure {
+    /// Not available from the worker's region
+    GeoBlocked,
+    /// Needs a signed-in account that has confirmed its age
+    AgeRestricted,
+    /// Private video or account
+    Private,
+    /// Removed, deleted, or never existed
+    Unavailable,
+    /// Timeouts, DNS, connection resets, rate limiting, 5xx responses
+    NetworkTransient,
+    /// Anything yt-dlp said that isn't recognised
+    Unknown,
+}
+
+impl DownloadFailure {
+    /// Only network trouble and unrecognised failures are worth retrying;
+    /// the rest depend on the video and fail the same way every time
+    pub fn is_transient(self) -> bool {
+        matches!(self, DownloadFailure::NetworkTransient | DownloadFailure::Unknown)
+    }
+
+    /// Error kind recorded for the job; Unknown keeps the generic "download"
+    pub fn kind(self) -> &'static str {
+        match self {
+            DownloadFailure::GeoBlocked => "geo_blocked",
+            DownloadFailure::AgeRestricted => "age_restricted",
+            DownloadFailure::Private => "private",
+            DownloadFailure::Unavailable => "unavailable",
+            DownloadFailure::NetworkTransient => "network",
+            DownloadFailure::Unknown => "download",
+        }
+    }
+}
+
+impl std::fmt::Display for DownloadFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DownloadFailure::GeoBlocked => "geo-blocked",
+            DownloadFailure::AgeRestricted => "age-restricted",
+            DownloadFailure::Private => "private",
+            DownloadFailure::Unavailable => "unavailable",
+            DownloadFailure::NetworkTransient => "network error",
+            DownloadFailure::Unknown => "yt-dlp error",
+        })
+    }
+}
+
+pub type Result<T> = std::result::Result<T, WorkerError>;
@@ -0,0 +1,294 @@
+use anyhow::Result;
+use image::imageops::FilterType;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+use crate::video::FrameData;
+
+/// `fingerprint:{job_id}` -> JSON array of the job's frame hashes (hex)
+const FINGERPRINT_PREFIX: &str = "fingerprint:";
+
+/// `fingerprint_bands:{band}:{bits}` -> sorted set of job_ids with a frame
+/// hash containing those 16 bits at that position, scored by when the job was
+/// recorded so entries past the TTL can be trimmed even from a band that
+/// keeps getting new members
+const BAND_PREFIX: &str = "fingerprint_bands:";
+
+/// Each 64-bit hash is indexed as four 16-bit bands; near-identical frames
+/// almost always agree on at least one, so sharing a band makes a job a
+/// candidate without comparing against every stored fingerprint
+const BANDS: u32 = 4;
+
+/// Frame hashes this many bits apart or fewer count as the same picture
+/// (survives re-encoding, rescaling, and small overlays)
+const MAX_FRAME_DISTANCE: u32 = 10;
+
+/// Default share of frames that must match for videos to count as the same
+const DEFAULT_THRESHOLD: f64 = 0.8;
+
+/// Default fingerprint lifetime (30 days)
+const DEFAULT_TTL_SECS: u64 = 30 * 86_400;
+
+/// Fewer usable frames than this says too little to call a match
+const MIN_FRAMES: usize = 3;
+
+/// Most candidate jobs compared in full per check
+const MAX_CANDIDATES: usize = 20;
+
+/// A prior job whose frames match this one's
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentMatch {
+    pub job_id: String,
+    /// Share of frames with a counterpart in the other video, 0-1
+    pub similarity: f64,
+}
+
+/// Flags re-uploads of an already processed video under a different URL by
+/// comparing perceptual hashes of their frames
+pub struct ContentDedup {
+    threshold: f64,
+    ttl_secs: u64,
+}
+
+impl ContentDedup {
+    /// Enabled by CONTENT_DEDUP; CONTENT_DEDUP_THRESHOLD sets the share of
+    /// matching frames (0-1) and CONTENT_DEDUP_TTL_SECS how long
+    /// fingerprints are kept
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("CONTENT_DEDUP")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+
+        let threshold = std::env::var("CONTENT_DEDUP_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|t| *t > 0.0 && *t <= 1.0)
+            .unwrap_or(DEFAULT_THRESHOLD);
+        let ttl_secs = std::env::var("CONTENT_DEDUP_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|s| *s > 0)
+            .unwrap_or(DEFAULT_TTL_SECS);
+
+        info!("Content deduplication enabled (threshold {:.2}, ttl {}s)", threshold, ttl_secs);
+
+        Some(Self { threshold, ttl_secs })
+    }
+
+    /// Fingerprint `frames`, look for a prior job above the threshold, and
+    /// store this job's fingerprint for later ones
+    pub async fn check_and_record(
+        &self,
+        conn: &mut ConnectionManager,
+        job_id: &str,
+        frames: &[FrameData],
+    ) -> Result<Option<ContentMatch>> {
+        let paths: Vec<String> = frames.iter().map(|f| f.frame_path.clone()).collect();
+        let hashes = tokio::task::spawn_blocking(move || fingerprint(&paths)).await?;
+        if hashes.len() < MIN_FRAMES {
+            info!("Job {}: Only {} usable frames, skipping content fingerprint", job_id, hashes.len());
+            return Ok(None);
+        }
+
+        let found = self.find_match(conn, job_id, &hashes).await?;
+        self.record(conn, job_id, &hashes).await?;
+        Ok(found)
+    }
+
+    async fn find_match(
+        &self,
+        conn: &mut ConnectionManager,
+        job_id: &str,
+        hashes: &[u64],
+    ) -> Result<Option<ContentMatch>> {
+        let oldest = unix_now().saturating_sub(self.ttl_secs);
+        let mut pipe = redis::pipe();
+        for hash in hashes {
+            for band in 0..BANDS {
+                pipe.cmd("ZRANGEBYSCORE").arg(band_key(*hash, band)).arg(oldest).arg("+inf");
+            }
+        }
+        let members: Vec<Vec<String>> = pipe.query_async(conn).await?;
+
+        // Jobs sharing the most bands are the likeliest matches
+        let mut shared: HashMap<String, usize> = HashMap::new();
+        for prior in members.into_iter().flatten().filter(|prior| prior != job_id) {
+            *shared.entry(prior).or_insert(0) += 1;
+        }
+        let mut candidates: Vec<(String, usize)> = shared.into_iter().collect();
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        candidates.truncate(MAX_CANDIDATES);
+
+        let mut best: Option<ContentMatch> = None;
+        for (prior, _) in candidates {
+            let stored: Option<String> = redis::cmd("GET")
+                .arg(format!("{}{}", FINGERPRINT_PREFIX, prior))
+                .query_async(conn)
+                .await?;
+            // A band entry can outlive its fingerprint by up to a trim; a
+            // missing one is a miss
+            let Some(prior_hashes) = stored.as_deref().and_then(parse_hashes) else {
+                continue;
+            };
+
+            let score = similarity(hashes, &prior_hashes);
+            if score >= self.threshold && !best.as_ref().is_some_and(|b| b.similarity >= score) {
+                best = Some(ContentMatch { job_id: prior, similarity: score });
+            }
+        }
+
+        Ok(best)
+    }
+
+    async fn record(&self, conn: &mut ConnectionManager, job_id: &str, hashes: &[u64]) -> Result<()> {
+        let encoded: Vec<String> = hashes.iter().map(|h| format!("{:016x}", h)).collect();
+        let now = unix_now();
+
+        let mut pipe = redis::pipe();
+        pipe.cmd("SET")
+            .arg(format!("{}{}", FINGERPRINT_PREFIX, job_id))
+            .arg(serde_json::to_string(&encoded)?)
+            .arg("EX")
+            .arg(self.ttl_secs)
+            .ignore();
+        for hash in hashes {
+            for band in 0..BANDS {
+                let key = band_key(*hash, band);
+                pipe.cmd("ZADD").arg(&key).arg(now).arg(job_id).ignore();
+                // Common bands are written too often to ever expire whole
+                pipe.cmd("ZREMRANGEBYSCORE")
+                    .arg(&key)
+                    .arg("-inf")
+                    .arg(format!("({}", now.saturating_sub(self.ttl_secs)))
+                    .ignore();
+                pipe.cmd("EXPIRE").arg(&key).arg(self.ttl_secs).ignore();
+            }
+        }
+        let _: () = pipe.query_async(conn).await?;
+
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn band_key(hash: u64, band: u32) -> String {
+    format!("{}{}:{:04x}", BAND_PREFIX, band, (hash >> (band * 16)) & 0xffff)
+}
+
+fn parse_hashes(stored: &str) -> Option<Vec<u64>> {
+    let encoded: Vec<String> = serde_json::from_str(stored).ok()?;
+    encoded.iter().map(|h| u64::from_str_radix(h, 16).ok()).collect()
+}
+
+/// Hashes of the frames at `paths` that carry enough detail to compare.
+/// Near-flat frames (black fades, solid title cards) hash to almost all
+/// zeros and would match any other video's fades, so they are left out.
+fn fingerprint(paths: &[String]) -> Vec<u64> {
+    paths
+        .iter()
+        .filter_map(|path| match frame_hash(path) {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                warn!("Failed to load {} for fingerprinting: {}", path, e);
+                None
+            }
+        })
+        .filter(|hash| (4..=60).contains(&hash.count_ones()))
+        .collect()
+}
+
+/// 64-bit difference hash: each bit says whether a pixel of a 9x8 grayscale
+/// copy is brighter than its right-hand neighbour
+fn frame_hash(path: &str) -> image::ImageResult<u64> {
+    let gray = image::open(path)?
+        .resize_exact(9, 8, FilterType::Triangle)
+        .to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if gray.get_pixel(x, y)[0] > gray.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Ok(hash)
+}
+
+/// Share of frames in either video with a near-identical frame in the other,
+/// averaged over both directions so a short clip of a long video scores
+/// lower than a full re-upload
+fn similarity(a: &[u64], b: &[u64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let covered = |from: &[u64], to: &[u64]| {
+        let matched = from
+            .iter()
+            .filter(|x| to.iter().any(|y| (*x ^ *y).count_ones() <= MAX_FRAME_DISTANCE))
+            .count();
+        matched as f64 / from.len() as f64
+    };
+    (covered(a, b) + covered(b, a)) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similarity_tolerates_small_bit_differences() {
+        let original = [0x0f0f_0f0f_0f0f_0f0f, 0x3c3c_3c3c_3c3c_3c3c, 0x5555_aaaa_5555_aaaa];
+        // Re-encoded: a few bits flipped per frame
+        let reupload = [0x0f0f_0f0f_0f0f_0f0e, 0x3c3c_3c3c_3c3c_7c3c, 0x5555_aaaa_5555_aaab];
+        assert_eq!(similarity(&original, &reupload), 1.0);
+
+        let unrelated = [!original[0], !original[1], !original[2]];
+        assert_eq!(similarity(&original, &unrelated), 0.0);
+
+        // A clip holding one of the three frames
+        let clip = [original[1]];
+        assert!((similarity(&original, &clip) - (1.0 / 3.0 + 1.0) / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn frame_hash_ignores_rescaling() {
+        let dir = tempfile::tempdir().unwrap();
+        // Smooth waves, so resampling at either size lands on the same shape
+        let waves = |width: u32, height: u32| {
+            image::GrayImage::from_fn(width, height, |x, y| {
+                let u = x as f64 / width as f64 * std::f64::consts::TAU * 1.5;
+                let v = y as f64 / height as f64 * std::f64::consts::PI;
+                image::Luma([(128.0 + 100.0 * u.sin() * v.cos()) as u8])
+            })
+        };
+        let large = dir.path().join("large.png");
+        let small = dir.path().join("small.png");
+        waves(640, 360).save(&large).unwrap();
+        waves(320, 180).save(&small).unwrap();
+
+        let a = frame_hash(large.to_str().unwrap()).unwrap();
+        let b = frame_hash(small.to_str().unwrap()).unwrap();
+        assert!((a ^ b).count_ones() <= MAX_FRAME_DISTANCE);
+    }
+
+    #[test]
+    fn stored_hashes_round_trip() {
+        let hashes = vec![0x0123_4567_89ab_cdef, u64::MAX, 1];
+        let encoded: Vec<String> = hashes.iter().map(|h| format!("{:016x}", h)).collect();
+        assert_eq!(parse_hashes(&serde_json::to_string(&encoded).unwrap()), Some(hashes));
+        assert_eq!(band_key(0x0123_4567_89ab_cdef, 0), "fingerprint_bands:0:cdef");
+        assert_eq!(band_key(0x0123_4567_89ab_cdef, 3), "fingerprint_bands:3:0123");
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::Message;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Offset, TopicPartitionList};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::error::WorkerError;
+use crate::result::AiJob;
+use crate::transport::{self, Job, JobSource, ResultSink};
+
+/// How long `next_job` waits for a message before returning None
+const POLL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Position of a consumed message, committed once the job is done
+pub struct KafkaReceipt {
+    topic: String,
+    partition: i32,
+    offset: i64,
+}
+
+/// Consumes job messages from a topic with manual offset commits
+pub struct KafkaSource {
+    consumer: StreamConsumer,
+}
+
+impl KafkaSource {
+    pub fn new(brokers: &str, group: &str, topic: &str) -> Result<Self> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("group.id", group)
+            // Offsets are committed only after a job is published
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .context("Failed to create Kafka consumer")?;
+
+        consumer
+            .subscribe(&[topic])
+            .with_context(|| format!("Failed to subscribe to {}", topic))?;
+
+        info!("Consuming jobs from Kafka topic {} (group {})", topic, group);
+
+        Ok(Self { consumer })
+    }
+}
+
+impl KafkaSource {
+    fn commit(&self, receipt: &KafkaReceipt) -> Result<()> {
+        let mut offsets = TopicPartitionList::new();
+        // The committed offset is the next one to read
+        offsets.add_partition_offset(&receipt.topic, receipt.partition, Offset::Offset(receipt.offset + 1))?;
+        self.consumer.commit(&offsets, CommitMode::Async)?;
+        Ok(())
+    }
+}
+
+impl JobSource for KafkaSource {
+    type Receipt = KafkaReceipt;
+
+    async fn next_job(&mut self) -> Result<Option<(Job, KafkaReceipt)>> {
+        let message = match tokio::time::timeout(POLL_TIMEOUT, self.consumer.recv()).await {
+            Ok(message) => message?,
+            Err(_) => return Ok(None),
+        };
+
+        let receipt = KafkaReceipt {
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+        };
+
+        let job = message
+            .payload()
+            .context("Empty Kafka message")
+            .and_then(|payload| Ok(serde_json::from_slice::<Value>(payload)?))
+            .and_then(Job::from_value);
+
+        match job {
+            Ok(job) => Ok(Some((job, receipt))),
+            Err(e) => {
+                // A malformed message will never parse; skip past it
+                warn!(
+                    "Skipping unparseable message at {}[{}]@{}: {}",
+                    receipt.topic, receipt.partition, receipt.offset, e
+                );
+                self.commit(&receipt)?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn ack(&mut self, receipt: KafkaReceipt) -> Result<()> {
+        self.commit(&receipt)
+    }
+
+    async fn retry(&mut self, receipt: KafkaReceipt) -> Result<()> {
+        self.consumer.seek(
+            &receipt.topic,
+            receipt.partition,
+            Offset::Offset(receipt.offset),
+            Duration::from_secs(10),
+        )?;
+        Ok(())
+    }
+}
+
+/// Publishes results, keyed by job_id, to an output topic. Messages are
+/// always JSON; AI_QUEUE_FORMAT only applies to the Redis AI queue.
+pub struct KafkaSink {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl KafkaSink {
+    pub fn new(brokers: &str, topic: &str) -> Result<Self> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "30000")
+            .create()
+            .context("Failed to create Kafka producer")?;
+
+        Ok(Self {
+            producer,
+            topic: topic.to_string(),
+        })
+    }
+
+    async fn send(&self, job_id: &str, payload: &Value) -> Result<()> {
+        let body = payload.to_string();
+        self.producer
+            .send(
+                FutureRecord::to(&self.topic).key(job_id).payload(&body),
+                Duration::from_secs(10),
+            )
+            .await
+            .map_err(|(e, _)| anyhow::anyhow!("Failed to publish job {}: {}", job_id, e))?;
+        Ok(())
+    }
+}
+
+impl ResultSink for KafkaSink {
+    async fn publish(&self, job: &AiJob) -> Result<()> {
+        // Failures share the topic, so mark which kind of message this is
+        let mut payload = serde_json::to_value(job)?;
+        payload["status"] = json!("ai_processing");
+        self.send(&job.job_id, &payload).await
+    }
+
+    async fn publish_failure(&self, job: &Job, error: &WorkerError) -> Result<()> {
+        self.send(&job.job_id, &transport::failure_payload(job, error)).await
+    }
+}
@@ -0,0 +1,81 @@
+//! Video processing for Reel to Recipe: download a reel, pull keyframes,
+//! OCR them, transcribe the audio, and assemble the result the AI worker
+//! turns into a recipe.
+//!
+//! `process_video_pipeline` runs everything for one URL. The individual
+//! stages are re-exported below for callers that only need part of it;
+//! they shell out to yt-dlp, ffmpeg, and whisper, so `preflight` should
+//! pass first. The `worker-rust` binary is a thin CLI over this crate.
+
+mod assemble;
+mod audio;
+mod config;
+#[cfg(feature = "postgres")]
+mod db;
+mod dedup;
+mod download;
+mod error;
+mod fingerprint;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod manifest;
+mod ocr;
+mod pipeline;
+mod preflight;
+mod ratelimit;
+mod redis_conn;
+mod result;
+mod selftest;
+#[cfg(feature = "http")]
+mod server;
+mod stage;
+#[cfg(feature = "s3")]
+mod storage;
+mod telemetry;
+mod timecode;
+mod tools;
+mod transcribe;
+mod transport;
+mod video;
+mod webhook;
+mod worker;
+
+use tokio_util::sync::CancellationToken;
+
+// Pipeline stages and their results
+pub use audio::{extract_audio, transcribe_audio, Transcript};
+pub use download::{download_video, DownloadedVideo, ImagePost, Section};
+pub use error::WorkerError;
+pub use ocr::{process_frames, OcrOptions};
+pub use pipeline::{probe_single_video, process_single_video, result_path, stream_single_video, write_atomic};
+pub use result::{AiJob, MediaType, ProbeResult, ProcessResult};
+pub use stage::StageMask;
+pub use transcribe::Transcriber;
+pub use video::{
+    dedupe_frames, extract_frames_at, extract_keyframes, limit_frames, process_video, FfprobeOutput, FrameData, FrameEncoding,
+    FrameFormat, PreviewFormat, VideoInfo, DEDUPE_DELTA_SECS,
+};
+
+// Setup shared by every entry point
+pub use config::Config;
+pub use download::set_max_concurrent_downloads;
+pub use preflight::{preflight, tool_versions, ToolStatus};
+pub use telemetry::{init as init_telemetry, shutdown as shutdown_telemetry};
+pub use tools::configure as configure_tools;
+pub use transcribe::{configure as configure_transcriber, Backend as TranscribeBackend};
+
+// Queue workers and their admin commands
+#[cfg(feature = "kafka")]
+pub use kafka::{KafkaSink, KafkaSource};
+pub use selftest::{run as self_test, SelfTestReport};
+#[cfg(feature = "http")]
+pub use server::serve;
+pub use transport::{run as run_transport, Job, JobSource, ResultSink};
+pub use worker::{queue_stats, requeue_dead_letters, DeadLetter, QueueStats, RequeueFilter, VideoWorker};
+
+/// Run every stage for `url` under a fresh job id and write
+/// `{output_dir}/{job_id}/result.json`
+pub async fn process_video_pipeline(url: &str, output_dir: &str, config: &Config) -> anyhow::Result<ProcessResult> {
+    let job_id = uuid::Uuid::new_v4().to_string();
+    pipeline::process_single_video(url, output_dir, &job_id, config, StageMask::ALL, None, &CancellationToken::new()).await
+}
@@ -1,15 +1,12 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use std::sync::Arc;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use tokio_util::sync::CancellationToken;
 use tracing::{info, error};
 
-mod audio;
-mod download;
-mod ocr;
-mod video;
-mod worker;
-
-use worker::VideoWorker;
+use worker_rust::{Config, QueueStats, RequeueFilter, Section, StageMask, TranscribeBackend, VideoWorker};
+#[cfg(feature = "kafka")]
+use worker_rust::{KafkaSink, KafkaSource};
 
 #[derive(Parser)]
 #[command(name = "worker-rust")]
@@ -18,13 +15,24 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
     
-    /// Redis URL
-    #[arg(long, env = "REDIS_URL", default_value = "redis://localhost:6379")]
-    redis_url: String,
+    /// Print the worker and external tool versions (same as the version command)
+    #[arg(short = 'V', long)]
+    version: bool,
+    
+    /// TOML config file; environment variables override it and flags override both
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    
+    /// Redis URL [default: REDIS_URL, else redis://localhost:6379]
+    #[arg(long, global = true)]
+    redis_url: Option<String>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Print the worker version and the detected yt-dlp, ffmpeg, ffprobe,
+    /// Tesseract, and Whisper versions
+    Version,
     /// Run as a worker processing jobs from Redis queue
     Worker {
         /// Consumer group name
@@ -34,84 +42,338 @@ enum Commands {
         #[arg(long)]
         consumer: Option<String>,
     },
+    /// Replay dead-lettered jobs onto the processing queue, e.g. after
+    /// fixing the cause of a batch of failures
+    Requeue {
+        /// Only jobs that failed with this error kind (download, geo_blocked,
+        /// probe, ...)
+        #[arg(long)]
+        error_kind: Option<String>,
+        /// Only this job
+        #[arg(long)]
+        job_id: Option<String>,
+        /// Requeue at most this many jobs, oldest first
+        #[arg(long)]
+        limit: Option<usize>,
+        /// List the matching jobs without requeueing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print queue length, pending entries per consumer, dead letters, and
+    /// job counts by status. Read-only.
+    Stats {
+        /// Consumer group whose pending entries are reported
+        #[arg(long, default_value = "video-workers")]
+        group: String,
+    },
     /// Process a single video file (CLI mode)
     Process {
         /// Video URL to download and process
         #[arg(short, long)]
         url: String,
-        /// Output directory
-        #[arg(short, long, default_value = "./output")]
-        output: String,
+        /// Output directory [default: OUTPUT_DIR, else ./output]
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Only download and probe metadata; skip frames, OCR, and audio
+        #[arg(long)]
+        probe_only: bool,
+        /// Also stream events to stdout as NDJSON while processing
+        #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+        output_format: OutputFormat,
+        /// Comma-separated stages to run (download, frames, ocr, audio,
+        /// transcribe), plus the stages that read their output; the rest
+        /// reuse an earlier run's output in the job directory, so this needs
+        /// --job-id
+        #[arg(long, value_parser = StageMask::parse, requires = "job_id")]
+        stages: Option<StageMask>,
+        /// Job id to use instead of a fresh one, e.g. to re-run stages of an earlier job
+        #[arg(long)]
+        job_id: Option<String>,
+        /// Only download and process this range, as start-end in seconds or
+        /// m:ss (e.g. 1:30-2:45); result timestamps are relative to its start
+        #[arg(long, value_parser = Section::parse)]
+        section: Option<Section>,
+    },
+    /// Run every pipeline stage on a small clip generated with ffmpeg and
+    /// report pass/fail per stage, to check a new deployment. Doesn't use Redis.
+    Selftest {
+        /// Download this video and test with it instead of the generated clip
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Process a file of newline-delimited video URLs
+    ProcessBatch {
+        /// File containing one URL per line
+        #[arg(short, long)]
+        input: PathBuf,
+        /// Number of videos to process at once
+        #[arg(short, long, default_value_t = 1)]
+        concurrency: usize,
+        /// Output directory [default: OUTPUT_DIR, else ./output]
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Accept jobs over HTTP instead of (or in front of) the Redis queue
+    #[cfg(feature = "http")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "0.0.0.0:8080")]
+        addr: String,
+        /// Enqueue jobs to Redis instead of processing them inline
+        #[arg(long)]
+        enqueue: bool,
+        /// Output directory for inline processing [default: OUTPUT_DIR, else ./output]
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Run as a worker consuming jobs from Kafka and publishing results to Kafka
+    #[cfg(feature = "kafka")]
+    KafkaWorker {
+        /// Comma-separated bootstrap servers
+        #[arg(long, env = "KAFKA_BROKERS", default_value = "localhost:9092")]
+        brokers: String,
+        /// Consumer group id
+        #[arg(long, env = "KAFKA_GROUP", default_value = "video-workers")]
+        group: String,
+        /// Topic to read jobs from
+        #[arg(long, env = "KAFKA_INPUT_TOPIC", default_value = "video_processing")]
+        input_topic: String,
+        /// Topic to publish results and failures to
+        #[arg(long, env = "KAFKA_OUTPUT_TOPIC", default_value = "ai_processing")]
+        output_topic: String,
+        /// Output directory [default: OUTPUT_DIR, else ./output]
+        #[arg(short, long)]
+        output: Option<String>,
     },
 }
 
+/// How the Process command reports results
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// Write `{job_id}/result.json` only
+    Json,
+    /// Additionally print one JSON line per video_info, frame, and transcript
+    /// segment as they are produced, then a `done` marker
+    Ndjson,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize tracing
-    tracing_subscriber::fmt::init();
+    worker_rust::init_telemetry()?;
     
     let cli = Cli::parse();
     
+    // Before settings are loaded, so a bad config file or environment can't
+    // stop --version from reporting what's installed
+    if cli.version || matches!(cli.command, Some(Commands::Version)) {
+        if let Ok(config) = Config::load(cli.config.as_deref()) {
+            worker_rust::configure_tools(config.binaries());
+        }
+        print_versions().await;
+        worker_rust::shutdown_telemetry();
+        return Ok(());
+    }
+    
+    let mut config = Config::load(cli.config.as_deref())?;
+    if let Some(redis_url) = cli.redis_url {
+        config.redis_url = redis_url;
+    }
+    worker_rust::configure_tools(config.binaries());
+    worker_rust::set_max_concurrent_downloads(config.max_concurrent_downloads);
+    worker_rust::configure_transcriber(TranscribeBackend::from_env()?);
+    // --output wins over OUTPUT_DIR / the config file
+    let output_or_default =
+        |output: Option<String>| output.unwrap_or_else(|| config.output_dir_or("./output"));
+    
     match cli.command {
+        Some(Commands::Version) => unreachable!("handled before the config is loaded"),
         Some(Commands::Worker { group, consumer }) => {
             info!("Starting video worker...");
-            let worker = VideoWorker::new(&cli.redis_url, &group, consumer.as_deref()).await?;
+            let worker = VideoWorker::new(&config, &group, consumer.as_deref()).await?;
             worker.run().await?;
         }
-        Some(Commands::Process { url, output }) => {
+        Some(Commands::Requeue { error_kind, job_id, limit, dry_run }) => {
+            let filter = RequeueFilter { error_kind, job_id, limit };
+            let jobs = worker_rust::requeue_dead_letters(&config, &filter, dry_run).await?;
+            for job in &jobs {
+                println!("{} ({}): {}", job.job_id, job.error_kind, job.error);
+            }
+            let verb = if dry_run { "Would requeue" } else { "Requeued" };
+            println!("{} {} job(s)", verb, jobs.len());
+        }
+        Some(Commands::Stats { group }) => {
+            let stats = worker_rust::queue_stats(&config, &group).await?;
+            print_stats(&stats, &group);
+        }
+        Some(Commands::Process { url, output, probe_only, output_format, stages, job_id, section }) => {
             info!("Processing single video: {}", url);
-            process_single_video(&url, &output).await?;
+            worker_rust::preflight().await?;
+            let output = output_or_default(output);
+            let stages = stages.unwrap_or_default();
+            let job_id = job_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+            let cancel = cancel_on_ctrl_c();
+            if probe_only {
+                worker_rust::probe_single_video(&url, &output, &job_id, &cancel).await?;
+            } else if output_format == OutputFormat::Ndjson {
+                worker_rust::stream_single_video(&url, &output, &job_id, &config, stages, section, &cancel).await?;
+            } else {
+                worker_rust::process_single_video(&url, &output, &job_id, &config, stages, section, &cancel).await?;
+            }
+        }
+        Some(Commands::Selftest { url }) => {
+            let report = worker_rust::self_test(url.as_deref()).await?;
+            for stage in &report.stages {
+                println!("{}", stage);
+            }
+            if !report.passed() {
+                anyhow::bail!("self-test failed");
+            }
+            println!("Self-test passed");
+        }
+        Some(Commands::ProcessBatch { input, concurrency, output }) => {
+            info!("Processing batch from {:?}", input);
+            worker_rust::preflight().await?;
+            let output = output_or_default(output);
+            process_batch(&input, concurrency, &output, &config, &cancel_on_ctrl_c()).await?;
+        }
+        #[cfg(feature = "http")]
+        Some(Commands::Serve { addr, enqueue, output }) => {
+            if !enqueue {
+                worker_rust::preflight().await?;
+            }
+            worker_rust::serve(&addr, &output_or_default(output), enqueue, &config).await?;
+        }
+        #[cfg(feature = "kafka")]
+        Some(Commands::KafkaWorker { brokers, group, input_topic, output_topic, output }) => {
+            info!("Starting Kafka video worker...");
+            worker_rust::preflight().await?;
+            let mut source = KafkaSource::new(&brokers, &group, &input_topic)?;
+            let sink = KafkaSink::new(&brokers, &output_topic)?;
+            let output = output_or_default(output);
+            worker_rust::run_transport(&mut source, &sink, &output, &config, &cancel_on_ctrl_c()).await?;
         }
         None => {
             // Default to worker mode
             info!("Starting video worker (default mode)...");
-            let worker = VideoWorker::new(&cli.redis_url, "video-workers", None).await?;
+            let worker = VideoWorker::new(&config, "video-workers", None).await?;
             worker.run().await?;
         }
     }
     
+    worker_rust::shutdown_telemetry();
+    
     Ok(())
 }
 
-async fn process_single_video(url: &str, output_dir: &str) -> Result<()> {
-    use std::path::Path;
-    use uuid::Uuid;
+/// Stats as a two-column table
+fn print_stats(stats: &QueueStats, group: &str) {
+    println!("{:<32} {:>8}", "queue:video_processing", stats.queue_length);
+    match stats.pending {
+        Some(pending) => {
+            println!("{:<32} {:>8}", format!("  pending ({})", group), pending);
+            for (consumer, count) in &stats.pending_by_consumer {
+                println!("{:<32} {:>8}", format!("    {}", consumer), count);
+            }
+        }
+        None => println!("{:<32} {:>8}", format!("  pending ({})", group), "no group"),
+    }
+    println!("{:<32} {:>8}", "queue:video_dead_letter", stats.dead_letters);
+    let total: u64 = stats.jobs_by_status.iter().map(|(_, count)| count).sum();
+    println!("{:<32} {:>8}", "jobs", total);
+    for (status, count) in &stats.jobs_by_status {
+        println!("{:<32} {:>8}", format!("  {}", status), count);
+    }
+}
+
+/// Versions for support tickets; tools that can't be run show as not installed
+async fn print_versions() {
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    for tool in worker_rust::tool_versions().await {
+        let version = match (&tool.version, tool.available) {
+            (Some(version), _) => version.as_str(),
+            (None, true) => "unknown version",
+            (None, false) => "not installed",
+        };
+        println!("{}: {}", tool.name, version);
+    }
+}
+
+/// Token that is cancelled when the process receives Ctrl-C
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let token = CancellationToken::new();
+    let child = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!("Received Ctrl-C, cancelling...");
+            child.cancel();
+        }
+    });
+    token
+}
+
+async fn process_batch(
+    input: &std::path::Path,
+    concurrency: usize,
+    output_dir: &str,
+    config: &Config,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    use futures::stream::{self, StreamExt};
     
-    let job_id = Uuid::new_v4().to_string();
     std::fs::create_dir_all(output_dir)?;
+    let contents = std::fs::read_to_string(input)?;
+    let urls: Vec<String> = contents
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect();
     
-    info!("Job {}: Downloading video from {}", job_id, url);
-    let video_path = download::download_video(url, output_dir, &job_id).await?;
-    
-    info!("Job {}: Processing video", job_id);
-    let video_info = video::process_video(&video_path, output_dir, &job_id).await?;
+    info!("Batch: {} URLs, concurrency {}", urls.len(), concurrency);
     
-    info!("Job {}: Extracting frames", job_id);
-    let frames = video::extract_keyframes(&video_path, output_dir, &job_id).await?;
+    let outcomes: Vec<(String, Result<PathBuf>)> = stream::iter(urls)
+        .map(|url| async move {
+            let job_id = uuid::Uuid::new_v4().to_string();
+            let outcome = worker_rust::process_single_video(&url, output_dir, &job_id, config, StageMask::ALL, None, cancel)
+                .await
+                .map(|_| worker_rust::result_path(output_dir, &job_id));
+            (url, outcome)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
     
-    info!("Job {}: Running OCR on frames", job_id);
-    let frames_with_ocr = ocr::process_frames(frames).await?;
-    
-    info!("Job {}: Extracting audio", job_id);
-    let audio_path = audio::extract_audio(&video_path, output_dir, &job_id).await?;
+    let mut succeeded = Vec::new();
+    let mut failed = Vec::new();
+    for (url, outcome) in outcomes {
+        match outcome {
+            Ok(result_path) => succeeded.push(serde_json::json!({
+                "url": url,
+                "result_path": result_path,
+            })),
+            Err(e) => {
+                error!("Batch: {} failed: {}", url, e);
+                failed.push(serde_json::json!({
+                    "url": url,
+                    "error": e.to_string(),
+                }));
+            }
+        }
+    }
     
-    info!("Job {}: Transcribing audio", job_id);
-    let transcription = audio::transcribe_audio(&audio_path).await?;
+    info!("Batch complete: {} succeeded, {} failed", succeeded.len(), failed.len());
     
-    // Save results
-    let result = serde_json::json!({
-        "job_id": job_id,
-        "video_path": video_path,
-        "video_info": video_info,
-        "frames": frames_with_ocr,
-        "audio_path": audio_path,
-        "transcription": transcription,
+    let summary = serde_json::json!({
+        "succeeded": succeeded.len(),
+        "failed": failed.len(),
+        "results": succeeded,
+        "errors": failed,
     });
+    let summary_path = std::path::Path::new(output_dir).join("batch_summary.json");
+    worker_rust::write_atomic(&summary_path, serde_json::to_string_pretty(&summary)?.as_bytes())?;
     
-    let result_path = Path::new(output_dir).join(format!("{}_result.json", job_id));
-    std::fs::write(&result_path, serde_json::to_string_pretty(&result)?)?;
-    
-    info!("Job {}: Complete! Results saved to {:?}", job_id, result_path);
+    info!("Batch summary saved to {:?}", summary_path);
     
     Ok(())
-}
\ No newline at end of file
+}
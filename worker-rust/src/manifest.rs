@@ -0,0 +1,85 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::pipeline;
+
+/// One file produced for a job
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    /// Path relative to the job directory
+    pub path: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+}
+
+/// Everything a job left in its `{job_id}/` directory
+#[derive(Debug, Clone, Serialize)]
+pub struct Manifest {
+    pub job_id: String,
+    pub created_at: String,
+    pub artifacts: Vec<Artifact>,
+}
+
+/// Whether to write `manifest.json` (WRITE_MANIFEST); off by default since
+/// it hashes every artifact, including the video
+pub fn manifest_enabled() -> bool {
+    std::env::var("WRITE_MANIFEST")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Where the manifest for a job is written
+pub fn manifest_path(job_dir: &Path) -> PathBuf {
+    job_dir.join("manifest.json")
+}
+
+/// Hash every file under `job_dir` and write `manifest.json` next to them
+pub async fn write_manifest(job_dir: &Path, job_id: &str) -> std::io::Result<PathBuf> {
+    let dir = job_dir.to_path_buf();
+    let job_id = job_id.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let mut artifacts = Vec::new();
+        collect(&dir, &dir, &mut artifacts)?;
+        artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let manifest = Manifest {
+            job_id,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            artifacts,
+        };
+
+        let path = manifest_path(&dir);
+        pipeline::write_atomic(&path, &serde_json::to_vec_pretty(&manifest)?)?;
+
+        Ok(path)
+    })
+    .await?
+}
+
+fn collect(root: &Path, dir: &Path, artifacts: &mut Vec<Artifact>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(root, &path, artifacts)?;
+            continue;
+        }
+        // A previous manifest is not an artifact of this run
+        if path == manifest_path(root) {
+            continue;
+        }
+
+        let mut hasher = Sha256::new();
+        let mut file = std::fs::File::open(&path)?;
+        let size_bytes = std::io::copy(&mut file, &mut hasher)?;
+
+        artifacts.push(Artifact {
+            path: path.strip_prefix(root).unwrap_or(&path).to_string_lossy().to_string(),
+            size_bytes,
+            sha256: hex::encode(hasher.finalize()),
+        });
+    }
+
+    Ok(())
+}
@@ -1,63 +1,1006 @@
-use anyhow::{Context, Result};
-use tracing::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, instrument, warn};
 
+use crate::error::{Result, WorkerError};
 use crate::video::FrameData;
 
-/// Process frames with OCR to extract text
-pub async fn process_frames(mut frames: Vec<FrameData>) -> Result<Vec<FrameData>> {
-    info!("Processing OCR for {} frames", frames.len());
+/// A recognized word and where it sits in the frame
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OcrWord {
+    pub text: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Tesseract word confidence, 0-100
+    pub confidence: f32,
+}
+
+/// Text recognized in one image
+pub(crate) struct OcrOutput {
+    pub text: String,
+    pub words: Option<Vec<OcrWord>>,
+    /// Text from the subtitle region pass, when one was requested
+    pub subtitle: Option<String>,
+}
+
+/// A rectangle given as fractions (0-1) of the frame's width and height
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Region {
+    /// The bottom third, where reels usually burn in captions
+    pub const BOTTOM_THIRD: Region = Region {
+        x: 0.0,
+        y: 0.67,
+        width: 1.0,
+        height: 0.33,
+    };
     
-    // Process frames in parallel using rayon or async
-    let mut tasks = Vec::new();
+    /// Parse `x,y,width,height` fractions; None unless it fits inside the frame
+    pub fn parse(value: &str) -> Option<Self> {
+        let parts: Vec<f32> = value
+            .split(',')
+            .map(|p| p.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let [x, y, width, height] = parts[..] else {
+            return None;
+        };
+        
+        let fits = x >= 0.0 && y >= 0.0 && width > 0.0 && height > 0.0 && x + width <= 1.0 && y + height <= 1.0;
+        fits.then_some(Region { x, y, width, height })
+    }
     
-    for frame in &frames {
-        let frame_path = frame.frame_path.clone();
-        tasks.push(tokio::spawn(async move {
-            extract_text_from_image(&frame_path).await
-        }));
+    /// Pixel rectangle (left, top, width, height) for an image of the given size
+    fn to_pixels(self, image_width: u32, image_height: u32) -> (i32, i32, i32, i32) {
+        let w = image_width as f32;
+        let h = image_height as f32;
+        (
+            (self.x * w).round() as i32,
+            (self.y * h).round() as i32,
+            (self.width * w).round().max(1.0) as i32,
+            (self.height * h).round().max(1.0) as i32,
+        )
+    }
+}
+
+/// Fewer OCR'd frames than this can't show that a line is persistent
+const MIN_WATERMARK_FRAMES: usize = 4;
+
+/// Default Tesseract page segmentation mode: a single uniform block of text
+const DEFAULT_PSM: u8 = 6;
+
+/// Highest page segmentation mode Tesseract accepts
+const MAX_PSM: u8 = 13;
+
+/// Tallest composite built for batched OCR; Tesseract rejects images over
+/// 32767 pixels in either dimension
+const MAX_COMPOSITE_HEIGHT: u32 = 32_000;
+
+/// White band between frames in a composite, so no text line spans two
+const COMPOSITE_GAP: u32 = 48;
+
+/// Which frames get OCR'd, how Tesseract reads them, and how much detail is kept
+#[derive(Debug, Clone)]
+pub struct OcrOptions {
+    /// Also record word-level bounding boxes (a second, hOCR pass per frame)
+    pub word_boxes: bool,
+    /// Skip regular-interval frames; they stay in the result without text
+    pub keyframes_only: bool,
+    /// Tesseract page segmentation mode (0-13). Useful ones here:
+    /// 6 for a block of text such as an ingredient card (default),
+    /// 7 for a single caption line, 11/12 for sparse overlay text scattered
+    /// around the frame, 3 for fully automatic layout analysis
+    pub psm: u8,
+    /// Only recognize these characters, e.g. digits and fraction glyphs for
+    /// quantities; None allows everything
+    pub whitelist: Option<String>,
+    /// Return per-frame timing stats for inclusion in the result
+    pub record_timing: bool,
+    /// Run a second pass over just this region for burned-in subtitles
+    pub subtitle_region: Option<Region>,
+    /// Drop text lines found on at least this fraction of OCR'd frames:
+    /// creator handles, platform logos, and other UI that sits on every
+    /// frame. None (the default) keeps everything.
+    pub watermark_threshold: Option<f64>,
+    /// Stack this many frames into one tall image and OCR it in a single
+    /// Tesseract pass, splitting the text back per frame by position; this
+    /// spreads engine start-up over the batch. 1 reads frames one by one.
+    /// Single line/word modes (psm 7-10, 13) always read frames one by one.
+    pub batch_size: usize,
+}
+
+/// Wall time spent in Tesseract across a process_frames call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrTiming {
+    pub frames: usize,
+    pub total_ms: u64,
+    pub mean_ms: f64,
+    pub max_ms: u64,
+    pub slowest_frame: Option<String>,
+}
+
+impl OcrTiming {
+    fn from_durations(durations: &[(String, Duration)]) -> Self {
+        let total: Duration = durations.iter().map(|(_, d)| *d).sum();
+        let slowest = durations.iter().max_by_key(|(_, d)| *d);
+        
+        Self {
+            frames: durations.len(),
+            total_ms: total.as_millis() as u64,
+            mean_ms: if durations.is_empty() {
+                0.0
+            } else {
+                total.as_secs_f64() * 1000.0 / durations.len() as f64
+            },
+            max_ms: slowest.map(|(_, d)| d.as_millis() as u64).unwrap_or(0),
+            slowest_frame: slowest.map(|(path, _)| path.clone()),
+        }
+    }
+}
+
+impl Default for OcrOptions {
+    fn default() -> Self {
+        Self {
+            word_boxes: false,
+            keyframes_only: false,
+            psm: DEFAULT_PSM,
+            whitelist: None,
+            record_timing: false,
+            subtitle_region: None,
+            watermark_threshold: None,
+            batch_size: 1,
+        }
+    }
+}
+
+impl OcrOptions {
+    /// Read OCR_WORD_BOXES, OCR_KEYFRAMES_ONLY, OCR_PSM, OCR_WHITELIST, OCR_TIMING,
+    /// OCR_SUBTITLES, OCR_SUBTITLE_REGION, OCR_WATERMARK_THRESHOLD, and
+    /// OCR_BATCH_SIZE. An out-of-range OCR_PSM, OCR_SUBTITLE_REGION,
+    /// OCR_WATERMARK_THRESHOLD, or OCR_BATCH_SIZE is ignored with a warning.
+    pub fn from_env() -> Self {
+        let psm = match std::env::var("OCR_PSM").ok().filter(|v| !v.is_empty()) {
+            Some(v) => match v.parse().ok().and_then(|p| validate_psm(p).ok()) {
+                Some(psm) => psm,
+                None => {
+                    warn!("Ignoring invalid OCR_PSM '{}' (expected 0-{})", v, MAX_PSM);
+                    DEFAULT_PSM
+                }
+            },
+            None => DEFAULT_PSM,
+        };
+        
+        Self {
+            word_boxes: env_flag("OCR_WORD_BOXES"),
+            keyframes_only: env_flag("OCR_KEYFRAMES_ONLY"),
+            psm,
+            whitelist: std::env::var("OCR_WHITELIST").ok().filter(|w| !w.is_empty()),
+            record_timing: env_flag("OCR_TIMING"),
+            subtitle_region: env_flag("OCR_SUBTITLES").then(subtitle_region_from_env),
+            watermark_threshold: watermark_threshold_from_env(),
+            batch_size: batch_size_from_env(),
+        }
+    }
+}
+
+/// OCR_BATCH_SIZE as a frame count of at least 1
+fn batch_size_from_env() -> usize {
+    let Some(v) = std::env::var("OCR_BATCH_SIZE").ok().filter(|v| !v.is_empty()) else {
+        return 1;
+    };
+    match v.parse::<usize>() {
+        Ok(n) if n >= 1 => n,
+        _ => {
+            warn!("Ignoring invalid OCR_BATCH_SIZE '{}' (expected a positive number)", v);
+            1
+        }
+    }
+}
+
+/// Whether text read in `psm` can be split back per frame; the single
+/// line, word, and character modes read a whole composite as one line
+fn supports_batching(psm: u8) -> bool {
+    !matches!(psm, 7..=10 | 13)
+}
+
+/// OCR_WATERMARK_THRESHOLD as a fraction in (0, 1]; unset or 0 leaves
+/// stripping off
+fn watermark_threshold_from_env() -> Option<f64> {
+    let v = std::env::var("OCR_WATERMARK_THRESHOLD").ok().filter(|v| !v.is_empty())?;
+    match v.parse::<f64>() {
+        Ok(0.0) => None,
+        Ok(t) if t > 0.0 && t <= 1.0 => Some(t),
+        _ => {
+            warn!("Ignoring invalid OCR_WATERMARK_THRESHOLD '{}' (expected 0-1)", v);
+            None
+        }
+    }
+}
+
+/// Check a page segmentation mode is one Tesseract knows
+pub fn validate_psm(psm: u8) -> Result<u8> {
+    if psm > MAX_PSM {
+        return Err(WorkerError::Ocr(format!(
+            "page segmentation mode {} out of range 0-{}",
+            psm, MAX_PSM
+        )));
+    }
+    Ok(psm)
+}
+
+fn subtitle_region_from_env() -> Region {
+    match std::env::var("OCR_SUBTITLE_REGION").ok().filter(|v| !v.is_empty()) {
+        Some(v) => Region::parse(&v).unwrap_or_else(|| {
+            warn!("Ignoring invalid OCR_SUBTITLE_REGION '{}' (expected x,y,width,height fractions)", v);
+            Region::BOTTOM_THIRD
+        }),
+        None => Region::BOTTOM_THIRD,
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Process frames with OCR to extract text.
+///
+/// Frames skipped by `options.keyframes_only` are returned untouched, so they
+/// remain available for thumbnail selection. Timing stats are returned when
+/// `options.record_timing` is set.
+#[instrument(skip_all, fields(frames = frames.len()))]
+pub async fn process_frames(
+    mut frames: Vec<FrameData>,
+    options: OcrOptions,
+) -> Result<(Vec<FrameData>, Option<OcrTiming>)> {
+    validate_psm(options.psm)?;
+    
+    if !tesseract_available().await {
+        warn!("Tesseract 'eng' data not available, skipping OCR for {} frames", frames.len());
+        return Ok((frames, None));
     }
     
+    let selected = select_frames(&frames, options.keyframes_only);
+    let attempted = selected.len();
+    let batch_size = if supports_batching(options.psm) { options.batch_size } else { 1 };
+    info!("Processing OCR for {} of {} frames", attempted, frames.len());
+    
+    let results = if batch_size > 1 {
+        ocr_batched(&frames, &selected, batch_size, &options).await
+    } else {
+        ocr_each(&frames, &selected, &options).await
+    };
+    
     // Collect results
-    for (i, task) in tasks.into_iter().enumerate() {
-        match task.await {
-            Ok(Ok(text)) => {
-                if !text.trim().is_empty() {
-                    frames[i].ocr_text = Some(text);
+    let mut durations = Vec::with_capacity(results.len());
+    for (i, output, elapsed) in results {
+        durations.push((frames[i].frame_path.clone(), elapsed));
+        match output {
+            Ok(output) => {
+                if !output.text.trim().is_empty() {
+                    frames[i].ocr_text = Some(output.text);
+                    frames[i].ocr_boxes = output.words;
                 }
-            }
-            Ok(Err(e)) => {
-                warn!("OCR failed for frame {}: {}", frames[i].frame_path, e);
+                frames[i].subtitle_text = output.subtitle.filter(|s| !s.is_empty());
             }
             Err(e) => {
-                warn!("Task panicked for frame: {}", e);
+                warn!("OCR failed for frame {}: {}", frames[i].frame_path, e);
             }
         }
     }
     
+    let timing = OcrTiming::from_durations(&durations);
+    debug!(
+        "OCR timing: {} frames, total {}ms, mean {:.1}ms, max {}ms ({})",
+        timing.frames,
+        timing.total_ms,
+        timing.mean_ms,
+        timing.max_ms,
+        timing.slowest_frame.as_deref().unwrap_or("-")
+    );
+    
+    // Needs every frame's text, and must run before collapsing clears repeats
+    if let Some(threshold) = options.watermark_threshold {
+        let watermarks = strip_watermarks(&mut frames, attempted, threshold);
+        if !watermarks.is_empty() {
+            info!("Stripped {} watermark line(s) from OCR text: {:?}", watermarks.len(), watermarks);
+        }
+    }
+    
     let text_frames = frames.iter().filter(|f| f.ocr_text.is_some()).count();
     info!("OCR complete: {}/{} frames contain text", text_frames, frames.len());
     
-    Ok(frames)
+    let collapsed = collapse_repeated_text(&mut frames, &selected);
+    if collapsed > 0 {
+        info!("Collapsed {} frames repeating the previous frame's text", collapsed);
+    }
+    
+    for frame in frames.iter_mut() {
+        frame.lang = frame.ocr_text.as_deref().and_then(detect_language);
+    }
+    
+    Ok((frames, options.record_timing.then_some(timing)))
+}
+
+/// Indices of the frames to OCR, in order
+fn select_frames(frames: &[FrameData], keyframes_only: bool) -> Vec<usize> {
+    frames
+        .iter()
+        .enumerate()
+        .filter(|(_, frame)| !keyframes_only || frame.is_keyframe)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// OCR each of the `selected` frames in its own task
+async fn ocr_each(
+    frames: &[FrameData],
+    selected: &[usize],
+    options: &OcrOptions,
+) -> Vec<(usize, Result<OcrOutput>, Duration)> {
+    let mut tasks = Vec::with_capacity(selected.len());
+    for &i in selected {
+        let frame_path = frames[i].frame_path.clone();
+        let options = options.clone();
+        // Timed inside the task so the measurement doesn't wait on other frames
+        tasks.push((i, tokio::spawn(async move {
+            let started = Instant::now();
+            let output = extract_text_from_image(&frame_path, &options).await;
+            (output, started.elapsed())
+        })));
+    }
+    
+    let mut results = Vec::with_capacity(tasks.len());
+    for (i, task) in tasks {
+        match task.await {
+            Ok((output, elapsed)) => results.push((i, output, elapsed)),
+            Err(e) => warn!("Task panicked for frame: {}", e),
+        }
+    }
+    results
+}
+
+/// OCR the `selected` frames `batch_size` at a time, one Tesseract pass per
+/// batch. Each frame is charged an equal share of its batch's time.
+async fn ocr_batched(
+    frames: &[FrameData],
+    selected: &[usize],
+    batch_size: usize,
+    options: &OcrOptions,
+) -> Vec<(usize, Result<OcrOutput>, Duration)> {
+    let mut tasks = Vec::new();
+    for batch in selected.chunks(batch_size) {
+        let paths: Vec<String> = batch.iter().map(|&i| frames[i].frame_path.clone()).collect();
+        let options = options.clone();
+        tasks.push((batch.to_vec(), tokio::task::spawn_blocking(move || {
+            let started = Instant::now();
+            let outputs = read_stacked(&paths, &options);
+            (outputs, started.elapsed())
+        })));
+    }
+    
+    let mut results = Vec::with_capacity(selected.len());
+    for (batch, task) in tasks {
+        match task.await {
+            Ok((outputs, elapsed)) => {
+                let share = elapsed / batch.len() as u32;
+                results.extend(batch.into_iter().zip(outputs).map(|(i, output)| (i, output, share)));
+            }
+            Err(e) => warn!("Task panicked for a batch of {} frames: {}", batch.len(), e),
+        }
+    }
+    results
+}
+
+/// Fold runs of the same text on consecutive text-bearing frames into the
+/// first frame of the run, which gets `ocr_text_until` set to the run's last
+/// timestamp. Comparison ignores case and whitespace to absorb OCR jitter.
+/// Only the `ocrd` frames (ascending indices) are looked at, so frames that
+/// were never read don't split a run; an OCR'd frame without text ends it.
+/// Returns how many frames were cleared.
+fn collapse_repeated_text(frames: &mut [FrameData], ocrd: &[usize]) -> usize {
+    let mut run_start: Option<(usize, String)> = None;
+    let mut collapsed = 0;
+    
+    for &i in ocrd {
+        let normalized = match &frames[i].ocr_text {
+            Some(text) => normalize_text(text),
+            None => {
+                run_start = None;
+                continue;
+            }
+        };
+        
+        match &run_start {
+            Some((start, previous)) if *previous == normalized => {
+                let timestamp = frames[i].timestamp;
+                frames[*start].ocr_text_until = Some(timestamp);
+                frames[i].ocr_text = None;
+                frames[i].ocr_boxes = None;
+                collapsed += 1;
+            }
+            _ => run_start = Some((i, normalized)),
+        }
+    }
+    
+    collapsed
+}
+
+/// Comparison key for watermark lines: letters, digits, and `@` only, so
+/// OCR noise around a handle or logo doesn't split its count
+fn watermark_key(line: &str) -> String {
+    line.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '@')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Remove text lines that appear on at least `threshold` of the `attempted`
+/// OCR'd frames, along with their word boxes; text that comes and goes is
+/// kept, boxes and all, even when it shares words with a watermark. Frames
+/// left without text lose their ocr_text. Returns the stripped lines
+/// (normalized), or nothing when too few frames were read to tell.
+fn strip_watermarks(frames: &mut [FrameData], attempted: usize, threshold: f64) -> Vec<String> {
+    if attempted < MIN_WATERMARK_FRAMES {
+        return Vec::new();
+    }
+    
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for text in frames.iter().filter_map(|f| f.ocr_text.as_deref()) {
+        let keys: HashSet<String> = text.lines().map(watermark_key).filter(|k| !k.is_empty()).collect();
+        for key in keys {
+            *counts.entry(key).or_insert(0) += 1;
+        }
+    }
+    
+    let min_frames = ((threshold * attempted as f64).ceil() as usize).max(MIN_WATERMARK_FRAMES);
+    let watermarks: HashSet<String> = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= min_frames)
+        .map(|(key, _)| key)
+        .collect();
+    if watermarks.is_empty() {
+        return Vec::new();
+    }
+    
+    for frame in frames.iter_mut() {
+        let Some(text) = frame.ocr_text.as_deref() else { continue };
+        let lines: Vec<(&str, bool)> = text
+            .lines()
+            .map(|line| (line, watermarks.contains(&watermark_key(line))))
+            .collect();
+        if !lines.iter().any(|(_, removed)| *removed) {
+            continue;
+        }
+        
+        let kept = lines
+            .iter()
+            .filter(|(_, removed)| !removed)
+            .map(|(line, _)| *line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        
+        if kept.trim().is_empty() {
+            frame.ocr_text = None;
+            frame.ocr_boxes = None;
+        } else {
+            if let Some(words) = frame.ocr_boxes.as_mut() {
+                let removed = removed_line_boxes(&lines, words);
+                *words = std::mem::take(words)
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(i, _)| !removed.contains(i))
+                    .map(|(_, word)| word)
+                    .collect();
+            }
+            frame.ocr_text = Some(kept);
+        }
+    }
+    
+    let mut watermarks: Vec<String> = watermarks.into_iter().collect();
+    watermarks.sort();
+    watermarks
+}
+
+/// Indices of the word boxes that belong to removed lines. Boxes come in
+/// reading order, so each line's words are matched against the boxes after
+/// the previous line's; a word Tesseract boxed differently is skipped.
+fn removed_line_boxes(lines: &[(&str, bool)], words: &[OcrWord]) -> HashSet<usize> {
+    let mut removed = HashSet::new();
+    let mut next = 0;
+    for (line, is_removed) in lines {
+        for key in line.split_whitespace().map(watermark_key).filter(|k| !k.is_empty()) {
+            let Some(offset) = words[next..].iter().position(|w| watermark_key(&w.text) == key) else {
+                continue;
+            };
+            if *is_removed {
+                removed.insert(next + offset);
+            }
+            next += offset + 1;
+        }
+    }
+    removed
+}
+
+/// Letters needed before a language guess is worth keeping
+const MIN_LANG_DETECT_CHARS: usize = 12;
+
+/// ISO 639-3 code for `text`, or None when it is too short or ambiguous
+fn detect_language(text: &str) -> Option<String> {
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    if letters < MIN_LANG_DETECT_CHARS {
+        return None;
+    }
+    
+    let info = whatlang::detect(text)?;
+    info.is_reliable().then(|| info.lang().code().to_string())
+}
+
+fn normalize_text(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Whether Tesseract could be initialized with "eng" data, checked once per process
+static TESSERACT_AVAILABLE: OnceCell<bool> = OnceCell::const_new();
+
+/// Whether Tesseract and its "eng" traineddata can be loaded. A missing
+/// install fails every frame the same way, so this is checked once up front.
+pub async fn tesseract_available() -> bool {
+    *TESSERACT_AVAILABLE
+        .get_or_init(|| async {
+            tokio::task::spawn_blocking(|| leptess::LepTess::new(None, "eng").is_ok())
+                .await
+                .unwrap_or(false)
+        })
+        .await
 }
 
 /// Extract text from image using Tesseract OCR
-async fn extract_text_from_image(image_path: &str) -> Result<String> {
+pub(crate) async fn extract_text_from_image(image_path: &str, options: &OcrOptions) -> Result<OcrOutput> {
     // Run OCR in a blocking task since leptess is not async
     let path = image_path.to_string();
-    let text = tokio::task::spawn_blocking(move || {
+    let psm = options.psm.to_string();
+    let whitelist = options.whitelist.clone();
+    let with_boxes = options.word_boxes;
+    let subtitle_region = options.subtitle_region;
+    let output = tokio::task::spawn_blocking(move || {
         use leptess::{LepTess, Variable};
         
-        let mut lt = LepTess::new(None, "eng")?;
-        lt.set_image(&path)?;
+        let ocr_err = |e: &dyn std::fmt::Display| WorkerError::Ocr(format!("{}: {}", path, e));
+        
+        let mut lt = LepTess::new(None, "eng").map_err(|e| ocr_err(&e))?;
+        lt.set_image(&path).map_err(|e| ocr_err(&e))?;
         
         // Optimize for text detection
-        lt.set_variable(Variable::TesseditPagesegMode, "6")?; // Assume uniform block of text
-        lt.set_variable(Variable::TesseditCharWhitelist, None)?;
+        lt.set_variable(Variable::TesseditPagesegMode, &psm).map_err(|e| ocr_err(&e))?;
+        lt.set_variable(Variable::TesseditCharWhitelist, whitelist.as_deref().unwrap_or(""))
+            .map_err(|e| ocr_err(&e))?;
+        
+        let text = lt.get_utf8_text().map_err(|e| ocr_err(&e))?;
+        let words = if with_boxes {
+            let hocr = lt.get_hocr_text(0).map_err(|e| ocr_err(&e))?;
+            Some(parse_hocr_words(&hocr))
+        } else {
+            None
+        };
         
-        Ok::<_, anyhow::Error>(lt.get_utf8_text()?)
+        // Same engine and image, restricted to the caption area
+        let subtitle = match (subtitle_region, lt.get_image_dimensions()) {
+            (Some(region), Some((width, height))) => {
+                let (left, top, w, h) = region.to_pixels(width, height);
+                lt.set_rectangle(left, top, w, h);
+                Some(lt.get_utf8_text().map_err(|e| ocr_err(&e))?.trim().to_string())
+            }
+            _ => None,
+        };
+        
+        Ok::<_, WorkerError>(OcrOutput { text, words, subtitle })
     })
     .await
-    .context("OCR task failed")??;
+    .map_err(|e| WorkerError::Ocr(format!("OCR task failed: {}", e)))??;
+    
+    Ok(output)
+}
+
+/// OCR the images at `paths` through as few stacked composites as fit under
+/// MAX_COMPOSITE_HEIGHT. Returns one output per path, in order.
+fn read_stacked(paths: &[String], options: &OcrOptions) -> Vec<Result<OcrOutput>> {
+    let images: Vec<Result<image::GrayImage>> = paths
+        .iter()
+        .map(|path| {
+            image::open(path)
+                .map(|image| image.to_luma8())
+                .map_err(|e| WorkerError::Ocr(format!("{}: {}", path, e)))
+        })
+        .collect();
+    
+    // Group the readable images into composites, in order
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut height = 0;
+    for (i, image) in images.iter().enumerate() {
+        let Ok(image) = image else { continue };
+        match groups.last_mut() {
+            Some(group) if height + COMPOSITE_GAP + image.height() <= MAX_COMPOSITE_HEIGHT => {
+                group.push(i);
+                height += COMPOSITE_GAP + image.height();
+            }
+            _ => {
+                groups.push(vec![i]);
+                height = image.height();
+            }
+        }
+    }
+    
+    let mut outputs: Vec<Option<Result<OcrOutput>>> = Vec::with_capacity(paths.len());
+    outputs.resize_with(paths.len(), || None);
+    for group in groups {
+        let stacked: Vec<&image::GrayImage> = group.iter().filter_map(|&i| images[i].as_ref().ok()).collect();
+        match read_composite(&stacked, options) {
+            Ok(read) => {
+                for (i, output) in group.into_iter().zip(read) {
+                    outputs[i] = Some(Ok(output));
+                }
+            }
+            Err(e) => {
+                for i in group {
+                    outputs[i] = Some(Err(WorkerError::Ocr(e.to_string())));
+                }
+            }
+        }
+    }
+    
+    images
+        .into_iter()
+        .zip(outputs)
+        .map(|(image, output)| match (image, output) {
+            (_, Some(output)) => output,
+            (Err(e), None) => Err(e),
+            (Ok(_), None) => Err(WorkerError::Ocr("frame left out of its OCR batch".to_string())),
+        })
+        .collect()
+}
+
+/// Stack `images` top to bottom on a white canvas, OCR it in one pass, and
+/// split the recognized lines back per image
+fn read_composite(images: &[&image::GrayImage], options: &OcrOptions) -> Result<Vec<OcrOutput>> {
+    use leptess::{LepTess, Variable};
     
-    Ok(text)
-}
\ No newline at end of file
+    let width = images.iter().map(|image| image.width()).max().unwrap_or(1);
+    let gaps = COMPOSITE_GAP * (images.len() as u32).saturating_sub(1);
+    let height = images.iter().map(|image| image.height()).sum::<u32>() + gaps;
+    let mut canvas = image::GrayImage::from_pixel(width, height, image::Luma([255]));
+    let mut tops = Vec::with_capacity(images.len());
+    let mut top = 0;
+    for image in images {
+        image::imageops::replace(&mut canvas, *image, 0, top as i64);
+        tops.push(top);
+        top += image.height() + COMPOSITE_GAP;
+    }
+    
+    let ocr_err = |e: &dyn std::fmt::Display| WorkerError::Ocr(format!("composite of {} frames: {}", images.len(), e));
+    
+    let mut png = Vec::new();
+    image::DynamicImage::ImageLuma8(canvas)
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+        .map_err(|e| ocr_err(&e))?;
+    
+    let mut lt = LepTess::new(None, "eng").map_err(|e| ocr_err(&e))?;
+    lt.set_image_from_mem(&png).map_err(|e| ocr_err(&e))?;
+    lt.set_variable(Variable::TesseditPagesegMode, &options.psm.to_string())
+        .map_err(|e| ocr_err(&e))?;
+    lt.set_variable(Variable::TesseditCharWhitelist, options.whitelist.as_deref().unwrap_or(""))
+        .map_err(|e| ocr_err(&e))?;
+    
+    // hOCR places every line, which is what maps text back to its frame
+    let hocr = lt.get_hocr_text(0).map_err(|e| ocr_err(&e))?;
+    let lines = parse_hocr_lines(&hocr);
+    
+    Ok(images
+        .iter()
+        .zip(tops)
+        .map(|(image, top)| frame_output(&lines, top, image.width(), image.height(), options))
+        .collect())
+}
+
+/// The composite lines whose vertical centre falls on the frame placed at
+/// `top`, moved back into the frame's own coordinates. Subtitle text is the
+/// lines centred inside the subtitle region, rather than a second pass.
+fn frame_output(lines: &[Vec<OcrWord>], top: u32, width: u32, height: u32, options: &OcrOptions) -> OcrOutput {
+    let subtitle_rect = options.subtitle_region.map(|region| region.to_pixels(width, height));
+    let mut text = Vec::new();
+    let mut words = Vec::new();
+    let mut subtitle = Vec::new();
+    
+    for line in lines {
+        let Some((x, y, w, h)) = line_bounds(line) else { continue };
+        let center_y = y + h / 2;
+        if center_y < top || center_y >= top + height {
+            continue;
+        }
+        
+        let line_text = line.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ");
+        if let Some((left, rect_top, rect_width, rect_height)) = subtitle_rect {
+            let cx = (x + w / 2) as i32;
+            let cy = (center_y - top) as i32;
+            if cx >= left && cx < left + rect_width && cy >= rect_top && cy < rect_top + rect_height {
+                subtitle.push(line_text.clone());
+            }
+        }
+        words.extend(line.iter().map(|word| OcrWord {
+            y: word.y.saturating_sub(top),
+            ..word.clone()
+        }));
+        text.push(line_text);
+    }
+    
+    OcrOutput {
+        text: text.join("\n"),
+        words: options.word_boxes.then_some(words),
+        subtitle: subtitle_rect.map(|_| subtitle.join("\n")),
+    }
+}
+
+/// Bounding box (x, y, width, height) around a line's words
+fn line_bounds(line: &[OcrWord]) -> Option<(u32, u32, u32, u32)> {
+    let left = line.iter().map(|w| w.x).min()?;
+    let top = line.iter().map(|w| w.y).min()?;
+    let right = line.iter().map(|w| w.x + w.width).max()?;
+    let bottom = line.iter().map(|w| w.y + w.height).max()?;
+    Some((left, top, right - left, bottom - top))
+}
+
+/// Words of Tesseract's hOCR output grouped by text line. Every line-like
+/// span (ocr_line, ocr_caption, ocr_header, ocr_textfloat) has a `line_` id.
+fn parse_hocr_lines(hocr: &str) -> Vec<Vec<OcrWord>> {
+    hocr.split("id='line_")
+        .skip(1)
+        .map(parse_hocr_words)
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+/// Pull `ocrx_word` spans out of Tesseract's hOCR output.
+///
+/// Each looks like:
+/// `<span class='ocrx_word' id='word_1_1' title='bbox 36 92 96 116; x_wconf 93'>Flour</span>`
+fn parse_hocr_words(hocr: &str) -> Vec<OcrWord> {
+    let mut words = Vec::new();
+    
+    for chunk in hocr.split("<span class='ocrx_word'").skip(1) {
+        let title = match chunk
+            .split_once("title='")
+            .and_then(|(_, rest)| rest.split_once('\''))
+        {
+            Some((title, _)) => title,
+            None => continue,
+        };
+        
+        let mut bbox = None;
+        let mut confidence = 0.0;
+        for prop in title.split(';').map(str::trim) {
+            if let Some(coords) = prop.strip_prefix("bbox ") {
+                let c: Vec<u32> = coords.split_whitespace().filter_map(|n| n.parse().ok()).collect();
+                if c.len() == 4 {
+                    bbox = Some((c[0], c[1], c[2].saturating_sub(c[0]), c[3].saturating_sub(c[1])));
+                }
+            } else if let Some(conf) = prop.strip_prefix("x_wconf ") {
+                confidence = conf.trim().parse().unwrap_or(0.0);
+            }
+        }
+        
+        // Word text is the span body with any inline <strong>/<em> tags removed
+        let body = chunk
+            .split_once('>')
+            .and_then(|(_, rest)| rest.split_once("</span>"))
+            .map(|(body, _)| body)
+            .unwrap_or("");
+        let text = decode_entities(&strip_tags(body));
+        
+        let text = text.trim();
+        if let Some((x, y, width, height)) = bbox.filter(|_| !text.is_empty()) {
+            words.push(OcrWord {
+                text: text.to_string(),
+                x,
+                y,
+                width,
+                height,
+                confidence,
+            });
+        }
+    }
+    
+    words
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn composite_lines_map_back_to_their_frames() {
+        let hocr = "\
+<span class='ocr_line' id='line_1_1' title=\"bbox 10 20 200 44\">\
+<span class='ocrx_word' id='word_1_1' title='bbox 10 20 90 44; x_wconf 91'>2</span> \
+<span class='ocrx_word' id='word_1_2' title='bbox 100 20 200 44; x_wconf 88'>eggs</span></span>\
+<span class='ocr_caption' id='line_1_2' title=\"bbox 40 300 260 330\">\
+<span class='ocrx_word' id='word_1_3' title='bbox 40 300 260 330; x_wconf 90'>stir</span></span>\
+<span class='ocr_line' id='line_1_3' title=\"bbox 30 420 230 450\">\
+<span class='ocrx_word' id='word_1_4' title='bbox 30 420 230 450; x_wconf 85'>bake</span></span>";
+        let lines = parse_hocr_lines(hocr);
+        assert_eq!(lines.len(), 3);
+        
+        let options = OcrOptions {
+            word_boxes: true,
+            subtitle_region: Some(Region::BOTTOM_THIRD),
+            ..OcrOptions::default()
+        };
+        // Two 360px frames stacked with the gap between them
+        let first = frame_output(&lines, 0, 640, 360, &options);
+        let second = frame_output(&lines, 360 + COMPOSITE_GAP, 640, 360, &options);
+        
+        assert_eq!(first.text, "2 eggs\nstir");
+        assert_eq!(first.subtitle.as_deref(), Some("stir"));
+        assert_eq!(first.words.as_ref().map(Vec::len), Some(3));
+        
+        assert_eq!(second.text, "bake");
+        assert_eq!(second.subtitle.as_deref(), Some(""));
+        let bake = &second.words.unwrap()[0];
+        assert_eq!((bake.x, bake.y), (30, 420 - 360 - COMPOSITE_GAP));
+    }
+    
+    fn word(text: &str, y: u32) -> serde_json::Value {
+        serde_json::json!({ "text": text, "x": 0, "y": y, "width": 50, "height": 20, "confidence": 90.0 })
+    }
+    
+    fn ocr_frame(text: Option<&str>, boxes: Vec<serde_json::Value>) -> FrameData {
+        serde_json::from_value(serde_json::json!({
+            "timestamp": 0.0,
+            "frame_path": "frame.jpg",
+            "is_keyframe": false,
+            "ocr_text": text,
+            "ocr_boxes": boxes,
+        }))
+        .unwrap()
+    }
+    
+    #[test]
+    fn watermark_lines_are_stripped_with_only_their_boxes() {
+        let mut frames = vec![
+            ocr_frame(
+                Some("@chef.anna\nfollow @chef.anna for more"),
+                vec![
+                    word("@chef.anna", 0),
+                    word("follow", 100),
+                    word("@chef.anna", 100),
+                    word("for", 100),
+                    word("more", 100),
+                ],
+            ),
+            ocr_frame(Some("@chef_anna\n2 eggs"), vec![word("@chef_anna", 0), word("2", 50), word("eggs", 50)]),
+            ocr_frame(Some("@chefanna\n2 eggs"), vec![word("@chefanna", 0), word("2", 50), word("eggs", 50)]),
+            ocr_frame(Some("@chef.anna\nbake"), vec![word("@chef.anna", 0), word("bake", 50)]),
+            ocr_frame(Some("@chef.anna"), vec![word("@chef.anna", 0)]),
+            ocr_frame(None, Vec::new()),
+        ];
+        
+        assert_eq!(strip_watermarks(&mut frames, 6, 0.8), ["@chefanna"]);
+        
+        assert_eq!(frames[0].ocr_text.as_deref(), Some("follow @chef.anna for more"));
+        let boxes = frames[0].ocr_boxes.as_ref().unwrap();
+        assert_eq!(boxes.len(), 4);
+        // The handle inside the kept sentence keeps its box
+        assert!(boxes.iter().all(|b| b.y == 100));
+        assert_eq!(frames[1].ocr_text.as_deref(), Some("2 eggs"));
+        // "2 eggs" is on two of six frames, well under the threshold
+        assert_eq!(frames[2].ocr_boxes.as_ref().map(Vec::len), Some(2));
+        assert!(frames[4].ocr_text.is_none() && frames[4].ocr_boxes.is_none());
+    }
+    
+    #[test]
+    fn watermarks_need_enough_frames_and_a_threshold() {
+        let mut frames: Vec<FrameData> = (0..3).map(|_| ocr_frame(Some("@chef.anna"), Vec::new())).collect();
+        assert!(strip_watermarks(&mut frames, 3, 0.8).is_empty());
+        assert_eq!(frames[0].ocr_text.as_deref(), Some("@chef.anna"));
+        
+        // Opt-in: nothing is stripped unless a threshold is configured
+        assert_eq!(OcrOptions::default().watermark_threshold, None);
+    }
+    
+    fn timed_frame(timestamp: f64, text: Option<&str>) -> FrameData {
+        FrameData { timestamp, ..ocr_frame(text, Vec::new()) }
+    }
+    
+    #[test]
+    fn repeated_text_collapses_into_the_first_frame() {
+        let mut frames = vec![
+            timed_frame(0.0, Some("Preheat oven to 200C")),
+            timed_frame(1.0, Some("preheat  oven to 200c")),
+            timed_frame(2.0, Some("Preheat oven to 200C")),
+            timed_frame(3.0, Some("Grease the tin")),
+        ];
+        assert_eq!(collapse_repeated_text(&mut frames, &[0, 1, 2, 3]), 2);
+        assert_eq!(frames[0].ocr_text_until, Some(2.0));
+        assert_eq!(frames[1].ocr_text, None);
+        assert_eq!(frames[2].ocr_text, None);
+        assert_eq!(frames[3].ocr_text.as_deref(), Some("Grease the tin"));
+        assert_eq!(frames[3].ocr_text_until, None);
+    }
+    
+    #[test]
+    fn a_frame_without_text_ends_the_run() {
+        let mut frames = vec![
+            timed_frame(0.0, Some("Add the flour")),
+            timed_frame(1.0, None),
+            timed_frame(2.0, Some("Add the flour")),
+            timed_frame(3.0, Some("Add the flour")),
+        ];
+        assert_eq!(collapse_repeated_text(&mut frames, &[0, 1, 2, 3]), 1);
+        assert_eq!(frames[0].ocr_text_until, None);
+        assert_eq!(frames[2].ocr_text.as_deref(), Some("Add the flour"));
+        assert_eq!(frames[2].ocr_text_until, Some(3.0));
+        assert_eq!(frames[3].ocr_text, None);
+    }
+    
+    #[test]
+    fn frames_skipped_by_keyframes_only_do_not_end_the_run() {
+        let keyframe = |timestamp: f64, text: &str| FrameData { is_keyframe: true, ..timed_frame(timestamp, Some(text)) };
+        let mut frames = vec![
+            keyframe(0.4, "Whisk the eggs"),
+            timed_frame(2.0, None),
+            keyframe(2.7, "whisk the eggs"),
+            timed_frame(4.0, None),
+            keyframe(5.1, "Fold in the sugar"),
+        ];
+        let selected = select_frames(&frames, true);
+        assert_eq!(selected, [0, 2, 4]);
+        
+        assert_eq!(collapse_repeated_text(&mut frames, &selected), 1);
+        assert_eq!(frames[0].ocr_text_until, Some(2.7));
+        assert_eq!(frames[2].ocr_text, None);
+        assert_eq!(frames[4].ocr_text.as_deref(), Some("Fold in the sugar"));
+    }
+    
+    #[test]
+    fn single_line_modes_are_not_batched() {
+        assert!(supports_batching(DEFAULT_PSM));
+        assert!(supports_batching(11));
+        assert!(!supports_batching(7));
+        assert!(!supports_batching(13));
+    }
+}
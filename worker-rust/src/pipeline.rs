@@ -0,0 +1,912 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument, warn};
+
+use crate::assemble;
+use crate::audio::{self, AudioTrack, Transcript, TranscriptSegment};
+use crate::config::Config;
+use crate::download::{self, DownloadedVideo, ImagePost, Section};
+use crate::error::{self, WorkerError};
+use crate::manifest;
+use crate::ocr::{self, OcrTiming};
+use crate::result::{MediaType, ProbeResult, ProcessResult, StageOutcome};
+use crate::stage::{Stage, StageMask};
+#[cfg(feature = "s3")]
+use crate::storage;
+use crate::video::{self, CropRect, FrameData, VideoInfo};
+
+/// Run `fut` unless `cancel` fires first, in which case it fails with
+/// `WorkerError::Cancelled`. Child processes are spawned with kill_on_drop,
+/// so dropping the in-flight future stops any running ffmpeg/yt-dlp/whisper.
+pub async fn cancellable<T, E>(
+    cancel: &CancellationToken,
+    fut: impl Future<Output = std::result::Result<T, E>>,
+) -> std::result::Result<T, E>
+where
+    E: From<WorkerError>,
+{
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(WorkerError::Cancelled.into()),
+        result = fut => result,
+    }
+}
+
+/// Run the full pipeline for one URL and write `{job_id}/result.json`
+pub async fn process_single_video(
+    url: &str,
+    output_dir: &str,
+    job_id: &str,
+    config: &Config,
+    stages: StageMask,
+    section: Option<Section>,
+    cancel: &CancellationToken,
+) -> Result<ProcessResult> {
+    cancellable(cancel, run_pipeline(url, output_dir, job_id, config, stages, section, &ignore_events)).await
+}
+
+/// Like process_single_video, but also writes each event to stdout as one
+/// JSON line as it happens, ending with a `done` marker
+pub async fn stream_single_video(
+    url: &str,
+    output_dir: &str,
+    job_id: &str,
+    config: &Config,
+    stages: StageMask,
+    section: Option<Section>,
+    cancel: &CancellationToken,
+) -> Result<ProcessResult> {
+    let result =
+        cancellable(cancel, run_pipeline(url, output_dir, job_id, config, stages, section, &print_event)).await?;
+    print_event(Event::Done {
+        job_id,
+        result_path: &result_path(output_dir, job_id),
+    });
+    Ok(result)
+}
+
+#[instrument(skip(output_dir, config, events))]
+async fn run_pipeline(
+    url: &str,
+    output_dir: &str,
+    job_id: &str,
+    config: &Config,
+    stages: StageMask,
+    section: Option<Section>,
+    events: Events<'_>,
+) -> Result<ProcessResult> {
+    std::fs::create_dir_all(output_dir)?;
+    
+    let ctx = JobContext {
+        job_id,
+        output_dir,
+        config,
+        stages,
+        progress: &log_progress,
+        events,
+    };
+    #[cfg_attr(not(feature = "s3"), allow(unused_mut))]
+    let mut result = match process(&ctx, url, section).await {
+        Ok(result) => result,
+        Err(e) => {
+            // Nothing retries a CLI run, so partial downloads are dead weight
+            download::remove_partial_downloads(&job_dir(output_dir, job_id));
+            return Err(e.into());
+        }
+    };
+    
+    #[cfg(feature = "s3")]
+    let store = storage::ObjectStore::from_env()?;
+    #[cfg(feature = "s3")]
+    if let Some(store) = &store {
+        store.upload_assets(&mut result).await;
+    }
+    
+    let result_path = result_path(output_dir, job_id);
+    write_atomic(&result_path, serde_json::to_string_pretty(&result)?.as_bytes())?;
+    
+    #[cfg(feature = "s3")]
+    if let Some(store) = &store {
+        let url = store.upload_file(job_id, &result_path, "application/json").await?;
+        info!("Job {}: Uploaded results to {}", job_id, url);
+    }
+    
+    if manifest::manifest_enabled() {
+        let manifest_path = manifest::write_manifest(&job_dir(output_dir, job_id), job_id).await?;
+        info!("Job {}: Manifest saved to {:?}", job_id, manifest_path);
+        
+        #[cfg(feature = "s3")]
+        if let Some(store) = &store {
+            store.upload_file(job_id, &manifest_path, "application/json").await?;
+        }
+    }
+    
+    info!("Job {}: Complete! Results saved to {:?}", job_id, result_path);
+    
+    Ok(result)
+}
+
+/// Called as the pipeline enters each stage, with the stage's progress percentage
+pub type Progress<'a> = &'a (dyn Fn(Stage, u8) + Send + Sync);
+
+/// Progress sink that just logs, for the CLI
+pub fn log_progress(stage: Stage, percent: u8) {
+    info!("Stage {} ({}%)", stage.name(), percent);
+}
+
+/// A piece of the result, emitted as soon as it is known
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    VideoInfo { video_info: &'a VideoInfo },
+    Frame { frame: &'a FrameData },
+    TranscriptSegment { segment: &'a TranscriptSegment },
+    /// Last event of a successful run
+    Done { job_id: &'a str, result_path: &'a Path },
+}
+
+/// Called with each Event as the pipeline produces it
+pub type Events<'a> = &'a (dyn Fn(Event<'_>) + Send + Sync);
+
+/// Event sink for callers that only want the final result
+pub fn ignore_events(_: Event<'_>) {}
+
+/// What every stage of one job shares: where it writes, how it is
+/// configured, which stages run, and where progress and events go
+#[derive(Clone, Copy)]
+pub struct JobContext<'a> {
+    pub job_id: &'a str,
+    /// Parent of the job directory
+    pub output_dir: &'a str,
+    pub config: &'a Config,
+    pub stages: StageMask,
+    pub progress: Progress<'a>,
+    pub events: Events<'a>,
+}
+
+impl JobContext<'_> {
+    /// Directory holding everything produced for this job
+    pub fn dir(&self) -> PathBuf {
+        job_dir(self.output_dir, self.job_id)
+    }
+}
+
+/// Event sink writing NDJSON to stdout
+pub fn print_event(event: Event<'_>) {
+    match serde_json::to_string(&event) {
+        Ok(line) => {
+            let mut stdout = std::io::stdout().lock();
+            // A closed pipe just means nobody is listening any more
+            let _ = writeln!(stdout, "{}", line).and_then(|_| stdout.flush());
+        }
+        Err(e) => warn!("Failed to serialize event: {}", e),
+    }
+}
+
+/// Run every stage for one URL and build the result, without persisting it.
+///
+/// This is the library entry point; callers decide where progress, events,
+/// and the result go. With a `section`, only that range is downloaded and
+/// every timestamp in the result is relative to its start.
+pub async fn process(ctx: &JobContext<'_>, url: &str, section: Option<Section>) -> error::Result<ProcessResult> {
+    let JobContext { job_id, output_dir, stages, progress, events, .. } = *ctx;
+    let (video, video_info) = fetch(url, output_dir, job_id, stages, section, progress, events).await?;
+    Ok(analyze(ctx, &video, video_info).await)
+}
+
+/// Download the video (or reuse an earlier download when `stages` skips it)
+/// and read its metadata. Errors here fail the job.
+pub async fn fetch(
+    url: &str,
+    output_dir: &str,
+    job_id: &str,
+    stages: StageMask,
+    section: Option<Section>,
+    progress: Progress<'_>,
+    events: Events<'_>,
+) -> error::Result<(DownloadedVideo, VideoInfo)> {
+    let dir = job_dir(output_dir, job_id);
+    std::fs::create_dir_all(&dir)?;
+    let dir = dir.to_string_lossy();
+    
+    report(progress, Stage::Downloading);
+    let video = if stages.download {
+        download::download_video(url, &dir, job_id, section).await?
+    } else {
+        earlier_download(&dir)?
+    };
+    
+    report(progress, Stage::Probing);
+    let video_info = match &video.image_post {
+        Some(post) => image_post_info(post)?,
+        None => video::process_video(&video.path, job_id).await?,
+    };
+    events(Event::VideoInfo { video_info: &video_info });
+    
+    Ok((video, video_info))
+}
+
+/// Frames, thumbnail, OCR, audio, and transcription for a fetched video.
+/// An image post's images are OCR'd as its frames and its caption becomes
+/// the transcript.
+///
+/// The frames/OCR and audio/transcription branches only share the
+/// downloaded file, so they run concurrently. Failures in either are logged
+/// and leave the matching part of the result empty, so a video without
+/// audio or legible text still completes. Stages masked off in `stages`
+/// reuse the artifacts and `result.json` an earlier run left in the job
+/// directory.
+pub async fn analyze(ctx: &JobContext<'_>, video: &DownloadedVideo, video_info: VideoInfo) -> ProcessResult {
+    let JobContext { job_id, stages, progress, .. } = *ctx;
+    let video_path = video.path.as_str();
+    let dir = ctx.dir();
+    let dir = dir.to_string_lossy();
+    let previous = if stages.is_all() { None } else { previous_result(&dir) };
+    
+    // Both branches report progress; only pass on forward moves so the job
+    // doesn't flip back and forth between their stages
+    let reached = AtomicU8::new(0);
+    let forward = |stage: Stage, percent: u8| {
+        if reached.fetch_max(percent, Ordering::Relaxed) < percent {
+            progress(stage, percent);
+        }
+    };
+    
+    let branch = JobContext { progress: &forward, ..*ctx };
+    let (visual, speech) = match &video.image_post {
+        Some(post) => (
+            image_branch(&branch, post, previous.as_ref()).await,
+            caption_branch(&branch, post, &video_info),
+        ),
+        None => tokio::join!(
+            visual_branch(&branch, video_path, &video_info, previous.as_ref()),
+            speech_branch(&branch, video_path, &video_info),
+        ),
+    };
+    
+    let segments = assemble::SegmentOptions::from_env().map(|options| {
+        assemble::detect_segments(
+            &visual.frames,
+            &speech.transcript.segments,
+            video_info.duration_seconds,
+            &options,
+        )
+    });
+    let steps = assemble::build_steps(&visual.frames, &speech.transcript.segments, &assemble::StepOptions::from_env());
+    
+    let mut outcomes = visual.stages;
+    outcomes.extend(speech.stages);
+    
+    // An image post has no single file to point at; its frames are the images
+    let (media_type, media_path) = match &video.image_post {
+        Some(_) => (MediaType::Images, ""),
+        None => (MediaType::Video, video_path),
+    };
+    let mut result = ProcessResult::new(
+        job_id,
+        media_path,
+        video_info,
+        visual.frames,
+        speech.audio_path,
+        speech.transcript,
+    );
+    result.media_type = media_type;
+    result.thumbnail_path = visual.thumbnail_path;
+    result.preview_path = visual.preview_path;
+    result.crop = visual.crop;
+    result.audio_track = speech.audio_track;
+    result.sponsorblock_trimmed = match (&previous, stages.download) {
+        (Some(previous), false) => previous.sponsorblock_trimmed,
+        _ => video.sponsorblock_trimmed,
+    };
+    result.video_integrity = match (&previous, stages.download) {
+        (Some(previous), false) => previous.video_integrity.clone(),
+        _ => video.integrity.clone(),
+    };
+    result.section = match (&previous, stages.download) {
+        (Some(previous), false) => previous.section,
+        _ => video.section,
+    };
+    result.ocr_timing = visual.ocr_timing;
+    result.stages = outcomes;
+    result.vtt_path = speech.vtt_path;
+    if let Some(segments) = segments {
+        info!("Job {}: Proposed {} recipe segment(s)", job_id, segments.len());
+        result.segments = segments;
+    }
+    result.steps = steps;
+    
+    result
+}
+
+/// Output of analyze's frames/thumbnail/OCR branch
+struct Visual {
+    crop: Option<CropRect>,
+    frames: Vec<FrameData>,
+    thumbnail_path: Option<String>,
+    preview_path: Option<String>,
+    ocr_timing: Option<OcrTiming>,
+    stages: BTreeMap<String, StageOutcome>,
+}
+
+/// Output of analyze's audio/transcription branch
+struct Speech {
+    audio_track: Option<AudioTrack>,
+    audio_path: Option<String>,
+    transcript: Transcript,
+    vtt_path: Option<String>,
+    stages: BTreeMap<String, StageOutcome>,
+}
+
+async fn visual_branch(
+    ctx: &JobContext<'_>,
+    video_path: &str,
+    video_info: &VideoInfo,
+    previous: Option<&ProcessResult>,
+) -> Visual {
+    let JobContext { job_id, config, stages: mask, progress, .. } = *ctx;
+    let dir = ctx.dir();
+    let dir = dir.to_string_lossy();
+    let mut stages = BTreeMap::new();
+    
+    report(progress, Stage::Frames);
+    let crop = if !mask.frames {
+        previous.and_then(|p| p.crop)
+    } else if video::crop_borders_enabled() {
+        match video::detect_crop(video_path, video_info).await {
+            Ok(crop) => crop,
+            Err(e) => {
+                warn!("Job {}: Crop detection failed: {}", job_id, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    
+    let encoding = video::FrameEncoding::from_env();
+    let extracted = if !mask.frames {
+        video::load_frames(&dir)
+    } else if video::fast_frames_enabled() {
+        video::extract_frames_fast(
+            video_path,
+            &dir,
+            job_id,
+            video_info.duration_seconds,
+            crop.as_ref(),
+            config.max_frames,
+            &encoding,
+        )
+        .await
+    } else {
+        video::extract_keyframes(
+            video_path,
+            &dir,
+            job_id,
+            video_info.duration_seconds,
+            crop.as_ref(),
+            config.max_frames,
+            &encoding,
+        )
+        .await
+    };
+    let frames = match extracted {
+        Ok(mut frames) => {
+            video::score_frames(&mut frames).await;
+            let outcome = if mask.frames { StageOutcome::Succeeded } else { StageOutcome::skipped(REUSED) };
+            stages.insert(Stage::Frames.name().to_string(), outcome);
+            frames
+        }
+        Err(e) => {
+            warn!("Job {}: Frame extraction failed: {}", job_id, e);
+            stages.insert(Stage::Frames.name().to_string(), StageOutcome::failed(&e));
+            Vec::new()
+        }
+    };
+    
+    let thumbnail_path = match video::extract_thumbnail(
+        video_path,
+        &dir,
+        job_id,
+        video_info,
+        &frames,
+        video::ThumbnailStrategy::from_env(),
+        crop.as_ref(),
+    )
+    .await
+    {
+        Ok(path) => {
+            stages.insert("thumbnail".to_string(), StageOutcome::Succeeded);
+            Some(path)
+        }
+        Err(e) => {
+            warn!("Job {}: Thumbnail extraction failed: {}", job_id, e);
+            stages.insert("thumbnail".to_string(), StageOutcome::failed(&e));
+            None
+        }
+    };
+    
+    let preview_path = match video::PreviewOptions::from_env() {
+        Some(options) => {
+            match video::extract_preview(video_path, &dir, job_id, video_info, &frames, options, crop.as_ref()).await {
+                Ok(path) => {
+                    stages.insert("preview".to_string(), StageOutcome::Succeeded);
+                    Some(path)
+                }
+                Err(e) => {
+                    warn!("Job {}: Preview generation failed: {}", job_id, e);
+                    stages.insert("preview".to_string(), StageOutcome::failed(&e));
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    
+    let (frames, ocr_timing) = ocr_stage(ctx, frames, previous, &mut stages).await;
+    
+    Visual {
+        crop,
+        frames,
+        thumbnail_path,
+        preview_path,
+        ocr_timing,
+        stages,
+    }
+}
+
+/// OCR `frames`, or with OCR masked off, carry text over from the earlier
+/// run's frames, recording the stage outcome in `stages`
+async fn ocr_stage(
+    ctx: &JobContext<'_>,
+    frames: Vec<FrameData>,
+    previous: Option<&ProcessResult>,
+    stages: &mut BTreeMap<String, StageOutcome>,
+) -> (Vec<FrameData>, Option<OcrTiming>) {
+    let JobContext { job_id, stages: mask, progress, events, .. } = *ctx;
+    report(progress, Stage::Ocr);
+    let ocr_outcome = if !mask.ocr {
+        StageOutcome::skipped(REUSED)
+    } else if frames.is_empty() {
+        StageOutcome::skipped("no frames to read")
+    } else if !ocr::tesseract_available().await {
+        StageOutcome::skipped("tesseract unavailable")
+    } else {
+        StageOutcome::Succeeded
+    };
+    let ocr_run = if mask.ocr {
+        ocr::process_frames(frames, ocr::OcrOptions::from_env()).await
+    } else {
+        let mut frames = frames;
+        if let Some(previous) = previous {
+            carry_ocr(&mut frames, &previous.frames);
+        }
+        Ok((frames, None))
+    };
+    let (frames, ocr_timing) = match ocr_run {
+        Ok(processed) => {
+            stages.insert(Stage::Ocr.name().to_string(), ocr_outcome);
+            processed
+        }
+        Err(e) => {
+            warn!("Job {}: OCR failed: {}", job_id, e);
+            stages.insert(Stage::Ocr.name().to_string(), StageOutcome::failed(&e));
+            (Vec::new(), None)
+        }
+    };
+    for frame in &frames {
+        events(Event::Frame { frame });
+    }
+    
+    (frames, ocr_timing)
+}
+
+/// VideoInfo for an image post, sized by its first image and as long as a
+/// slideshow of it would be
+fn image_post_info(post: &ImagePost) -> error::Result<VideoInfo> {
+    let first = post
+        .images
+        .first()
+        .ok_or_else(|| WorkerError::Probe("image post has no images".to_string()))?;
+    let (width, height) = image::image_dimensions(first)
+        .map_err(|e| WorkerError::Probe(format!("{}: {}", first, e)))?;
+    
+    Ok(VideoInfo {
+        duration_seconds: (post.images.len() as u32 * download::SLIDE_SECS) as f64,
+        width,
+        height,
+        fps: 0.0,
+        codec: "image".to_string(),
+        pix_fmt: "unknown".to_string(),
+        variable_frame_rate: false,
+        container: None,
+        bit_rate: None,
+        audio: None,
+        audio_streams: Vec::new(),
+        chapters: Vec::new(),
+    })
+}
+
+/// Visual branch for an image post: every image is a keyframe, spaced
+/// SLIDE_SECS apart, and the first image is the thumbnail
+async fn image_branch(ctx: &JobContext<'_>, post: &ImagePost, previous: Option<&ProcessResult>) -> Visual {
+    let job_id = ctx.job_id;
+    let mut stages = BTreeMap::new();
+    
+    report(ctx.progress, Stage::Frames);
+    let mut frames: Vec<FrameData> = post
+        .images
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let timestamp = (i as u32 * download::SLIDE_SECS) as f64;
+            FrameData {
+                timestamp,
+                frame_path: path.clone(),
+                thumb_path: None,
+                ocr_text: None,
+                ocr_text_until: None,
+                ocr_boxes: None,
+                lang: None,
+                subtitle_text: None,
+                sharpness: None,
+                brightness: None,
+                is_keyframe: true,
+            }
+        })
+        .collect();
+    video::score_frames(&mut frames).await;
+    stages.insert(Stage::Frames.name().to_string(), StageOutcome::Succeeded);
+    info!("Job {}: Using {} images of an image post as frames", job_id, frames.len());
+    
+    let thumbnail_path = post.images.first().cloned();
+    let (frames, ocr_timing) = ocr_stage(ctx, frames, previous, &mut stages).await;
+    
+    Visual {
+        crop: None,
+        frames,
+        thumbnail_path,
+        preview_path: None,
+        ocr_timing,
+        stages,
+    }
+}
+
+/// Speech branch for an image post: there is no audio, so the caption
+/// stands in for the transcript as one segment spanning every image
+fn caption_branch(ctx: &JobContext<'_>, post: &ImagePost, video_info: &VideoInfo) -> Speech {
+    let JobContext { progress, events, .. } = *ctx;
+    let mut stages = BTreeMap::new();
+    
+    report(progress, Stage::Audio);
+    stages.insert(Stage::Audio.name().to_string(), StageOutcome::skipped("image post"));
+    
+    report(progress, Stage::Transcribe);
+    let caption = post.caption.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    let transcript = match caption {
+        Some(caption) => {
+            stages.insert(Stage::Transcribe.name().to_string(), StageOutcome::Succeeded);
+            Transcript {
+                text: caption.to_string(),
+                segments: vec![TranscriptSegment {
+                    start: 0.0,
+                    end: video_info.duration_seconds,
+                    text: caption.to_string(),
+                    no_speech_prob: None,
+                    avg_logprob: None,
+                }],
+                filtered_segments: 0,
+            }
+        }
+        None => {
+            stages.insert(Stage::Transcribe.name().to_string(), StageOutcome::skipped("no caption"));
+            Transcript::default()
+        }
+    };
+    for segment in &transcript.segments {
+        events(Event::TranscriptSegment { segment });
+    }
+    
+    Speech {
+        audio_track: None,
+        audio_path: None,
+        transcript,
+        vtt_path: None,
+        stages,
+    }
+}
+
+async fn speech_branch(ctx: &JobContext<'_>, video_path: &str, video_info: &VideoInfo) -> Speech {
+    let JobContext { job_id, stages: mask, progress, events, .. } = *ctx;
+    let dir = ctx.dir();
+    let dir = dir.to_string_lossy();
+    let mut stages = BTreeMap::new();
+    
+    report(progress, Stage::Audio);
+    let audio_track = audio::select_track(video_info, &audio::TrackSelector::from_env());
+    let has_audio = audio_track.is_some();
+    let audio_path = if !mask.audio {
+        let path = Path::new(&*dir).join("audio.wav");
+        stages.insert(Stage::Audio.name().to_string(), StageOutcome::skipped(REUSED));
+        path.exists().then(|| path.to_string_lossy().to_string())
+    } else if !has_audio {
+        info!("Job {}: No audio stream, skipping audio", job_id);
+        stages.insert(Stage::Audio.name().to_string(), StageOutcome::skipped("no audio stream"));
+        None
+    } else if video_info.duration_seconds < audio::MIN_AUDIO_SECS {
+        info!(
+            "Job {}: {:.2}s clip is too short to transcribe, skipping audio",
+            job_id, video_info.duration_seconds
+        );
+        stages.insert(Stage::Audio.name().to_string(), StageOutcome::skipped("clip too short"));
+        None
+    } else {
+        match audio::extract_audio(
+            video_path,
+            &dir,
+            job_id,
+            audio_track.as_ref(),
+            audio::AudioNormalization::from_env(),
+        )
+        .await
+        {
+            Ok(path) => {
+                stages.insert(Stage::Audio.name().to_string(), StageOutcome::Succeeded);
+                Some(path)
+            }
+            Err(e) => {
+                warn!("Job {}: Audio extraction failed: {}", job_id, e);
+                stages.insert(Stage::Audio.name().to_string(), StageOutcome::failed(&e));
+                None
+            }
+        }
+    };
+    
+    report(progress, Stage::Transcribe);
+    let (transcript, transcribe_outcome) = match &audio_path {
+        Some(path) if !mask.transcribe => match audio::load_transcript(path) {
+            Ok(transcript) => (transcript.unwrap_or_default(), StageOutcome::skipped(REUSED)),
+            Err(e) => {
+                warn!("Job {}: Failed to load earlier transcript: {}", job_id, e);
+                (Transcript::default(), StageOutcome::failed(&e))
+            }
+        },
+        Some(path) => match audio::transcribe_audio(path).await {
+            Ok(transcript) => (transcript, StageOutcome::Succeeded),
+            Err(e) => {
+                warn!("Job {}: Transcription failed: {}", job_id, e);
+                (Transcript::default(), StageOutcome::failed(&e))
+            }
+        },
+        None => (Transcript::default(), StageOutcome::skipped("no audio")),
+    };
+    stages.insert(Stage::Transcribe.name().to_string(), transcribe_outcome);
+    for segment in &transcript.segments {
+        events(Event::TranscriptSegment { segment });
+    }
+    
+    let vtt_path = if transcript.segments.is_empty() {
+        None
+    } else {
+        let path = Path::new(&*dir).join("transcript.vtt");
+        match write_atomic(&path, transcript.to_webvtt().as_bytes()) {
+            Ok(()) => Some(path.to_string_lossy().to_string()),
+            Err(e) => {
+                warn!("Job {}: Failed to write WebVTT captions: {}", job_id, e);
+                None
+            }
+        }
+    };
+    
+    Speech {
+        audio_track,
+        audio_path,
+        transcript,
+        vtt_path,
+        stages,
+    }
+}
+
+/// Stage outcome reason for work taken from an earlier run
+const REUSED: &str = "reused earlier output";
+
+/// `result.json` from an earlier run of this job, if readable
+fn previous_result(job_dir: &str) -> Option<ProcessResult> {
+    let path = Path::new(job_dir).join("result.json");
+    let raw = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&raw) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            warn!("Ignoring unreadable earlier result {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// The download an earlier run of this job left, in the job directory or
+/// wherever its result says it went (the CACHE_DIR cache)
+fn earlier_download(job_dir: &str) -> error::Result<DownloadedVideo> {
+    let recorded = previous_result(job_dir).map(|result| result.video_path);
+    download::existing_video(job_dir, recorded.as_deref())
+}
+
+/// Frames of two runs closer together than this are the same frame
+const SAME_FRAME_SECS: f64 = 0.001;
+
+/// Copy OCR fields onto `frames` from the earlier run's frame at the same
+/// timestamp (earlier paths may since have been rewritten to object URLs)
+fn carry_ocr(frames: &mut [FrameData], previous: &[FrameData]) {
+    for frame in frames.iter_mut() {
+        let earlier = previous.iter().find(|f| (f.timestamp - frame.timestamp).abs() < SAME_FRAME_SECS);
+        if let Some(earlier) = earlier {
+            frame.ocr_text = earlier.ocr_text.clone();
+            frame.ocr_text_until = earlier.ocr_text_until;
+            frame.ocr_boxes = earlier.ocr_boxes.clone();
+            frame.lang = earlier.lang.clone();
+            frame.subtitle_text = earlier.subtitle_text.clone();
+        }
+    }
+}
+
+fn report(progress: Progress<'_>, stage: Stage) {
+    progress(stage, stage.progress());
+}
+
+/// Metadata for `url` without frames, OCR, or audio.
+///
+/// With STREAM_PROBE on (the default), ffprobe reads the remote stream that
+/// `yt-dlp -g` resolves, so nothing is downloaded. Platforms that can't give
+/// a single direct URL fall back to a full download.
+pub async fn probe(
+    url: &str,
+    output_dir: &str,
+    job_id: &str,
+    progress: Progress<'_>,
+) -> error::Result<ProbeResult> {
+    if download::stream_probe_enabled() {
+        report(progress, Stage::Probing);
+        match probe_stream(url, job_id).await {
+            Ok(result) => return Ok(result),
+            Err(e) => warn!("Job {}: Streaming probe failed, downloading instead: {}", job_id, e),
+        }
+    }
+    
+    let (video, video_info) = fetch(url, output_dir, job_id, StageMask::ALL, None, progress, &ignore_events).await?;
+    Ok(ProbeResult::new(job_id, &video.path, video_info))
+}
+
+async fn probe_stream(url: &str, job_id: &str) -> error::Result<ProbeResult> {
+    let stream = download::stream_url(url).await?;
+    let video_info = video::process_video(&stream, job_id).await?;
+    
+    info!("Job {}: Probed remote stream without downloading", job_id);
+    let mut result = ProbeResult::new(job_id, url, video_info);
+    result.streamed = true;
+    Ok(result)
+}
+
+/// Probe only, writing `{job_id}/result.json` with the VideoInfo
+pub async fn probe_single_video(
+    url: &str,
+    output_dir: &str,
+    job_id: &str,
+    cancel: &CancellationToken,
+) -> Result<ProbeResult> {
+    cancellable(cancel, run_probe(url, output_dir, job_id)).await
+}
+
+#[instrument(skip(output_dir))]
+async fn run_probe(url: &str, output_dir: &str, job_id: &str) -> Result<ProbeResult> {
+    std::fs::create_dir_all(output_dir)?;
+    
+    let result = match probe(url, output_dir, job_id, &log_progress).await {
+        Ok(result) => result,
+        Err(e) => {
+            download::remove_partial_downloads(&job_dir(output_dir, job_id));
+            return Err(e.into());
+        }
+    };
+    
+    std::fs::create_dir_all(job_dir(output_dir, job_id))?;
+    let result_path = result_path(output_dir, job_id);
+    write_atomic(&result_path, serde_json::to_string_pretty(&result)?.as_bytes())?;
+    
+    info!("Job {}: Probe complete! Results saved to {:?}", job_id, result_path);
+    
+    Ok(result)
+}
+
+/// Directory holding everything produced for one job
+pub fn job_dir(output_dir: &str, job_id: &str) -> PathBuf {
+    Path::new(output_dir).join(job_id)
+}
+
+/// Where the pipeline writes the result JSON for a job
+pub fn result_path(output_dir: &str, job_id: &str) -> PathBuf {
+    job_dir(output_dir, job_id).join("result.json")
+}
+
+/// Write via a temp file in the same directory and rename it into place, so
+/// readers see either the old file or the complete new one, never a partial write
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(contents)?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|e| e.error)?;
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn frame(timestamp: f64, frame_path: &str, ocr_text: Option<&str>) -> FrameData {
+        serde_json::from_value(json!({
+            "timestamp": timestamp,
+            "frame_path": frame_path,
+            "ocr_text": ocr_text,
+            "is_keyframe": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn ocr_is_carried_over_by_timestamp() {
+        let previous = vec![
+            frame(1.0, "https://cdn.example.com/job/frame_0001.jpg", Some("2 cups flour")),
+            frame(2.5, "https://cdn.example.com/job/frame_0002.jpg", Some("1 egg")),
+        ];
+        let mut frames = vec![
+            frame(1.0004, "/data/job/frame_0001.jpg", None),
+            frame(2.0, "/data/job/frame_0002.jpg", None),
+            frame(2.5, "/data/job/frame_0003.jpg", None),
+        ];
+        carry_ocr(&mut frames, &previous);
+
+        assert_eq!(frames[0].ocr_text.as_deref(), Some("2 cups flour"));
+        assert_eq!(frames[1].ocr_text, None);
+        assert_eq!(frames[2].ocr_text.as_deref(), Some("1 egg"));
+        assert_eq!(frames[2].frame_path, "/data/job/frame_0003.jpg");
+    }
+
+    #[test]
+    fn masked_rerun_reuses_a_cached_download() {
+        let cache = tempfile::tempdir().unwrap();
+        let job = tempfile::tempdir().unwrap();
+        let job_dir = job.path().to_string_lossy().to_string();
+        // Where download_cached leaves a video when CACHE_DIR is set
+        let cached = cache.path().join("3f1c9a.mp4");
+        std::fs::write(&cached, b"").unwrap();
+
+        assert!(earlier_download(&job_dir).is_err());
+
+        let video_info = serde_json::from_value(json!({
+            "duration_seconds": 12.0,
+            "width": 720,
+            "height": 1280,
+            "fps": 30.0,
+            "codec": "h264",
+        }))
+        .unwrap();
+        let result = ProcessResult::new("abc", &cached.to_string_lossy(), video_info, Vec::new(), None, Transcript::default());
+        std::fs::write(job.path().join("result.json"), serde_json::to_vec(&result).unwrap()).unwrap();
+
+        let video = earlier_download(&job_dir).unwrap();
+        assert_eq!(video.path, cached.to_string_lossy());
+        assert!(video.image_post.is_none());
+
+        // An evicted cache entry is still an error
+        std::fs::remove_file(&cached).unwrap();
+        assert!(earlier_download(&job_dir).is_err());
+    }
+}
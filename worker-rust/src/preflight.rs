@@ -0,0 +1,234 @@
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::ocr;
+use crate::tools;
+
+/// External binaries the pipeline cannot run without, with their version flag
+fn required_tools() -> [(String, &'static str); 3] {
+    [
+        (tools::ytdlp(), "--version"),
+        (tools::ffmpeg(), "-version"),
+        (tools::ffprobe(), "-version"),
+    ]
+}
+
+/// Prints the version of the installed openai-whisper package; the whisper
+/// CLI itself has no version flag
+const WHISPER_VERSION_SCRIPT: &str =
+    "from importlib.metadata import version; print(version('openai-whisper'))";
+
+/// Result of checking a single external tool
+#[derive(Debug, Clone)]
+pub struct ToolStatus {
+    pub name: String,
+    pub version: Option<String>,
+    pub available: bool,
+}
+
+/// Summary of the preflight checks
+#[derive(Debug, Clone)]
+pub struct PreflightReport {
+    pub tools: Vec<ToolStatus>,
+    /// Tesseract "eng" data could be loaded
+    pub ocr_available: bool,
+    /// The whisper CLI is on PATH
+    pub whisper_available: bool,
+}
+
+/// Check that required tools are installed before accepting work.
+///
+/// Missing yt-dlp/ffmpeg/ffprobe is fatal. OCR and Whisper are optional,
+/// so missing them only logs a warning and the worker runs degraded.
+pub async fn preflight() -> Result<PreflightReport> {
+    let mut tools = Vec::new();
+    for (name, version_arg) in required_tools() {
+        tools.push(check_tool(&name, version_arg).await);
+    }
+
+    let missing: Vec<&str> = tools
+        .iter()
+        .filter(|t| !t.available)
+        .map(|t| t.name.as_str())
+        .collect();
+
+    if !missing.is_empty() {
+        anyhow::bail!("Required tools not found: {}", missing.join(", "));
+    }
+
+    for tool in &tools {
+        info!(
+            "Found {}: {}",
+            tool.name,
+            tool.version.as_deref().unwrap_or("unknown version")
+        );
+    }
+
+    let whisper = run_tool("whisper", &tools::whisper(), &["--help"]).await;
+    if !whisper.available {
+        warn!("whisper not found, audio transcription will be skipped");
+    }
+
+    let ocr_available = ocr::tesseract_available().await;
+    if !ocr_available {
+        warn!("Tesseract 'eng' data not available, OCR will be skipped");
+    }
+
+    Ok(PreflightReport {
+        tools,
+        ocr_available,
+        whisper_available: whisper.available,
+    })
+}
+
+/// Versions of every external tool, required or optional, for the
+/// `version` command. Unlike preflight, nothing here is an error.
+pub async fn tool_versions() -> Vec<ToolStatus> {
+    let mut tools = Vec::new();
+    for (name, version_arg) in required_tools() {
+        tools.push(check_tool(&name, version_arg).await);
+    }
+    tools.push(check_tool("tesseract", "--version").await);
+    tools.push(run_tool("whisper", "python3", &["-c", WHISPER_VERSION_SCRIPT]).await);
+    tools
+}
+
+async fn check_tool(name: &str, version_arg: &str) -> ToolStatus {
+    run_tool(name, name, &[version_arg]).await
+}
+
+/// Run `program args` and report it as `name`
+async fn run_tool(name: &str, program: &str, args: &[&str]) -> ToolStatus {
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .output()
+        .await;
+
+    match output {
+        Ok(output) if output.status.success() => {
+            // First line is enough: "ffmpeg version 6.0 ..." / "2023.11.16".
+            // Older Tesseract releases print their version to stderr.
+            let first_line = |bytes: &[u8]| {
+                String::from_utf8_lossy(bytes)
+                    .lines()
+                    .next()
+                    .map(|l| l.trim().to_string())
+                    .filter(|l| !l.is_empty())
+            };
+            let version = first_line(&output.stdout).or_else(|| first_line(&output.stderr));
+
+            ToolStatus {
+                name: name.to_string(),
+                version,
+                available: true,
+            }
+        }
+        _ => ToolStatus {
+            name: name.to_string(),
+            version: None,
+            available: false,
+        },
+    }
+}
+
+/// Default MIN_FREE_BYTES: 1 GiB, room for a long 1080p download plus frames
+const DEFAULT_MIN_FREE_BYTES: u64 = 1 << 30;
+
+/// How often a paused worker checks whether space has been freed
+pub const DISK_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Bytes available to this process on the filesystem holding `path`
+pub fn free_space(path: &Path) -> std::io::Result<u64> {
+    fs2::available_space(path)
+}
+
+/// Stops a worker taking jobs while the output directory's disk is nearly
+/// full, so jobs wait in the queue instead of failing one by one
+pub struct DiskGuard {
+    min_free: u64,
+    paused: bool,
+}
+
+impl DiskGuard {
+    /// Read MIN_FREE_BYTES (default 1 GiB; 0 disables the check)
+    pub fn from_env() -> Self {
+        let min_free = std::env::var("MIN_FREE_BYTES")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_MIN_FREE_BYTES);
+        Self { min_free, paused: false }
+    }
+
+    /// Whether `dir` has room for another job. Logs once when intake pauses
+    /// and once when it resumes. A filesystem that can't report free space
+    /// is assumed to have room.
+    pub fn has_room(&mut self, dir: &Path) -> bool {
+        if self.min_free == 0 {
+            return true;
+        }
+        let free = match free_space(dir) {
+            Ok(free) => free,
+            Err(e) => {
+                warn!("Could not read free space for {:?}: {}", dir, e);
+                return true;
+            }
+        };
+        self.record_free(dir, free)
+    }
+
+    /// Compare `free` bytes in `dir` against MIN_FREE_BYTES, logging when
+    /// this pauses or resumes intake
+    fn record_free(&mut self, dir: &Path, free: u64) -> bool {
+        let room = free >= self.min_free;
+        if !room && !self.paused {
+            warn!(
+                "Only {} MiB free in {:?} (MIN_FREE_BYTES is {} MiB); not taking jobs until space is freed",
+                free >> 20,
+                dir,
+                self.min_free >> 20
+            );
+        } else if room && self.paused {
+            info!("{} MiB free in {:?} again, resuming", free >> 20, dir);
+        }
+        self.paused = !room;
+        room
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intake_pauses_below_the_minimum_and_resumes_above_it() {
+        let dir = Path::new("/data/jobs");
+        let mut guard = DiskGuard { min_free: 1 << 30, paused: false };
+
+        assert!(guard.record_free(dir, 2 << 30));
+        assert!(!guard.paused);
+
+        assert!(!guard.record_free(dir, 512 << 20));
+        assert!(guard.paused);
+        assert!(!guard.record_free(dir, 512 << 20));
+        assert!(guard.paused);
+
+        // Exactly the minimum is enough
+        assert!(guard.record_free(dir, 1 << 30));
+        assert!(!guard.paused);
+    }
+
+    #[test]
+    fn zero_minimum_or_unreadable_space_never_pauses() {
+        let missing = Path::new("/nonexistent/worker-rust/jobs");
+
+        let mut disabled = DiskGuard { min_free: 0, paused: false };
+        assert!(disabled.has_room(missing));
+        assert!(!disabled.paused);
+
+        let mut guard = DiskGuard { min_free: u64::MAX, paused: false };
+        assert!(guard.has_room(missing));
+        assert!(!guard.paused);
+    }
+}
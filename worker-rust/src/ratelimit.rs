@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Allowed downloads per time window, e.g. "5/min"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub count: u32,
+    pub per: Duration,
+}
+
+impl Rate {
+    /// "N/s", "N/min", or "N/hour" (also "sec"/"second", "m"/"minute", "h"/"hr")
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (count, unit) = spec.trim().split_once('/')?;
+        let count = count.trim().parse::<u32>().ok().filter(|c| *c > 0)?;
+        let per = match unit.trim().to_lowercase().as_str() {
+            "s" | "sec" | "second" => Duration::from_secs(1),
+            "m" | "min" | "minute" => Duration::from_secs(60),
+            "h" | "hr" | "hour" => Duration::from_secs(3600),
+            _ => return None,
+        };
+        Some(Self { count, per })
+    }
+
+    /// Tokens regained over `elapsed`
+    fn tokens_for(&self, elapsed: Duration) -> f64 {
+        elapsed.as_secs_f64() * self.count as f64 / self.per.as_secs_f64()
+    }
+
+    /// Time to regain `tokens`
+    fn time_for(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64(tokens * self.per.as_secs_f64() / self.count as f64)
+    }
+}
+
+/// Token bucket holding up to `rate.count` tokens, refilled continuously
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(rate: Rate, now: Instant) -> Self {
+        Self {
+            tokens: rate.count as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, rate: Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.tokens = (self.tokens + rate.tokens_for(elapsed)).min(rate.count as f64);
+        self.updated = now;
+    }
+
+    /// Take a token, returning how long the caller must wait before using it.
+    /// The balance goes negative while callers queue, so each waiter gets its
+    /// own slot instead of all of them waking for the same token.
+    fn reserve(&mut self, rate: Rate, now: Instant) -> Duration {
+        self.refill(rate, now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            rate.time_for(-self.tokens)
+        }
+    }
+}
+
+/// Per-site token buckets shared by every download in the process
+pub struct HostRateLimiter {
+    rate: Rate,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl HostRateLimiter {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reserve a token for `site`, returning how long to wait before using it
+    fn reserve(&self, site: &str, now: Instant) -> Duration {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // A full bucket is what a new site gets anyway, so drop those to
+        // keep the map from growing with every host ever seen
+        buckets.retain(|_, bucket| {
+            bucket.refill(self.rate, now);
+            bucket.tokens < self.rate.count as f64
+        });
+
+        buckets
+            .entry(site.to_string())
+            .or_insert_with(|| Bucket::full(self.rate, now))
+            .reserve(self.rate, now)
+    }
+
+    /// Sleep until a download from `url`'s site is allowed
+    pub async fn acquire(&self, url: &str) {
+        let Some(site) = site_key(url) else { return };
+        let wait = self.reserve(&site, Instant::now());
+        if !wait.is_zero() {
+            info!("Rate limiting {}: waiting {:.1}s before downloading", site, wait.as_secs_f64());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Second-level labels that country-code TLDs register names under, as in
+/// bbc.co.uk or abc.net.au
+const CCTLD_SECOND_LEVELS: &[&str] = &[
+    "ac", "co", "com", "edu", "go", "gob", "gov", "ltd", "mil", "ne", "net", "nic", "or", "org", "plc", "sch",
+];
+
+/// Bucket key for `url`: the host without subdomains, so www., m., and vm.
+/// links to the same platform share one limit. Under a two-letter TLD a
+/// registry label like "co" keeps a third label, so bbc.co.uk and
+/// example.co.uk stay apart. IP hosts are used as is.
+pub fn site_key(url: &str) -> Option<String> {
+    let host = reqwest::Url::parse(url.trim()).ok()?.host_str()?.to_lowercase();
+    let host = host.trim_end_matches('.');
+    if host.trim_matches(|c| c == '[' || c == ']').parse::<IpAddr>().is_ok() {
+        return Some(host.to_string());
+    }
+
+    let labels: Vec<&str> = host.split('.').collect();
+    let kept = match labels.as_slice() {
+        [.., _, second, tld] if tld.len() == 2 && CCTLD_SECOND_LEVELS.contains(second) => 3,
+        _ => 2,
+    };
+    Some(labels[labels.len().saturating_sub(kept)..].join("."))
+}
+
+static LIMITER: OnceLock<Option<HostRateLimiter>> = OnceLock::new();
+
+/// Wait for `url`'s site to allow another download under RATE_LIMIT_PER_HOST
+/// (e.g. "5/min"); returns at once when unset. Buckets are per process, so
+/// workers sharing an egress IP should split the platform's budget between
+/// them.
+pub async fn wait_for_download(url: &str) {
+    let limiter = LIMITER.get_or_init(|| {
+        let spec = std::env::var("RATE_LIMIT_PER_HOST").ok().filter(|s| !s.trim().is_empty())?;
+        match Rate::parse(&spec) {
+            Some(rate) => {
+                info!("Limiting downloads to {} per {:?} per site", rate.count, rate.per);
+                Some(HostRateLimiter::new(rate))
+            }
+            None => {
+                warn!("Invalid RATE_LIMIT_PER_HOST {:?} (expected e.g. \"5/min\"), not rate limiting", spec);
+                None
+            }
+        }
+    });
+
+    if let Some(limiter) = limiter {
+        limiter.acquire(url).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_parses_count_and_unit() {
+        assert_eq!(
+            Rate::parse("5/min"),
+            Some(Rate {
+                count: 5,
+                per: Duration::from_secs(60)
+            })
+        );
+        assert_eq!(Rate::parse(" 30 / Hour ").map(|r| r.per), Some(Duration::from_secs(3600)));
+        assert_eq!(Rate::parse("2/s").map(|r| r.count), Some(2));
+        assert_eq!(Rate::parse("0/min"), None);
+        assert_eq!(Rate::parse("5/day"), None);
+        assert_eq!(Rate::parse("5"), None);
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_then_spaces_requests() {
+        let rate = Rate::parse("5/min").unwrap();
+        let start = Instant::now();
+        let mut bucket = Bucket::full(rate, start);
+
+        for _ in 0..5 {
+            assert_eq!(bucket.reserve(rate, start), Duration::ZERO);
+        }
+        // One token every 12s; queued callers get successive slots
+        assert_eq!(bucket.reserve(rate, start), Duration::from_secs(12));
+        assert_eq!(bucket.reserve(rate, start), Duration::from_secs(24));
+
+        // After the queue has drained and another interval passed, one is free
+        assert_eq!(bucket.reserve(rate, start + Duration::from_secs(36)), Duration::ZERO);
+    }
+
+    #[test]
+    fn sites_have_separate_buckets() {
+        let limiter = HostRateLimiter::new(Rate::parse("1/min").unwrap());
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("instagram.com", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("tiktok.com", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("instagram.com", now), Duration::from_secs(60));
+
+        // Refilled buckets are forgotten
+        limiter.reserve("instagram.com", now + Duration::from_secs(600));
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn site_key_groups_subdomains() {
+        assert_eq!(site_key("https://www.instagram.com/reel/abc/").as_deref(), Some("instagram.com"));
+        assert_eq!(site_key("https://vm.tiktok.com/ZMabc/").as_deref(), Some("tiktok.com"));
+        assert_eq!(site_key("https://youtu.be/xyz").as_deref(), Some("youtu.be"));
+        assert_eq!(site_key("http://10.0.0.5:8080/video.mp4").as_deref(), Some("10.0.0.5"));
+        assert_eq!(site_key("not a url"), None);
+    }
+
+    #[test]
+    fn site_key_keeps_the_name_under_multi_part_suffixes() {
+        assert_eq!(site_key("https://www.bbc.co.uk/food/recipes").as_deref(), Some("bbc.co.uk"));
+        assert_eq!(site_key("https://example.co.uk/r/1").as_deref(), Some("example.co.uk"));
+        assert_eq!(site_key("https://m.abc.net.au/news").as_deref(), Some("abc.net.au"));
+        assert_eq!(site_key("https://www.instagram.com.br/reel/abc/").as_deref(), Some("instagram.com.br"));
+        // Not a registry label, or not a country-code TLD
+        assert_eq!(site_key("https://www.chef.de/rezept").as_deref(), Some("chef.de"));
+        assert_eq!(site_key("https://blog.co.com/post").as_deref(), Some("co.com"));
+        assert_eq!(site_key("https://co.uk/").as_deref(), Some("co.uk"));
+    }
+}
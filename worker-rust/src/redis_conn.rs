@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use redis::{ConnectionAddr, IntoConnectionInfo};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Open a Redis client for `redis_url`.
+///
+/// Accepts `redis://` and `rediss://` (TLS) URLs. Credentials may be embedded
+/// in the URL or supplied via REDIS_USERNAME / REDIS_PASSWORD, which take
+/// precedence so secrets can stay out of the URL.
+pub fn open(redis_url: &str) -> Result<redis::Client> {
+    let mut info = redis_url
+        .into_connection_info()
+        .context("Invalid REDIS_URL")?;
+    
+    if let Some(username) = std::env::var("REDIS_USERNAME").ok().filter(|u| !u.is_empty()) {
+        info.redis.username = Some(username);
+    }
+    if let Some(password) = std::env::var("REDIS_PASSWORD").ok().filter(|p| !p.is_empty()) {
+        info.redis.password = Some(password);
+    }
+    
+    redis::Client::open(info).context("Failed to create Redis client")
+}
+
+/// Connect and PING so bad hosts, TLS, or AUTH fail at startup with a clear
+/// message instead of on the first queue command.
+pub async fn verify(client: &redis::Client) -> Result<()> {
+    let info = client.get_connection_info();
+    let (target, tls) = match &info.addr {
+        ConnectionAddr::Tcp(host, port) => (format!("{}:{}", host, port), false),
+        ConnectionAddr::TcpTls { host, port, .. } => (format!("{}:{}", host, port), true),
+        ConnectionAddr::Unix(path) => (path.display().to_string(), false),
+    };
+    
+    let mut conn = client
+        .get_async_connection()
+        .await
+        .with_context(|| format!("Failed to connect to Redis at {} (tls={})", target, tls))?;
+    
+    let _: String = redis::cmd("PING")
+        .query_async(&mut conn)
+        .await
+        .with_context(|| format!("Redis at {} rejected PING (check credentials)", target))?;
+    
+    info!("Connected to Redis at {} (tls={})", target, tls);
+    
+    Ok(())
+}
+
+/// Failures in a row before the breaker opens
+const DEFAULT_BREAKER_THRESHOLD: u32 = 3;
+
+/// Longest wait between reconnect probes while the breaker is open
+const DEFAULT_BREAKER_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Delay after a failure while the breaker is still closed
+const CLOSED_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// `delay` plus up to 20% random jitter, so a fleet of workers that failed
+/// together doesn't retry together
+pub fn with_jitter(delay: Duration) -> Duration {
+    delay + delay.mul_f64((uuid::Uuid::new_v4().as_u128() % 1000) as f64 / 5000.0)
+}
+
+/// Backs off reconnects during Redis outages.
+///
+/// Below the threshold, failures retry after about 5s. Once open, each
+/// further failure doubles the delay up to the cap, with jitter so a fleet of
+/// workers doesn't reconnect in lockstep. The next attempt after a delay is
+/// the recovery probe; a success closes the breaker.
+pub struct CircuitBreaker {
+    failures: u32,
+    threshold: u32,
+    max_backoff: Duration,
+}
+
+impl CircuitBreaker {
+    /// Read REDIS_BREAKER_THRESHOLD and REDIS_BREAKER_MAX_BACKOFF_SECS
+    pub fn from_env() -> Self {
+        let threshold = std::env::var("REDIS_BREAKER_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_BREAKER_THRESHOLD);
+        let max_backoff = std::env::var("REDIS_BREAKER_MAX_BACKOFF_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|s| *s > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_BREAKER_MAX_BACKOFF);
+        
+        Self {
+            failures: 0,
+            threshold,
+            max_backoff,
+        }
+    }
+    
+    pub fn is_open(&self) -> bool {
+        self.failures >= self.threshold
+    }
+    
+    /// Close the breaker after a successful Redis round trip
+    pub fn record_success(&mut self) {
+        if self.is_open() {
+            info!("Redis reachable again, circuit closed after {} failures", self.failures);
+        }
+        self.failures = 0;
+    }
+    
+    /// Count a failure and return how long to wait before the next attempt
+    pub fn record_failure(&mut self) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        
+        if !self.is_open() {
+            return with_jitter(CLOSED_RETRY_DELAY);
+        }
+        
+        let exponent = (self.failures - self.threshold).min(16);
+        let backoff = CLOSED_RETRY_DELAY
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        let delay = with_jitter(backoff);
+        
+        if self.failures == self.threshold {
+            warn!(
+                "Redis circuit open after {} consecutive failures, worker degraded; probing every {:?} or less",
+                self.failures, self.max_backoff
+            );
+        } else {
+            warn!("Redis still unavailable ({} failures), next probe in {:.1?}", self.failures, delay);
+        }
+        
+        delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker { failures: 0, threshold: 3, max_backoff: Duration::from_secs(60) }
+    }
+    
+    /// `delay` is `base` plus at most 20% jitter
+    fn assert_jittered(delay: Duration, base: Duration) {
+        assert!(delay >= base && delay < base.mul_f64(1.2), "{:?} is not {:?} plus jitter", delay, base);
+    }
+    
+    #[test]
+    fn backoff_doubles_once_open_up_to_the_cap() {
+        let mut breaker = breaker();
+        assert_jittered(breaker.record_failure(), CLOSED_RETRY_DELAY);
+        assert_jittered(breaker.record_failure(), CLOSED_RETRY_DELAY);
+        assert!(!breaker.is_open());
+        
+        for secs in [5, 10, 20, 40, 60, 60] {
+            assert_jittered(breaker.record_failure(), Duration::from_secs(secs));
+            assert!(breaker.is_open());
+        }
+        
+        breaker.failures = u32::MAX - 1;
+        assert_jittered(breaker.record_failure(), Duration::from_secs(60));
+        assert_jittered(breaker.record_failure(), Duration::from_secs(60));
+    }
+    
+    #[test]
+    fn a_success_closes_the_breaker() {
+        let mut breaker = breaker();
+        for _ in 0..5 {
+            breaker.record_failure();
+        }
+        assert!(breaker.is_open());
+        
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert_jittered(breaker.record_failure(), CLOSED_RETRY_DELAY);
+    }
+}
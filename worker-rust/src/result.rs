@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::assemble::{self, Segment, Step, TimelineEvent};
+use crate::audio::{AudioTrack, Transcript};
+use crate::download::{FileIntegrity, Section};
+use crate::ocr::OcrTiming;
+use crate::video::{CropRect, FrameData, VideoInfo};
+
+/// Version of the ProcessResult JSON layout.
+///
+/// Bump this on any breaking change (renamed/removed fields, changed types).
+/// Adding optional fields is not a breaking change.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Version of the AiJob message layout on `queue:ai_processing`; bumped on
+/// the same rules as SCHEMA_VERSION
+pub const AI_JOB_SCHEMA_VERSION: u32 = 1;
+
+/// Message handed to the AI worker for one finished video
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiJob {
+    pub schema_version: u32,
+    pub job_id: String,
+    /// URL the job was submitted with
+    pub source_url: String,
+    /// Earlier job whose result was reused instead of processing again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+    /// RFC 3339 time the message was built
+    pub enqueued_at: String,
+    /// The finished video; for a duplicate, the earlier job's result
+    pub video_data: ProcessResult,
+}
+
+impl AiJob {
+    pub fn new(job_id: &str, source_url: &str, video_data: ProcessResult) -> Self {
+        Self {
+            schema_version: AI_JOB_SCHEMA_VERSION,
+            job_id: job_id.to_string(),
+            source_url: source_url.to_string(),
+            duplicate_of: None,
+            enqueued_at: chrono::Utc::now().to_rfc3339(),
+            video_data,
+        }
+    }
+}
+
+/// Video resolution in pixels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What a job's media turned out to be once downloaded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaType {
+    #[default]
+    Video,
+    /// A post made only of images (a carousel, or a single still); its
+    /// images are the frames and its caption the transcript
+    Images,
+}
+
+/// How an optional pipeline stage went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum StageOutcome {
+    Succeeded,
+    /// There was nothing to do, e.g. a video without audio
+    Skipped { reason: String },
+    /// The stage broke; its part of the result is empty
+    Failed { reason: String },
+}
+
+impl StageOutcome {
+    pub fn skipped(reason: &str) -> Self {
+        StageOutcome::Skipped { reason: reason.to_string() }
+    }
+
+    pub fn failed(error: &dyn std::fmt::Display) -> Self {
+        StageOutcome::Failed { reason: error.to_string() }
+    }
+}
+
+/// Output of the video pipeline, written by the Process CLI and sent to the AI queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessResult {
+    pub schema_version: u32,
+    pub job_id: String,
+    #[serde(default)]
+    pub media_type: MediaType,
+    /// The downloaded video; empty for an image post, whose images are
+    /// listed in `frames`
+    pub video_path: String,
+    /// Flattened from video_info for the AI worker
+    pub duration_seconds: f64,
+    pub resolution: Resolution,
+    pub fps: f64,
+    pub video_info: VideoInfo,
+    /// Content rectangle frames were cropped to, if letterboxing was removed
+    pub crop: Option<CropRect>,
+    pub frames: Vec<FrameData>,
+    pub thumbnail_path: Option<String>,
+    /// Short looping clip for recipe cards, when PREVIEW_FORMAT is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview_path: Option<String>,
+    pub audio_path: Option<String>,
+    /// Transcript segments as WebVTT captions, when there was speech
+    #[serde(default)]
+    pub vtt_path: Option<String>,
+    /// Audio stream that was transcribed, including its language tag
+    pub audio_track: Option<AudioTrack>,
+    pub transcription: String,
+    /// Transcript segments dropped as likely hallucinations over silence or music
+    #[serde(default)]
+    pub transcript_segments_filtered: usize,
+    /// Range of the original video that was processed; all timestamps are
+    /// relative to its start. None for the whole video.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<Section>,
+    /// Earlier job whose frames match this video's (CONTENT_DEDUP), e.g. the
+    /// same recipe re-uploaded by another account. Unlike
+    /// `AiJob::duplicate_of`, this video was still processed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_match_of: Option<String>,
+    /// Share of frames matching those of `content_match_of`, 0-1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_match_similarity: Option<f64>,
+    /// Size and SHA-256 of the downloaded video, when it was verified
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_integrity: Option<FileIntegrity>,
+    /// SponsorBlock segments were removed before processing, so timestamps
+    /// refer to the trimmed video rather than the original
+    #[serde(default)]
+    pub sponsorblock_trimmed: bool,
+    /// OCR text and speech interleaved by timestamp
+    #[serde(default)]
+    pub timeline: Vec<TimelineEvent>,
+    /// Per-recipe ranges for compilation videos; empty unless SPLIT_SEGMENTS is on
+    #[serde(default)]
+    pub segments: Vec<Segment>,
+    /// Narration grouped into ordered steps, each with its best frame
+    #[serde(default)]
+    pub steps: Vec<Step>,
+    /// Tesseract wall time stats, only when OCR_TIMING is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_timing: Option<OcrTiming>,
+    /// Outcome of each optional stage (frames, thumbnail, preview, ocr,
+    /// audio, transcribe), so an empty field can be told apart from a failed stage
+    #[serde(default)]
+    pub stages: BTreeMap<String, StageOutcome>,
+}
+
+/// Output of a probe-only run: metadata without frames, OCR, or audio
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResult {
+    pub schema_version: u32,
+    pub job_id: String,
+    /// Always true; lets consumers tell this apart from a full ProcessResult
+    pub probe_only: bool,
+    /// Local download, or the submitted URL when `streamed` (the media URL
+    /// it resolved to is signed and soon expires, so it isn't kept)
+    pub video_path: String,
+    /// Metadata was read straight from the stream without downloading
+    #[serde(default)]
+    pub streamed: bool,
+    pub video_info: VideoInfo,
+}
+
+impl ProbeResult {
+    pub fn new(job_id: &str, video_path: &str, video_info: VideoInfo) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            job_id: job_id.to_string(),
+            probe_only: true,
+            video_path: video_path.to_string(),
+            streamed: false,
+            video_info,
+        }
+    }
+}
+
+impl ProcessResult {
+    pub fn new(
+        job_id: &str,
+        video_path: &str,
+        video_info: VideoInfo,
+        frames: Vec<FrameData>,
+        audio_path: Option<String>,
+        transcript: Transcript,
+    ) -> Self {
+        let timeline = assemble::build_timeline(&frames, &transcript.segments);
+        
+        Self {
+            schema_version: SCHEMA_VERSION,
+            job_id: job_id.to_string(),
+            media_type: MediaType::Video,
+            video_path: video_path.to_string(),
+            duration_seconds: video_info.duration_seconds,
+            resolution: Resolution {
+                width: video_info.width,
+                height: video_info.height,
+            },
+            fps: video_info.fps,
+            video_info,
+            crop: None,
+            frames,
+            thumbnail_path: None,
+            preview_path: None,
+            audio_path,
+            vtt_path: None,
+            audio_track: None,
+            transcription: transcript.text,
+            transcript_segments_filtered: transcript.filtered_segments,
+            section: None,
+            content_match_of: None,
+            content_match_similarity: None,
+            video_integrity: None,
+            sponsorblock_trimmed: false,
+            timeline,
+            segments: Vec::new(),
+            steps: Vec::new(),
+            ocr_timing: None,
+            stages: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result(job_id: &str) -> ProcessResult {
+        let video_info = serde_json::from_value(json!({
+            "duration_seconds": 31.5,
+            "width": 1080,
+            "height": 1920,
+            "fps": 30.0,
+            "codec": "h264",
+        }))
+        .unwrap();
+        ProcessResult::new(job_id, "video.mp4", video_info, Vec::new(), None, Transcript::default())
+    }
+
+    #[test]
+    fn ai_job_message_has_the_documented_shape() {
+        let job = AiJob::new("abc", "https://example.com/r/1", result("abc"));
+        let message = serde_json::to_value(&job).unwrap();
+
+        let mut fields: Vec<&str> = message.as_object().unwrap().keys().map(String::as_str).collect();
+        fields.sort();
+        assert_eq!(fields, ["enqueued_at", "job_id", "schema_version", "source_url", "video_data"]);
+        assert_eq!(message["schema_version"], AI_JOB_SCHEMA_VERSION);
+        assert_eq!(message["video_data"]["job_id"], "abc");
+        assert_eq!(message["video_data"]["video_info"]["codec"], "h264");
+        assert!(chrono::DateTime::parse_from_rfc3339(message["enqueued_at"].as_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn duplicate_ai_job_names_the_reused_job_and_round_trips() {
+        let mut job = AiJob::new("def", "https://example.com/r/1", result("def"));
+        job.duplicate_of = Some("abc".to_string());
+        let message = serde_json::to_string(&job).unwrap();
+
+        let decoded: AiJob = serde_json::from_str(&message).unwrap();
+        assert_eq!(decoded.duplicate_of.as_deref(), Some("abc"));
+        assert_eq!(decoded.video_data.job_id, "def");
+        assert_eq!(decoded.video_data.video_info.duration_seconds, 31.5);
+    }
+}
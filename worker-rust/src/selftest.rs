@@ -0,0 +1,300 @@
+//! End-to-end check of a deployment: runs every pipeline stage on a small
+//! clip and reports which ones work, without touching Redis.
+
+use anyhow::Result;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::audio::{self, AudioNormalization};
+use crate::download;
+use crate::error::WorkerError;
+use crate::ocr::{self, OcrOptions};
+use crate::preflight;
+use crate::tools;
+use crate::transcribe::{self, Backend};
+use crate::video::{self, FrameEncoding, DEFAULT_MAX_FRAMES};
+
+/// Length of the generated fixture; long enough for a few 2-second frames
+const FIXTURE_SECS: u32 = 6;
+
+/// Job id used for the self-test's files and log lines
+const JOB_ID: &str = "selftest";
+
+/// How one stage went
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageStatus {
+    Passed,
+    Failed,
+    /// Not run, because an earlier stage it depends on failed, its optional
+    /// tool is missing, or it wasn't asked for
+    Skipped,
+}
+
+/// One line of the self-test report
+#[derive(Debug, Clone)]
+pub struct StageReport {
+    pub stage: &'static str,
+    pub status: StageStatus,
+    pub elapsed: Duration,
+    /// What the stage produced, or why it failed or was skipped
+    pub detail: String,
+}
+
+impl std::fmt::Display for StageReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self.status {
+            StageStatus::Passed => "PASS",
+            StageStatus::Failed => "FAIL",
+            StageStatus::Skipped => "SKIP",
+        };
+        write!(
+            f,
+            "{:<10} {} {:>7.2}s  {}",
+            self.stage,
+            status,
+            self.elapsed.as_secs_f64(),
+            self.detail
+        )
+    }
+}
+
+/// Results of every stage, in pipeline order
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestReport {
+    pub stages: Vec<StageReport>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.stages.iter().all(|s| s.status != StageStatus::Failed)
+    }
+
+    /// Time `stage`, record how it went, and hand back its output on success
+    async fn run<T, F>(&mut self, stage: &'static str, work: F) -> Option<T>
+    where
+        F: Future<Output = std::result::Result<(T, String), String>>,
+    {
+        let started = Instant::now();
+        let outcome = work.await;
+        let elapsed = started.elapsed();
+
+        let (status, detail, value) = match outcome {
+            Ok((value, detail)) => (StageStatus::Passed, detail, Some(value)),
+            Err(reason) => {
+                warn!("Self-test stage {} failed: {}", stage, reason);
+                (StageStatus::Failed, reason, None)
+            }
+        };
+        self.stages.push(StageReport { stage, status, elapsed, detail });
+        value
+    }
+
+    fn skip(&mut self, stage: &'static str, reason: &str) {
+        self.stages.push(StageReport {
+            stage,
+            status: StageStatus::Skipped,
+            elapsed: Duration::ZERO,
+            detail: reason.to_string(),
+        });
+    }
+}
+
+/// Run tool checks, then download (only when `url` is given; otherwise a
+/// clip is generated with ffmpeg's lavfi sources), probe, frame extraction,
+/// OCR, audio extraction, and transcription. Every stage runs through the
+/// same functions the worker uses.
+pub async fn run(url: Option<&str>) -> Result<SelfTestReport> {
+    let dir = tempfile::tempdir()?;
+    let job_dir = dir.path().to_string_lossy().to_string();
+    let mut report = SelfTestReport::default();
+
+    // Like preflight, only the required tools fail the deployment; without
+    // an optional one the worker runs degraded and its stage is skipped
+    let checked = report
+        .run("tools", async {
+            let checked = preflight::preflight().await.map_err(|e| e.to_string())?;
+            let mut detail = format!("{} required tools found", checked.tools.len());
+            let mut missing = Vec::new();
+            if !checked.ocr_available {
+                missing.push("tesseract");
+            }
+            if !checked.whisper_available {
+                missing.push("whisper");
+            }
+            if !missing.is_empty() {
+                detail.push_str(&format!("; optional tools missing: {}", missing.join(", ")));
+            }
+            Ok((checked, detail))
+        })
+        .await;
+    if checked.is_none() {
+        info!("Continuing the self-test to see which stages still work");
+    }
+    // Unknown when the required tools are missing; the stage then finds out itself
+    let ocr_available = checked.as_ref().map_or(true, |c| c.ocr_available);
+    let whisper_available = checked.as_ref().map_or(true, |c| c.whisper_available)
+        || !matches!(transcribe::backend(), Backend::Whisper(_));
+
+    let video_path = match url {
+        Some(url) => {
+            report
+                .run("download", async {
+                    let video = download::download_video(url, &job_dir, JOB_ID, None)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let detail = format!("downloaded {}", video.path);
+                    Ok((video.path, detail))
+                })
+                .await
+        }
+        None => {
+            report.skip("download", "no --url given");
+            report
+                .run("fixture", async {
+                    let path = generate_fixture(dir.path()).await.map_err(|e| e.to_string())?;
+                    Ok((path, format!("{}s lavfi clip", FIXTURE_SECS)))
+                })
+                .await
+        }
+    };
+    let Some(video_path) = video_path else {
+        for stage in ["probe", "frames", "ocr", "audio", "transcribe"] {
+            report.skip(stage, "no video to test with");
+        }
+        return Ok(report);
+    };
+
+    let video_info = report
+        .run("probe", async {
+            let info = video::process_video(&video_path, JOB_ID)
+                .await
+                .map_err(|e| e.to_string())?;
+            let detail = format!(
+                "{}x{} {} {:.2}s",
+                info.width, info.height, info.codec, info.duration_seconds
+            );
+            Ok((info, detail))
+        })
+        .await;
+
+    let frames = match &video_info {
+        Some(info) => {
+            report
+                .run("frames", async {
+                    let frames = video::extract_keyframes(
+                        &video_path,
+                        &job_dir,
+                        JOB_ID,
+                        info.duration_seconds,
+                        None,
+                        DEFAULT_MAX_FRAMES,
+                        &FrameEncoding::from_env(),
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    let detail = format!("{} frames", frames.len());
+                    Ok((frames, detail))
+                })
+                .await
+        }
+        None => {
+            report.skip("frames", "probe failed");
+            None
+        }
+    };
+
+    match frames {
+        Some(_) if !ocr_available => report.skip("ocr", "Tesseract 'eng' data not available"),
+        Some(frames) => {
+            report
+                .run("ocr", async {
+                    // process_frames quietly skips OCR without Tesseract data
+                    if !ocr::tesseract_available().await {
+                        return Err("Tesseract 'eng' data not available".to_string());
+                    }
+                    let (frames, _) = ocr::process_frames(frames, OcrOptions::from_env())
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    let with_text = frames.iter().filter(|f| f.ocr_text.is_some()).count();
+                    Ok(((), format!("text on {} of {} frames", with_text, frames.len())))
+                })
+                .await;
+        }
+        None => report.skip("ocr", "no frames"),
+    }
+
+    let audio_path = match &video_info {
+        Some(_) => {
+            report
+                .run("audio", async {
+                    let path = audio::extract_audio(
+                        &video_path,
+                        &job_dir,
+                        JOB_ID,
+                        None,
+                        AudioNormalization::from_env(),
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?;
+                    Ok((path.clone(), path))
+                })
+                .await
+        }
+        None => {
+            report.skip("audio", "probe failed");
+            None
+        }
+    };
+
+    match audio_path {
+        Some(_) if !whisper_available => report.skip("transcribe", "whisper not found"),
+        Some(audio_path) => {
+            report
+                .run("transcribe", async {
+                    let transcript = audio::transcribe_audio(&audio_path)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    Ok(((), format!("{} segments", transcript.segments.len())))
+                })
+                .await;
+        }
+        None => report.skip("transcribe", "no audio"),
+    }
+
+    Ok(report)
+}
+
+/// Write a short clip with on-screen text and a tone to `dir`. Falls back
+/// to a plain test pattern when this ffmpeg was built without drawtext.
+async fn generate_fixture(dir: &Path) -> crate::error::Result<String> {
+    let path = dir.join("fixture.mp4");
+    let sources = [
+        format!(
+            "color=c=white:s=640x360:d={},drawtext=text='SELF TEST':fontsize=72:fontcolor=black:x=(w-text_w)/2:y=(h-text_h)/2",
+            FIXTURE_SECS
+        ),
+        format!("testsrc2=s=640x360:d={}", FIXTURE_SECS),
+    ];
+    let mut last_error = String::new();
+
+    for source in &sources {
+        let output = tokio::process::Command::new(tools::ffmpeg())
+            .kill_on_drop(true)
+            .args(&["-v", "error", "-f", "lavfi", "-i", source])
+            .args(&["-f", "lavfi", "-i", &format!("sine=frequency=440:duration={}", FIXTURE_SECS)])
+            .args(&["-c:v", "libx264", "-pix_fmt", "yuv420p", "-c:a", "aac", "-shortest", "-y"])
+            .arg(&path)
+            .output()
+            .await
+            .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+
+        if output.status.success() {
+            return Ok(path.to_string_lossy().to_string());
+        }
+        last_error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    }
+
+    Err(WorkerError::FrameExtraction(format!("could not generate a test clip: {}", last_error)))
+}
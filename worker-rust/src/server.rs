@@ -0,0 +1,197 @@
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::pipeline;
+use crate::redis_conn;
+use crate::stage::StageMask;
+
+/// Where submitted jobs go
+enum Backend {
+    /// Run the pipeline in-process and keep job state in memory
+    Inline {
+        jobs: RwLock<HashMap<String, Value>>,
+        output_dir: String,
+        config: Config,
+    },
+    /// Push to the Redis video queue and read state from `job:{id}`
+    Queue { redis_client: redis::Client },
+}
+
+#[derive(Deserialize)]
+struct CreateJob {
+    url: String,
+}
+
+/// Serve `POST /jobs` and `GET /jobs/{id}` on `addr`. With `enqueue`, jobs go
+/// to the Redis queue at `config.redis_url`; otherwise they run in-process.
+pub async fn serve(addr: &str, output_dir: &str, enqueue: bool, config: &Config) -> Result<()> {
+    let backend = match enqueue.then_some(config.redis_url.as_str()) {
+        Some(url) => {
+            info!("HTTP ingest enqueuing jobs to Redis");
+            let redis_client = redis_conn::open(url)?;
+            redis_conn::verify(&redis_client).await?;
+            Backend::Queue { redis_client }
+        }
+        None => {
+            info!("HTTP ingest processing jobs inline");
+            std::fs::create_dir_all(output_dir)?;
+            Backend::Inline {
+                jobs: RwLock::new(HashMap::new()),
+                output_dir: output_dir.to_string(),
+                config: config.clone(),
+            }
+        }
+    };
+
+    let app = Router::new()
+        .route("/jobs", post(create_job))
+        .route("/jobs/:id", get(get_job))
+        .with_state(Arc::new(backend));
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("HTTP ingest listening on {}", addr);
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn create_job(
+    State(backend): State<Arc<Backend>>,
+    Json(body): Json<CreateJob>,
+) -> Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)> {
+    let job_id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let job = json!({
+        "job_id": job_id,
+        "url": body.url,
+        "status": "pending",
+        "progress": 0,
+        "created_at": now,
+        "updated_at": now,
+    });
+
+    match backend.as_ref() {
+        Backend::Inline { jobs, .. } => {
+            jobs.write().await.insert(job_id.clone(), job.clone());
+
+            let backend = backend.clone();
+            let id = job_id.clone();
+            tokio::spawn(async move { run_inline(backend, id, body.url).await });
+        }
+        Backend::Queue { redis_client } => {
+            enqueue(redis_client, &job_id, &job).await.map_err(|e| {
+                error!("Failed to enqueue job {}: {}", job_id, e);
+                internal_error(&e.to_string())
+            })?;
+        }
+    }
+
+    info!("Accepted job {}", job_id);
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+async fn get_job(
+    State(backend): State<Arc<Backend>>,
+    Path(job_id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let job = match backend.as_ref() {
+        Backend::Inline { jobs, .. } => jobs.read().await.get(&job_id).cloned(),
+        Backend::Queue { redis_client } => {
+            let data = fetch_job(redis_client, &job_id)
+                .await
+                .map_err(|e| internal_error(&e.to_string()))?;
+            data.and_then(|d| serde_json::from_str(&d).ok())
+        }
+    };
+
+    job.map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Job {} not found", job_id) })),
+        )
+    })
+}
+
+async fn run_inline(backend: Arc<Backend>, job_id: String, url: String) {
+    let Backend::Inline { jobs, output_dir, config } = backend.as_ref() else {
+        return;
+    };
+
+    set_inline_status(jobs, &job_id, json!({ "status": "processing" })).await;
+
+    let cancel = CancellationToken::new();
+    let update = match pipeline::process_single_video(&url, output_dir, &job_id, config, StageMask::ALL, None, &cancel).await {
+        Ok(result) => json!({
+            "status": "completed",
+            "progress": 100,
+            "result": result,
+        }),
+        Err(e) => {
+            error!("Job {} failed: {}", job_id, e);
+            json!({ "status": "failed", "error_message": e.to_string() })
+        }
+    };
+
+    set_inline_status(jobs, &job_id, update).await;
+}
+
+async fn set_inline_status(jobs: &RwLock<HashMap<String, Value>>, job_id: &str, update: Value) {
+    if let Some(job) = jobs.write().await.get_mut(job_id) {
+        if let (Some(job), Some(update)) = (job.as_object_mut(), update.as_object()) {
+            for (k, v) in update {
+                job.insert(k.clone(), v.clone());
+            }
+            job.insert("updated_at".to_string(), json!(chrono::Utc::now().to_rfc3339()));
+        }
+    }
+}
+
+async fn enqueue(client: &redis::Client, job_id: &str, job: &Value) -> redis::RedisResult<()> {
+    let mut conn = client.get_async_connection().await?;
+
+    redis::cmd("SET")
+        .arg(format!("job:{}", job_id))
+        .arg(job.to_string())
+        .query_async::<_, ()>(&mut conn)
+        .await?;
+
+    redis::cmd("XADD")
+        .arg("queue:video_processing")
+        .arg("*")
+        .arg("job_id")
+        .arg(job_id)
+        .arg("data")
+        .arg(job.to_string())
+        .query_async::<_, String>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+async fn fetch_job(client: &redis::Client, job_id: &str) -> redis::RedisResult<Option<String>> {
+    let mut conn = client.get_async_connection().await?;
+    redis::cmd("GET")
+        .arg(format!("job:{}", job_id))
+        .query_async(&mut conn)
+        .await
+}
+
+fn internal_error(message: &str) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": message })),
+    )
+}
@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+/// Steps a queued job moves through, in order.
+///
+/// Each stage maps to the job status the API reports and a fixed progress
+/// value, so status updates are derived from where the pipeline is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Downloading,
+    Probing,
+    Frames,
+    Ocr,
+    Audio,
+    Transcribe,
+    Enqueue,
+}
+
+impl Stage {
+    /// Stage name recorded in the job as `stage` / `failed_stage`
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Downloading => "downloading",
+            Stage::Probing => "probing",
+            Stage::Frames => "frames",
+            Stage::Ocr => "ocr",
+            Stage::Audio => "audio",
+            Stage::Transcribe => "transcribe",
+            Stage::Enqueue => "enqueue",
+        }
+    }
+
+    /// Job status for this stage; must stay within the API's JobStatus values
+    pub fn status(self) -> &'static str {
+        match self {
+            Stage::Downloading => "downloading",
+            Stage::Probing => "processing_video",
+            Stage::Frames | Stage::Ocr => "extracting_ocr",
+            Stage::Audio | Stage::Transcribe => "transcribing_audio",
+            Stage::Enqueue => "ai_processing",
+        }
+    }
+
+    /// Progress percentage reported on entering this stage
+    pub fn progress(self) -> u8 {
+        match self {
+            Stage::Downloading => 10,
+            Stage::Probing => 25,
+            Stage::Frames => 40,
+            Stage::Ocr => 50,
+            Stage::Audio => 60,
+            Stage::Transcribe => 70,
+            Stage::Enqueue => 80,
+        }
+    }
+}
+
+/// Which optional pipeline steps a job runs.
+///
+/// A masked-off step reuses what an earlier run left in the job directory,
+/// e.g. re-running OCR on the frames already on disk without downloading
+/// the video again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageMask {
+    pub download: bool,
+    pub frames: bool,
+    pub ocr: bool,
+    pub audio: bool,
+    pub transcribe: bool,
+}
+
+impl Default for StageMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl StageMask {
+    /// Every step runs
+    pub const ALL: StageMask = StageMask {
+        download: true,
+        frames: true,
+        ocr: true,
+        audio: true,
+        transcribe: true,
+    };
+
+    /// Names accepted by `parse` and the job's `stages` field
+    pub const NAMES: &'static [&'static str] = &["download", "frames", "ocr", "audio", "transcribe"];
+
+    /// Parse a comma-separated list of steps to run, e.g. "ocr,transcribe".
+    /// A step that runs also re-runs the steps reading its output, so
+    /// "frames" implies "ocr" and "download" implies every step.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut mask = StageMask {
+            download: false,
+            frames: false,
+            ocr: false,
+            audio: false,
+            transcribe: false,
+        };
+        for name in list.split(',').map(|n| n.trim().to_lowercase()).filter(|n| !n.is_empty()) {
+            match name.as_str() {
+                "download" => mask.download = true,
+                "frames" => mask.frames = true,
+                "ocr" => mask.ocr = true,
+                "audio" => mask.audio = true,
+                "transcribe" => mask.transcribe = true,
+                _ => {
+                    return Err(format!(
+                        "unknown stage '{}' (expected one of {})",
+                        name,
+                        Self::NAMES.join(", ")
+                    ))
+                }
+            }
+        }
+        if mask.download {
+            mask.frames = true;
+            mask.audio = true;
+        }
+        mask.ocr |= mask.frames;
+        mask.transcribe |= mask.audio;
+        Ok(mask)
+    }
+
+    /// The `stages` field of a queued job, as an array of names or a
+    /// comma-separated string. Missing means every step.
+    pub fn from_job(job_data: &serde_json::Value) -> Result<Self, String> {
+        match &job_data["stages"] {
+            serde_json::Value::Null => Ok(Self::ALL),
+            serde_json::Value::String(list) => Self::parse(list),
+            serde_json::Value::Array(names) => {
+                let names: Option<Vec<&str>> = names.iter().map(|n| n.as_str()).collect();
+                let names = names.ok_or_else(|| "stages must be strings".to_string())?;
+                Self::parse(&names.join(","))
+            }
+            _ => Err("stages must be a list of stage names".to_string()),
+        }
+    }
+
+    /// Whether this is a normal full run
+    pub fn is_all(&self) -> bool {
+        *self == Self::ALL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn unknown_stage_names_are_rejected() {
+        let err = StageMask::parse("ocr, upscale").unwrap_err();
+        assert!(err.contains("'upscale'"), "{}", err);
+        assert!(StageMask::from_job(&json!({ "stages": ["ocr", 3] })).is_err());
+        assert!(StageMask::from_job(&json!({ "stages": true })).is_err());
+    }
+
+    #[test]
+    fn running_a_stage_reruns_the_stages_after_it() {
+        let frames = StageMask::parse("frames").unwrap();
+        assert!(frames.frames && frames.ocr);
+        assert!(!frames.download && !frames.audio && !frames.transcribe);
+
+        let audio = StageMask::parse("Audio").unwrap();
+        assert!(audio.audio && audio.transcribe && !audio.ocr);
+
+        assert!(StageMask::parse("download").unwrap().is_all());
+        assert_eq!(StageMask::parse("ocr").unwrap(), StageMask::from_job(&json!({ "stages": "ocr" })).unwrap());
+    }
+
+    #[test]
+    fn missing_stages_run_everything() {
+        assert!(StageMask::from_job(&json!({})).unwrap().is_all());
+        assert!(!StageMask::parse("").unwrap().ocr);
+    }
+}
@@ -0,0 +1,187 @@
+use s3::creds::Credentials;
+use s3::{Bucket, Region};
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::error::{Result, WorkerError};
+use crate::result::ProcessResult;
+use crate::video::{FrameData, FrameFormat, PreviewFormat};
+
+/// S3-compatible object storage for frames and results
+pub struct ObjectStore {
+    bucket: Bucket,
+    prefix: String,
+}
+
+impl ObjectStore {
+    /// Build from S3_BUCKET / S3_ENDPOINT / S3_REGION / S3_ACCESS_KEY_ID /
+    /// S3_SECRET_ACCESS_KEY / S3_PREFIX. Returns None when S3_BUCKET is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let bucket_name = match std::env::var("S3_BUCKET") {
+            Ok(name) if !name.is_empty() => name,
+            _ => return Ok(None),
+        };
+
+        let region_name = std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let region = match std::env::var("S3_ENDPOINT") {
+            Ok(endpoint) if !endpoint.is_empty() => Region::Custom {
+                region: region_name,
+                endpoint,
+            },
+            _ => region_name
+                .parse()
+                .map_err(|e| WorkerError::Storage(format!("invalid S3_REGION: {}", e)))?,
+        };
+
+        let credentials = Credentials::new(
+            std::env::var("S3_ACCESS_KEY_ID").ok().as_deref(),
+            std::env::var("S3_SECRET_ACCESS_KEY").ok().as_deref(),
+            None,
+            None,
+            None,
+        )
+        .map_err(|e| WorkerError::Storage(format!("invalid S3 credentials: {}", e)))?;
+
+        // Path-style addressing works with MinIO and most S3-compatible stores
+        let bucket = Bucket::new(&bucket_name, region, credentials)
+            .map_err(|e| WorkerError::Storage(format!("failed to configure S3 bucket: {}", e)))?
+            .with_path_style();
+
+        let prefix = std::env::var("S3_PREFIX")
+            .map(|p| p.trim_matches('/').to_string())
+            .unwrap_or_default();
+
+        info!("S3 upload enabled: bucket={}", bucket_name);
+
+        Ok(Some(Self { bucket, prefix }))
+    }
+
+    fn key(&self, job_id: &str, name: &str) -> String {
+        if self.prefix.is_empty() {
+            format!("{}/{}", job_id, name)
+        } else {
+            format!("{}/{}/{}", self.prefix, job_id, name)
+        }
+    }
+
+    /// Upload raw bytes and return the object URL
+    pub async fn upload_bytes(
+        &self,
+        job_id: &str,
+        name: &str,
+        content: &[u8],
+        content_type: &str,
+    ) -> Result<String> {
+        let key = self.key(job_id, name);
+
+        let response = self
+            .bucket
+            .put_object_with_content_type(&key, content, content_type)
+            .await
+            .map_err(|e| WorkerError::Storage(format!("failed to upload {}: {}", key, e)))?;
+
+        if !(200..300).contains(&response.status_code()) {
+            return Err(WorkerError::Storage(format!(
+                "upload of {} returned status {}",
+                key,
+                response.status_code()
+            )));
+        }
+
+        Ok(format!("{}/{}", self.bucket.url(), key))
+    }
+
+    /// Upload a local file and return the object URL
+    pub async fn upload_file(&self, job_id: &str, path: &Path, content_type: &str) -> Result<String> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| WorkerError::Storage(format!("{:?} has no file name", path)))?
+            .to_string_lossy()
+            .to_string();
+        let content = tokio::fs::read(path).await?;
+
+        self.upload_bytes(job_id, &name, &content, content_type).await
+    }
+
+    /// Upload frame images and point each frame_path at its object URL.
+    /// Frames that fail to upload keep their local path.
+    pub async fn upload_frames(&self, job_id: &str, frames: &mut [FrameData]) {
+        let mut uploaded = 0;
+
+        for frame in frames.iter_mut() {
+            let path = Path::new(&frame.frame_path).to_path_buf();
+            let content_type = FrameFormat::from_path(&path).unwrap_or_default().mime_type();
+            match self.upload_file(job_id, &path, content_type).await {
+                Ok(url) => {
+                    frame.frame_path = url;
+                    uploaded += 1;
+                }
+                Err(e) => warn!("Failed to upload frame {}: {}", frame.frame_path, e),
+            }
+
+            // Thumbnails share their frame's file name, so they get their own prefix
+            if let Some(thumb) = frame.thumb_path.clone() {
+                let thumb_upload = match (tokio::fs::read(&thumb).await, Path::new(&thumb).file_name()) {
+                    (Ok(content), Some(name)) => {
+                        let name = format!("thumbs/{}", name.to_string_lossy());
+                        self.upload_bytes(job_id, &name, &content, content_type).await
+                    }
+                    (Err(e), _) => Err(e.into()),
+                    (_, None) => Err(WorkerError::Storage(format!("{:?} has no file name", thumb))),
+                };
+                match thumb_upload {
+                    Ok(url) => frame.thumb_path = Some(url),
+                    Err(e) => warn!("Failed to upload frame thumbnail {}: {}", thumb, e),
+                }
+            }
+        }
+
+        info!("Uploaded {}/{} frames to S3", uploaded, frames.len());
+    }
+
+    /// Upload frames, thumbnail, preview, and captions, rewriting their paths to object URLs
+    pub async fn upload_assets(&self, result: &mut ProcessResult) {
+        let job_id = result.job_id.clone();
+        let local_paths: Vec<String> = result.frames.iter().map(|f| f.frame_path.clone()).collect();
+        self.upload_frames(&job_id, &mut result.frames).await;
+
+        // Steps reference frames by path; point them at the same objects
+        let urls: HashMap<String, String> = local_paths
+            .into_iter()
+            .zip(result.frames.iter().map(|f| f.frame_path.clone()))
+            .collect();
+        for step in &mut result.steps {
+            if let Some(url) = step.frame_path.as_ref().and_then(|path| urls.get(path)) {
+                step.frame_path = Some(url.clone());
+            }
+        }
+
+        // One of the frames, so in FRAME_FORMAT
+        if let Some(path) = result.thumbnail_path.clone() {
+            let content_type = FrameFormat::from_path(Path::new(&path)).unwrap_or_default().mime_type();
+            match self.upload_file(&job_id, Path::new(&path), content_type).await {
+                Ok(url) => result.thumbnail_path = Some(url),
+                Err(e) => warn!("Failed to upload thumbnail {}: {}", path, e),
+            }
+        }
+
+        if let Some(path) = result.preview_path.clone() {
+            let format = Path::new(&path)
+                .extension()
+                .and_then(|ext| PreviewFormat::parse(&ext.to_string_lossy()))
+                .unwrap_or(PreviewFormat::Gif);
+            match self.upload_file(&job_id, Path::new(&path), format.mime_type()).await {
+                Ok(url) => result.preview_path = Some(url),
+                Err(e) => warn!("Failed to upload preview {}: {}", path, e),
+            }
+        }
+
+        if let Some(path) = result.vtt_path.clone() {
+            match self.upload_file(&job_id, Path::new(&path), "text/vtt").await {
+                Ok(url) => result.vtt_path = Some(url),
+                Err(e) => warn!("Failed to upload captions {}: {}", path, e),
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::Result;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Install the tracing subscriber.
+///
+/// RUST_LOG picks what is recorded (and exported), defaulting to INFO.
+/// LOG_FORMAT=json emits one JSON object per line, including the fields of
+/// the enclosing spans (such as job_id); the default is human-readable text.
+/// Logs go to stderr so stdout stays free for command output such as NDJSON.
+///
+/// With the `otel` feature and OTEL_EXPORTER_OTLP_ENDPOINT set, spans are
+/// also exported to an OTLP collector over gRPC.
+pub fn init() -> Result<()> {
+    let json = std::env::var("LOG_FORMAT")
+        .map(|f| f.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+
+    // Only one of these is Some; Option<Layer> is a no-op when None
+    let (text_layer, json_layer) = if json {
+        let layer = tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .json()
+            .with_current_span(true)
+            .with_span_list(true);
+        (None, Some(layer))
+    } else {
+        (Some(tracing_subscriber::fmt::layer().with_writer(std::io::stderr)), None)
+    };
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(text_layer)
+        .with(json_layer);
+
+    #[cfg(feature = "otel")]
+    if let Some(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|e| !e.is_empty())
+    {
+        registry.with(otel::layer(&endpoint)?).init();
+        tracing::info!("Exporting traces to {}", endpoint);
+        return Ok(());
+    }
+
+    registry.init();
+    Ok(())
+}
+
+/// Flush any buffered spans before exit
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use anyhow::Result;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{runtime, trace, Resource};
+    use tracing_subscriber::registry::LookupSpan;
+
+    pub fn layer<S>(endpoint: &str) -> Result<impl tracing_subscriber::Layer<S>>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let service_name =
+            std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "worker-rust".to_string());
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_trace_config(
+                trace::config()
+                    .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)])),
+            )
+            .install_batch(runtime::Tokio)?;
+
+        Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+}
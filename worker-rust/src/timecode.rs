@@ -0,0 +1,32 @@
+//! Timestamps as people read them, shared by frame metadata and captions.
+
+/// Seconds as `HH:MM:SS.mmm`, rounded to the millisecond. This is also the
+/// WebVTT cue timing format. Hours don't wrap past 99, and negative or NaN
+/// input reads as zero.
+pub fn format_timestamp(seconds: f64) -> String {
+    let total_ms = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        total_ms / 3_600_000,
+        total_ms / 60_000 % 60,
+        total_ms / 1000 % 60,
+        total_ms % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_format_as_hours_minutes_seconds() {
+        assert_eq!(format_timestamp(0.0), "00:00:00.000");
+        assert_eq!(format_timestamp(0.25), "00:00:00.250");
+        // Rounds up into the next second rather than printing 60 seconds
+        assert_eq!(format_timestamp(59.9996), "00:01:00.000");
+        assert_eq!(format_timestamp(3_723.5), "01:02:03.500");
+        assert_eq!(format_timestamp(100.0 * 3600.0 + 1.0), "100:00:01.000");
+        assert_eq!(format_timestamp(-2.0), "00:00:00.000");
+        assert_eq!(format_timestamp(f64::NAN), "00:00:00.000");
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::warn;
+
+/// Commands used for the external tools
+#[derive(Debug, Clone)]
+pub struct Binaries {
+    pub ytdlp: String,
+    pub gallery_dl: String,
+    pub ffmpeg: String,
+    pub ffprobe: String,
+    pub whisper: String,
+}
+
+impl Default for Binaries {
+    /// Look everything up on PATH
+    fn default() -> Self {
+        Self {
+            ytdlp: "yt-dlp".to_string(),
+            gallery_dl: "gallery-dl".to_string(),
+            ffmpeg: "ffmpeg".to_string(),
+            ffprobe: "ffprobe".to_string(),
+            whisper: "whisper".to_string(),
+        }
+    }
+}
+
+static BINARIES: OnceLock<Binaries> = OnceLock::new();
+
+/// Set the tool paths for the rest of the process, from DLP_BINARY,
+/// GALLERY_DL_BINARY, FFMPEG_BINARY, FFPROBE_BINARY and WHISPER_BINARY via
+/// the config. Only the first call counts; without one the tools are looked
+/// up on PATH.
+pub fn configure(binaries: Binaries) {
+    let _ = BINARIES.set(binaries);
+}
+
+fn binaries() -> &'static Binaries {
+    BINARIES.get_or_init(Binaries::default)
+}
+
+/// Command for yt-dlp
+pub fn ytdlp() -> String {
+    binaries().ytdlp.clone()
+}
+
+/// Command for gallery-dl
+pub fn gallery_dl() -> String {
+    binaries().gallery_dl.clone()
+}
+
+/// Command for ffmpeg
+pub fn ffmpeg() -> String {
+    binaries().ffmpeg.clone()
+}
+
+/// Command for ffprobe
+pub fn ffprobe() -> String {
+    binaries().ffprobe.clone()
+}
+
+/// Command for the whisper CLI
+pub fn whisper() -> String {
+    binaries().whisper.clone()
+}
+
+/// Runs tried before a transient failure is handed back
+const TRANSIENT_ATTEMPTS: u32 = 3;
+
+/// Pause between runs; long enough for a network volume to catch up
+const TRANSIENT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Lowercased ffmpeg/ffprobe errors seen when a just-written file isn't
+/// fully visible yet. Format errors such as "invalid data found when
+/// processing input" or "moov atom not found" (also what a truncated MP4
+/// gives) are deliberately not here; rerunning can't fix them.
+const TRANSIENT_PATTERNS: &[&str] = &[
+    "no such file or directory",
+    "resource temporarily unavailable",
+    "stale file handle",
+];
+
+/// Whether a failed run's stderr points at a filesystem race rather than
+/// at the file itself
+pub fn is_transient_failure(stderr: &[u8]) -> bool {
+    let stderr = String::from_utf8_lossy(stderr).to_lowercase();
+    TRANSIENT_PATTERNS.iter().any(|p| stderr.contains(p))
+}
+
+/// Like `Command::output`, but reruns `command` after a short delay while it
+/// fails with a transient error, up to TRANSIENT_ATTEMPTS runs. The last
+/// run's output is returned either way, so callers handle failure as before.
+pub async fn output_retrying(command: &mut tokio::process::Command) -> std::io::Result<std::process::Output> {
+    let mut attempt = 1;
+    loop {
+        let output = command.output().await?;
+        if output.status.success() || attempt >= TRANSIENT_ATTEMPTS || !is_transient_failure(&output.stderr) {
+            return Ok(output);
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        warn!(
+            "{} failed transiently (attempt {}/{}), retrying: {}",
+            command.as_std().get_program().to_string_lossy(),
+            attempt,
+            TRANSIENT_ATTEMPTS,
+            stderr.trim().lines().last().unwrap_or("")
+        );
+        tokio::time::sleep(TRANSIENT_RETRY_DELAY).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_filesystem_races_are_transient() {
+        assert!(is_transient_failure(b"/mnt/jobs/abc/video.mp4: No such file or directory"));
+        assert!(!is_transient_failure(b"[mov,mp4 @ 0x55d0] moov atom not found\n/data/job/video.mp4: Invalid data found when processing input"));
+        assert!(!is_transient_failure(b"/data/job/video.mp4: Invalid data found when processing input"));
+        assert!(!is_transient_failure(b"Decoder (codec av1) not found for input stream #0:0"));
+    }
+}
@@ -0,0 +1,316 @@
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use std::future::Future;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::audio::{self, TranscriptSegment};
+use crate::error::{Result, WorkerError};
+use crate::tools;
+
+/// Deepgram's pre-recorded audio endpoint
+const DEEPGRAM_URL: &str = "https://api.deepgram.com/v1/listen";
+
+/// Upper bound on one cloud transcription request; reels are short, but the
+/// audio is uploaded whole
+const HTTP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Turns an extracted `audio.wav` into timed speech segments.
+///
+/// Implementations can use `async fn`; the future must be Send so callers
+/// can run transcription on a spawned task.
+pub trait Transcriber {
+    fn transcribe(&self, audio_path: &str) -> impl Future<Output = Result<Vec<TranscriptSegment>>> + Send;
+}
+
+/// Whisper's own decoding defaults: 5 beams at temperature 0, and 5 samples
+/// per step when a segment falls back to higher temperatures
+const DEFAULT_BEAM_SIZE: u32 = 5;
+const DEFAULT_BEST_OF: u32 = 5;
+const DEFAULT_TEMPERATURE_INCREMENT: f64 = 0.2;
+
+/// The local `whisper` CLI. A missing or failing CLI yields no segments
+/// rather than an error, so jobs still complete without a transcript.
+#[derive(Debug, Clone)]
+pub struct WhisperCli {
+    /// Beams searched at temperature 0; more beams are slower but more
+    /// accurate on noisy or accented speech
+    pub beam_size: u32,
+    /// Candidates sampled at each non-zero temperature
+    pub best_of: u32,
+    /// Starting temperature; 0 decodes deterministically
+    pub temperature: f64,
+    /// Added to the temperature when a segment fails Whisper's compression
+    /// or log-probability checks; None never retries a segment
+    pub temperature_increment: Option<f64>,
+}
+
+impl Default for WhisperCli {
+    fn default() -> Self {
+        Self {
+            beam_size: DEFAULT_BEAM_SIZE,
+            best_of: DEFAULT_BEST_OF,
+            temperature: 0.0,
+            temperature_increment: Some(DEFAULT_TEMPERATURE_INCREMENT),
+        }
+    }
+}
+
+impl WhisperCli {
+    /// Read WHISPER_BEAM_SIZE, WHISPER_BEST_OF, WHISPER_TEMPERATURE, and
+    /// WHISPER_TEMPERATURE_INCREMENT (0 disables temperature fallback)
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Self::default();
+
+        let beam_size = env_number("WHISPER_BEAM_SIZE")?.unwrap_or(defaults.beam_size);
+        if beam_size == 0 {
+            bail!("WHISPER_BEAM_SIZE must be at least 1");
+        }
+        let best_of = env_number("WHISPER_BEST_OF")?.unwrap_or(defaults.best_of);
+        if best_of == 0 {
+            bail!("WHISPER_BEST_OF must be at least 1");
+        }
+        let temperature = env_number("WHISPER_TEMPERATURE")?.unwrap_or(defaults.temperature);
+        if !(0.0..=1.0).contains(&temperature) {
+            bail!("WHISPER_TEMPERATURE must be between 0 and 1");
+        }
+        let temperature_increment = match env_number::<f64>("WHISPER_TEMPERATURE_INCREMENT")? {
+            None => defaults.temperature_increment,
+            Some(0.0) => None,
+            Some(step) if step > 0.0 && step <= 1.0 => Some(step),
+            Some(_) => bail!("WHISPER_TEMPERATURE_INCREMENT must be between 0 and 1"),
+        };
+
+        Ok(Self { beam_size, best_of, temperature, temperature_increment })
+    }
+}
+
+/// Parse `key` if it is set and non-empty
+fn env_number<T: std::str::FromStr>(key: &str) -> anyhow::Result<Option<T>> {
+    match std::env::var(key).ok().filter(|v| !v.trim().is_empty()) {
+        Some(v) => match v.trim().parse() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => bail!("{} must be a number, got '{}'", key, v),
+        },
+        None => Ok(None),
+    }
+}
+
+impl Transcriber for WhisperCli {
+    async fn transcribe(&self, audio_path: &str) -> Result<Vec<TranscriptSegment>> {
+        // Whisper names its output after the input file, in --output_dir
+        let audio_dir = Path::new(audio_path)
+            .parent()
+            .and_then(|p| p.to_str())
+            .filter(|p| !p.is_empty())
+            .unwrap_or(".");
+
+        let output = tokio::process::Command::new(tools::whisper())
+            .kill_on_drop(true)
+            .args(&[
+                audio_path,
+                "--model", "base",
+                "--language", "en",
+                "--output_format", "json",
+                "--output_dir", audio_dir,
+            ])
+            .args(&["--beam_size", &self.beam_size.to_string()])
+            .args(&["--best_of", &self.best_of.to_string()])
+            .args(&["--temperature", &self.temperature.to_string()])
+            // The CLI takes the literal "None" to turn fallback off
+            .args(&[
+                "--temperature_increment_on_fallback",
+                &self.temperature_increment.map_or("None".to_string(), |s| s.to_string()),
+            ])
+            .output()
+            .await;
+
+        match output {
+            Ok(output) if output.status.success() => match audio::load_whisper_output(audio_path)? {
+                Some(transcript) => return Ok(transcript.segments),
+                None => warn!("Whisper exited cleanly but wrote no transcript"),
+            },
+            Ok(_) => warn!("Whisper transcription failed"),
+            Err(e) => warn!("Whisper not available: {}", e),
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// Deepgram's speech-to-text API (TRANSCRIBE_BACKEND=deepgram)
+pub struct DeepgramTranscriber {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+}
+
+impl DeepgramTranscriber {
+    /// Build from TRANSCRIBE_API_KEY and optional TRANSCRIBE_URL (for a
+    /// self-hosted or proxied endpoint)
+    pub fn from_env() -> anyhow::Result<Self> {
+        let api_key = std::env::var("TRANSCRIBE_API_KEY")
+            .ok()
+            .filter(|k| !k.is_empty())
+            .context("TRANSCRIBE_API_KEY must be set for the deepgram backend")?;
+        let url = std::env::var("TRANSCRIBE_URL")
+            .ok()
+            .filter(|u| !u.is_empty())
+            .unwrap_or_else(|| DEEPGRAM_URL.to_string());
+
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .context("Failed to build transcription HTTP client")?;
+
+        Ok(Self { client, url, api_key })
+    }
+}
+
+impl Transcriber for DeepgramTranscriber {
+    async fn transcribe(&self, audio_path: &str) -> Result<Vec<TranscriptSegment>> {
+        let audio = tokio::fs::read(audio_path).await?;
+
+        let response = self
+            .client
+            .post(&self.url)
+            // Utterances are Deepgram's equivalent of Whisper segments
+            .query(&[("utterances", "true"), ("punctuate", "true"), ("language", "en")])
+            .header("Authorization", format!("Token {}", self.api_key))
+            .header("Content-Type", "audio/wav")
+            .body(audio)
+            .send()
+            .await
+            .map_err(|e| WorkerError::Transcription(format!("deepgram request failed: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(WorkerError::Transcription(format!("deepgram returned {}: {}", status, body)));
+        }
+
+        let body = response
+            .text()
+            .await
+            .map_err(|e| WorkerError::Transcription(format!("failed to read deepgram response: {}", e)))?;
+        parse_deepgram_response(&body)
+    }
+}
+
+/// Segments from a Deepgram response requested with `utterances=true`
+fn parse_deepgram_response(raw: &str) -> Result<Vec<TranscriptSegment>> {
+    #[derive(Deserialize)]
+    struct Response {
+        results: Results,
+    }
+    #[derive(Deserialize)]
+    struct Results {
+        #[serde(default)]
+        utterances: Vec<Utterance>,
+    }
+    #[derive(Deserialize)]
+    struct Utterance {
+        start: f64,
+        end: f64,
+        transcript: String,
+    }
+
+    let parsed: Response = serde_json::from_str(raw)
+        .map_err(|e| WorkerError::Transcription(format!("invalid deepgram response: {}", e)))?;
+
+    Ok(parsed
+        .results
+        .utterances
+        .into_iter()
+        .map(|u| TranscriptSegment {
+            start: u.start,
+            end: u.end,
+            text: u.transcript.trim().to_string(),
+            no_speech_prob: None,
+            avg_logprob: None,
+        })
+        .filter(|s| !s.text.is_empty())
+        .collect())
+}
+
+/// Transcription backend chosen at startup
+pub enum Backend {
+    Whisper(WhisperCli),
+    Deepgram(DeepgramTranscriber),
+}
+
+impl Backend {
+    /// Read TRANSCRIBE_BACKEND: "whisper" (default) or "deepgram"
+    pub fn from_env() -> anyhow::Result<Self> {
+        let name = std::env::var("TRANSCRIBE_BACKEND").unwrap_or_default().trim().to_lowercase();
+        let backend = match name.as_str() {
+            "" | "whisper" => Backend::Whisper(WhisperCli::from_env()?),
+            "deepgram" => Backend::Deepgram(DeepgramTranscriber::from_env()?),
+            other => bail!("unknown TRANSCRIBE_BACKEND '{}' (expected whisper or deepgram)", other),
+        };
+        info!("Transcription backend: {}", backend.name());
+        Ok(backend)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Whisper(_) => "whisper",
+            Backend::Deepgram(_) => "deepgram",
+        }
+    }
+}
+
+impl Transcriber for Backend {
+    async fn transcribe(&self, audio_path: &str) -> Result<Vec<TranscriptSegment>> {
+        match self {
+            Backend::Whisper(whisper) => whisper.transcribe(audio_path).await,
+            Backend::Deepgram(deepgram) => deepgram.transcribe(audio_path).await,
+        }
+    }
+}
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Set the backend for the rest of the process. Only the first call counts;
+/// without one the backend is read from the environment on first use.
+pub fn configure(backend: Backend) {
+    let _ = BACKEND.set(backend);
+}
+
+/// The configured backend, falling back to Whisper if the environment names
+/// one that can't be built
+pub fn backend() -> &'static Backend {
+    BACKEND.get_or_init(|| {
+        Backend::from_env().unwrap_or_else(|e| {
+            warn!("{:#}, using whisper", e);
+            Backend::Whisper(WhisperCli::default())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deepgram_utterances_become_segments() {
+        let raw = r#"{"results": {"utterances": [
+            {"start": 0.5, "end": 2.0, "transcript": " Chop the onions. "},
+            {"start": 2.0, "end": 2.4, "transcript": "  "},
+            {"start": 2.4, "end": 5.1, "transcript": "Fry them in butter."}
+        ]}}"#;
+        let segments = parse_deepgram_response(raw).unwrap();
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].text, "Chop the onions.");
+        assert_eq!((segments[1].start, segments[1].end), (2.4, 5.1));
+        assert!(segments[0].no_speech_prob.is_none());
+    }
+
+    #[test]
+    fn deepgram_response_without_utterances_is_empty() {
+        assert!(parse_deepgram_response(r#"{"results": {}}"#).unwrap().is_empty());
+        assert!(parse_deepgram_response(r#"{"error": "bad key"}"#).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::{Context, Result};
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::download::{self, Section};
+use crate::error::WorkerError;
+use crate::pipeline;
+use crate::preflight;
+use crate::redis_conn;
+use crate::result::AiJob;
+use crate::stage::StageMask;
+
+/// A video job as carried by any transport
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub job_id: String,
+    pub url: String,
+    /// The full message, passed through to failure records
+    pub data: Value,
+}
+
+impl Job {
+    /// Parse the `{"job_id": ..., "url": ...}` payload shared by all transports
+    pub fn from_value(data: Value) -> Result<Self> {
+        let job_id = data["job_id"].as_str().context("Job ID not found")?.to_string();
+        let url = data["url"].as_str().context("URL not found")?.to_string();
+        Ok(Self { job_id, url, data })
+    }
+}
+
+/// Where jobs come from. Messages are only acknowledged after processing,
+/// so a crash mid-job leads to redelivery.
+// Only driven from `run` on the current task, so the futures needn't be Send
+#[allow(async_fn_in_trait)]
+pub trait JobSource {
+    /// Transport handle identifying a received message
+    type Receipt;
+
+    /// Wait briefly for the next job; None when nothing arrived
+    async fn next_job(&mut self) -> Result<Option<(Job, Self::Receipt)>>;
+
+    /// Mark the message as done so it is not delivered again
+    async fn ack(&mut self, receipt: Self::Receipt) -> Result<()>;
+
+    /// Arrange for the message to be delivered again
+    async fn retry(&mut self, receipt: Self::Receipt) -> Result<()>;
+}
+
+/// Where finished and failed jobs are published
+#[allow(async_fn_in_trait)]
+pub trait ResultSink {
+    async fn publish(&self, job: &AiJob) -> Result<()>;
+
+    async fn publish_failure(&self, job: &Job, error: &WorkerError) -> Result<()>;
+}
+
+/// How video_data is encoded on the AI queue
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PayloadFormat {
+    /// A JSON string, readable by every consumer
+    #[default]
+    Json,
+    /// MessagePack bytes: smaller, and faster to parse for large
+    /// transcripts and frame lists
+    MessagePack,
+}
+
+impl PayloadFormat {
+    /// Read AI_QUEUE_FORMAT: "json" (default) or "msgpack". Applies to
+    /// RedisResultSink only; KafkaSink publishes JSON regardless.
+    pub fn from_env() -> Result<Self> {
+        let name = std::env::var("AI_QUEUE_FORMAT").unwrap_or_default().trim().to_lowercase();
+        let format = match name.as_str() {
+            "" | "json" => PayloadFormat::Json,
+            "msgpack" | "messagepack" => PayloadFormat::MessagePack,
+            other => anyhow::bail!("unknown AI_QUEUE_FORMAT '{}' (expected json or msgpack)", other),
+        };
+        if format != PayloadFormat::Json {
+            info!("AI queue payloads encoded as {}", format.name());
+        }
+        Ok(format)
+    }
+
+    /// Value of the `video_data_format` stream field
+    pub fn name(&self) -> &'static str {
+        match self {
+            PayloadFormat::Json => "json",
+            PayloadFormat::MessagePack => "msgpack",
+        }
+    }
+
+    pub fn encode<T: Serialize + ?Sized>(&self, video_data: &T) -> Result<Vec<u8>> {
+        Ok(match self {
+            PayloadFormat::Json => serde_json::to_vec(video_data)?,
+            PayloadFormat::MessagePack => rmp_serde::to_vec_named(video_data)?,
+        })
+    }
+
+    /// Inverse of `encode`
+    #[cfg(test)]
+    pub fn decode(&self, bytes: &[u8]) -> Result<Value> {
+        Ok(match self {
+            PayloadFormat::Json => serde_json::from_slice(bytes)?,
+            PayloadFormat::MessagePack => rmp_serde::from_slice(bytes)?,
+        })
+    }
+}
+
+/// The Redis AI queue and dead-letter stream
+pub struct RedisResultSink {
+    conn: ConnectionManager,
+    format: PayloadFormat,
+}
+
+impl RedisResultSink {
+    pub fn new(conn: ConnectionManager, format: PayloadFormat) -> Self {
+        Self { conn, format }
+    }
+}
+
+impl ResultSink for RedisResultSink {
+    /// One stream field per AiJob field, with video_data encoded in the
+    /// configured format. Non-JSON payloads add a `video_data_format` field
+    /// naming it; JSON messages keep their original layout.
+    async fn publish(&self, job: &AiJob) -> Result<()> {
+        let mut cmd = redis::cmd("XADD");
+        cmd.arg("queue:ai_processing")
+            .arg("*")
+            .arg("schema_version")
+            .arg(job.schema_version)
+            .arg("job_id")
+            .arg(&job.job_id)
+            .arg("source_url")
+            .arg(&job.source_url)
+            .arg("enqueued_at")
+            .arg(&job.enqueued_at);
+        if let Some(prior_id) = &job.duplicate_of {
+            cmd.arg("duplicate_of").arg(prior_id);
+        }
+        if self.format != PayloadFormat::Json {
+            cmd.arg("video_data_format").arg(self.format.name());
+        }
+        let _: String = cmd
+            .arg("video_data")
+            .arg(self.format.encode(&job.video_data)?)
+            .query_async(&mut self.conn.clone())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn publish_failure(&self, job: &Job, error: &WorkerError) -> Result<()> {
+        let _: String = redis::cmd("XADD")
+            .arg(crate::worker::DEAD_LETTER_STREAM)
+            .arg("*")
+            .arg("job_id")
+            .arg(&job.job_id)
+            .arg("data")
+            .arg(job.data.to_string())
+            .arg("error_kind")
+            .arg(error.kind())
+            .arg("error")
+            .arg(error.to_string())
+            .arg("failed_at")
+            .arg(chrono::Utc::now().to_rfc3339())
+            .query_async(&mut self.conn.clone())
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Process jobs from `source` until `shutdown`, publishing to `sink`.
+///
+/// This is the transport-neutral loop used by non-Redis transports; the
+/// Redis worker adds job status keys, locking, and cancellation on top.
+/// Transient failures are retried `config.max_job_retries` times; anything else is
+/// published as a failure and acknowledged.
+pub async fn run<S, K>(
+    source: &mut S,
+    sink: &K,
+    output_dir: &str,
+    config: &Config,
+    shutdown: &CancellationToken,
+) -> Result<()>
+where
+    S: JobSource,
+    K: ResultSink,
+{
+    std::fs::create_dir_all(output_dir)?;
+    let max_attempts = config.max_job_retries + 1;
+    let mut attempts: HashMap<String, u64> = HashMap::new();
+    let mut disk = preflight::DiskGuard::from_env();
+
+    while !shutdown.is_cancelled() {
+        if !disk.has_room(std::path::Path::new(output_dir)) {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(preflight::DISK_RECHECK_INTERVAL) => continue,
+            }
+        }
+
+        let next = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            next = source.next_job() => next,
+        };
+
+        let (job, receipt) = match next {
+            Ok(Some(next)) => next,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Failed to receive job: {}", e);
+                tokio::time::sleep(redis_conn::with_jitter(Duration::from_secs(5))).await;
+                continue;
+            }
+        };
+
+        info!("Processing job {}: {}", job.job_id, job.url);
+
+        let parsed = StageMask::from_job(&job.data)
+            .and_then(|stages| Ok((stages, Section::from_job(&job.data)?)));
+        let outcome = match parsed {
+            Ok((stages, section)) => {
+                let ctx = pipeline::JobContext {
+                    job_id: &job.job_id,
+                    output_dir,
+                    config,
+                    stages,
+                    progress: &pipeline::log_progress,
+                    events: &pipeline::ignore_events,
+                };
+                pipeline::cancellable(shutdown, pipeline::process(&ctx, &job.url, section)).await
+            }
+            Err(reason) => Err(WorkerError::InvalidJob(reason)),
+        };
+
+        match outcome {
+            Ok(result) => {
+                let ai_job = AiJob::new(&job.job_id, &job.url, result);
+                // A broker hiccup shouldn't stop the worker; redeliver the job
+                if let Err(e) = sink.publish(&ai_job).await {
+                    error!("Failed to publish result for job {}, retrying: {}", job.job_id, e);
+                    source.retry(receipt).await?;
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+                source.ack(receipt).await?;
+                attempts.remove(&job.job_id);
+                info!("Job {} published", job.job_id);
+            }
+            Err(WorkerError::Cancelled) => {
+                // Unacknowledged, so it is picked up again after restart
+                warn!("Job {} interrupted by shutdown", job.job_id);
+                break;
+            }
+            Err(e) => {
+                let attempt = attempts.entry(job.job_id.clone()).or_insert(0);
+                *attempt += 1;
+
+                if e.is_transient() && *attempt < max_attempts {
+                    warn!(
+                        "Job {} failed transiently (attempt {}/{}), retrying: {}",
+                        job.job_id, attempt, max_attempts, e
+                    );
+                    source.retry(receipt).await?;
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                } else {
+                    error!("Job {} failed permanently ({}): {}", job.job_id, e.kind(), e);
+                    download::remove_partial_downloads(&pipeline::job_dir(output_dir, &job.job_id));
+                    if let Err(publish_error) = sink.publish_failure(&job, &e).await {
+                        error!("Failed to publish failure for job {}, retrying: {}", job.job_id, publish_error);
+                        source.retry(receipt).await?;
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        continue;
+                    }
+                    source.ack(receipt).await?;
+                    attempts.remove(&job.job_id);
+                }
+            }
+        }
+    }
+
+    info!("Transport loop stopped");
+
+    Ok(())
+}
+
+/// Failure record published by transports that carry results as messages
+#[cfg(feature = "kafka")]
+pub fn failure_payload(job: &Job, error: &WorkerError) -> Value {
+    serde_json::json!({
+        "job_id": job.job_id,
+        "status": "failed",
+        "error_kind": error.kind(),
+        "error_message": error.to_string(),
+        "data": job.data,
+        "failed_at": chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn video_data() -> Value {
+        json!({
+            "schema_version": 1,
+            "job_id": "abc",
+            "video_info": { "duration_seconds": 31.52, "width": 1080, "height": 1920, "codec": "h264" },
+            "frames": [
+                { "timestamp": 0.4, "frame_path": "frames/frame_400.jpg", "ocr_text": "2 cups flour", "is_keyframe": true },
+                { "timestamp": 2.0, "frame_path": "frames/regular_2000.jpg", "is_keyframe": false },
+            ],
+            "transcription": "Mix it all",
+            "transcript_segments_filtered": 0,
+            "thumbnail_path": null,
+        })
+    }
+
+    #[test]
+    fn json_payload_round_trips() {
+        let encoded = PayloadFormat::Json.encode(&video_data()).unwrap();
+        assert_eq!(PayloadFormat::Json.decode(&encoded).unwrap(), video_data());
+        // Same bytes the queue carried before formats were configurable
+        assert_eq!(encoded, video_data().to_string().into_bytes());
+    }
+
+    #[test]
+    fn msgpack_payload_round_trips_smaller() {
+        let encoded = PayloadFormat::MessagePack.encode(&video_data()).unwrap();
+        assert_eq!(PayloadFormat::MessagePack.decode(&encoded).unwrap(), video_data());
+        assert!(encoded.len() < PayloadFormat::Json.encode(&video_data()).unwrap().len());
+    }
+}
@@ -1,73 +1,530 @@
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use tracing::{info, warn};
+use tokio::sync::OnceCell;
+use tracing::{info, instrument, warn};
+
+use crate::error::{Result, WorkerError};
+use crate::ocr::OcrWord;
+use crate::timecode::format_timestamp;
+use crate::tools;
 
 /// Video metadata
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoInfo {
     pub duration_seconds: f64,
     pub width: u32,
     pub height: u32,
     pub fps: f64,
     pub codec: String,
+    /// Decoded pixel format, e.g. "yuv420p"; "unknown" if ffprobe couldn't tell
+    #[serde(default)]
+    pub pix_fmt: String,
+    /// Frames aren't evenly spaced (common for phone recordings); `fps` is
+    /// then the average rate rather than the nominal one
+    #[serde(default)]
+    pub variable_frame_rate: bool,
+    /// Container as ffprobe names it, e.g. "mov,mp4,m4a,3gp,3g2,mj2"
+    #[serde(default)]
+    pub container: Option<String>,
+    /// Overall bit rate in bits per second
+    #[serde(default)]
+    pub bit_rate: Option<u64>,
+    /// First audio stream; None for silent videos
+    #[serde(default)]
+    pub audio: Option<AudioStreamInfo>,
+    /// Every audio stream, in ffmpeg `0:a:N` order
+    #[serde(default)]
+    pub audio_streams: Vec<AudioStreamInfo>,
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
 }
 
-/// Process video and extract metadata
-pub async fn process_video(video_path: &str, output_dir: &str, job_id: &str) -> Result<VideoInfo> {
-    info!("Processing video: {}", video_path);
+/// Audio stream details from ffprobe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AudioStreamInfo {
+    pub codec: String,
+    pub channels: u32,
+    /// Hz
+    pub sample_rate: Option<u32>,
+    /// ISO 639 language tag from the container, if any
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+/// Chapter marker in the container, in seconds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: Option<String>,
+}
+
+/// ffprobe's `-show_format -show_streams -show_chapters` JSON.
+///
+/// ffprobe prints most numbers as strings, so those fields stay strings
+/// here and are parsed where they are used.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FfprobeOutput {
+    #[serde(default)]
+    pub format: FfprobeFormat,
+    #[serde(default)]
+    pub streams: Vec<FfprobeStream>,
+    #[serde(default)]
+    pub chapters: Vec<FfprobeChapter>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FfprobeFormat {
+    pub format_name: Option<String>,
+    pub duration: Option<String>,
+    pub bit_rate: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FfprobeStream {
+    pub index: Option<u32>,
+    /// "video", "audio", "subtitle", "data", ...
+    pub codec_type: Option<String>,
+    pub codec_name: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub pix_fmt: Option<String>,
+    pub r_frame_rate: Option<String>,
+    pub avg_frame_rate: Option<String>,
+    pub duration: Option<String>,
+    pub bit_rate: Option<String>,
+    pub sample_rate: Option<String>,
+    pub channels: Option<u32>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FfprobeChapter {
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+impl FfprobeOutput {
+    /// First stream of `codec_type`
+    pub fn first_stream(&self, codec_type: &str) -> Option<&FfprobeStream> {
+        self.streams
+            .iter()
+            .find(|s| s.codec_type.as_deref() == Some(codec_type))
+    }
+}
+
+/// Default cap on frames kept per video
+pub(crate) const DEFAULT_MAX_FRAMES: usize = 60;
+
+/// Regular frames this close to a keyframe are treated as duplicates
+pub const DEDUPE_DELTA_SECS: f64 = 0.5;
+
+/// ffmpeg `-q:v` for exported frames (2 is near-lossless)
+const DEFAULT_JPEG_QUALITY: u8 = 2;
+
+/// zlib level for PNG frames; the encoder's default (9) is several times
+/// slower for files only a few percent smaller
+const PNG_COMPRESSION_LEVEL: &str = "3";
+
+/// Image format exported frames are written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameFormat {
+    #[default]
+    Jpeg,
+    /// Lossless, so fine text reaches OCR without compression artifacts;
+    /// files are several times larger
+    Png,
+}
+
+impl FrameFormat {
+    /// "jpeg"/"jpg" or "png"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(FrameFormat::Jpeg),
+            "png" => Some(FrameFormat::Png),
+            _ => None,
+        }
+    }
     
-    // Use ffprobe to get video info
-    let output = tokio::process::Command::new("ffprobe")
-        .args(&[
-            "-v", "error",
-            "-select_streams", "v:0",
-            "-show_entries", "stream=width,height,r_frame_rate,codec_name",
-            "-show_entries", "format=duration",
-            "-of", "json",
-            video_path,
-        ])
-        .output()
-        .await
-        .context("Failed to execute ffprobe")?;
+    /// Format of an existing frame file, by extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "jpg" => Some(FrameFormat::Jpeg),
+            "png" => Some(FrameFormat::Png),
+            _ => None,
+        }
+    }
+    
+    /// File extension, which is also how ffmpeg picks the encoder
+    pub fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Jpeg => "jpg",
+            FrameFormat::Png => "png",
+        }
+    }
+    
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            FrameFormat::Jpeg => "image/jpeg",
+            FrameFormat::Png => "image/png",
+        }
+    }
+}
+
+/// How exported frames are encoded.
+///
+/// Frames feed both OCR and storage: downscaling makes OCR faster and uploads
+/// cheaper, but small on-screen text may stop being legible to Tesseract.
+/// The defaults keep full resolution at high quality.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameEncoding {
+    pub format: FrameFormat,
+    /// ffmpeg mjpeg quality, 2 (best) to 31 (smallest); unused for PNG
+    pub quality: u8,
+    /// Longest side in pixels; frames are never upscaled
+    pub max_dimension: Option<u32>,
+    /// Also write a copy no larger than this into `frames/thumbs/`, from the
+    /// same decode, for UIs that don't need full-size frames
+    pub thumb_dimension: Option<u32>,
+}
+
+impl Default for FrameEncoding {
+    fn default() -> Self {
+        Self {
+            format: FrameFormat::default(),
+            quality: DEFAULT_JPEG_QUALITY,
+            max_dimension: None,
+            thumb_dimension: None,
+        }
+    }
+}
+
+impl FrameEncoding {
+    /// Read FRAME_FORMAT, FRAME_JPEG_QUALITY, FRAME_MAX_DIMENSION and
+    /// FRAME_THUMB_DIMENSION
+    pub fn from_env() -> Self {
+        let format = match std::env::var("FRAME_FORMAT") {
+            Ok(name) if !name.trim().is_empty() => FrameFormat::parse(&name).unwrap_or_else(|| {
+                warn!("Unknown FRAME_FORMAT {:?}, using jpeg", name);
+                FrameFormat::Jpeg
+            }),
+            _ => FrameFormat::Jpeg,
+        };
+        
+        let quality = std::env::var("FRAME_JPEG_QUALITY")
+            .ok()
+            .and_then(|v| v.parse::<u8>().ok())
+            .map(|q| q.clamp(2, 31))
+            .unwrap_or(DEFAULT_JPEG_QUALITY);
+        
+        let dimension = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .filter(|d| *d > 0)
+        };
+        
+        Self {
+            format,
+            quality,
+            max_dimension: dimension("FRAME_MAX_DIMENSION"),
+            thumb_dimension: dimension("FRAME_THUMB_DIMENSION"),
+        }
+    }
+    
+    /// ffmpeg output option setting the encoder's quality/compression
+    fn codec_args(&self) -> [String; 2] {
+        match self.format {
+            FrameFormat::Jpeg => ["-q:v".to_string(), self.quality.to_string()],
+            FrameFormat::Png => ["-compression_level".to_string(), PNG_COMPRESSION_LEVEL.to_string()],
+        }
+    }
+    
+    /// Frame file name for `stem`, e.g. "frame_%04d" -> "frame_%04d.png"
+    fn file_name(&self, stem: &str) -> String {
+        format!("{}.{}", stem, self.format.extension())
+    }
+    
+    /// Scale filter (with trailing comma) fitting frames inside the max dimension
+    fn scale_filter(&self) -> String {
+        match self.max_dimension {
+            Some(max) => format!("{},", fit_filter(max)),
+            None => String::new(),
+        }
+    }
+    
+    /// ffmpeg arguments running `filter` and writing its frames to
+    /// `frames_dir/{stem}.ext`, with `per_output` options applying to each
+    /// file written. With a thumbnail size set, the filtered frames are split
+    /// and a scaled-down copy goes to `frames_dir/thumbs/` with the same
+    /// name, so both come from one decode.
+    fn output_args(&self, filter: &str, frames_dir: &Path, stem: &str, per_output: &[&str]) -> Vec<String> {
+        let [codec_flag, codec_value] = self.codec_args();
+        let file_name = self.file_name(stem);
+        let per_output: Vec<String> = per_output.iter().map(|a| a.to_string()).collect();
+        
+        let Some(thumb) = self.thumb_dimension else {
+            let mut args = vec!["-vf".to_string(), filter.to_string()];
+            args.extend(per_output);
+            args.extend([codec_flag, codec_value]);
+            args.push(frames_dir.join(&file_name).to_string_lossy().to_string());
+            return args;
+        };
+        
+        let mut args = vec![
+            "-filter_complex".to_string(),
+            format!("{},split=2[full][small];[small]{}[thumb]", filter, fit_filter(thumb)),
+        ];
+        for (label, path) in [
+            ("[full]", frames_dir.join(&file_name)),
+            ("[thumb]", frames_dir.join(THUMBS_DIR).join(&file_name)),
+        ] {
+            args.extend(["-map".to_string(), label.to_string()]);
+            args.extend(per_output.iter().cloned());
+            args.extend([codec_flag.clone(), codec_value.clone()]);
+            args.push(path.to_string_lossy().to_string());
+        }
+        args
+    }
+}
+
+/// Subdirectory of `frames/` holding the thumbnail-size copies
+const THUMBS_DIR: &str = "thumbs";
+
+/// Scale filter fitting frames inside `max` x `max` without upscaling
+fn fit_filter(max: u32) -> String {
+    format!(
+        "scale='min(iw,{max})':'min(ih,{max})':force_original_aspect_ratio=decrease",
+        max = max
+    )
+}
+
+/// Delete a frame's image and its thumbnail copy, if any
+fn remove_frame_files(frame: &FrameData) {
+    let _ = std::fs::remove_file(&frame.frame_path);
+    if let Some(thumb) = &frame.thumb_path {
+        let _ = std::fs::remove_file(thumb);
+    }
+}
+
+/// Everything ffprobe reports about the container, its streams, and chapters
+pub async fn probe(video_path: &str) -> Result<FfprobeOutput> {
+    let output = tools::output_retrying(
+        tokio::process::Command::new(tools::ffprobe())
+            .kill_on_drop(true)
+            .args(&[
+                "-v", "error",
+                "-show_format",
+                "-show_streams",
+                "-show_chapters",
+                "-of", "json",
+                video_path,
+            ]),
+    )
+    .await
+    .map_err(|e| WorkerError::Probe(format!("failed to execute ffprobe: {}", e)))?;
     
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("ffprobe failed: {}", stderr);
+        return Err(WorkerError::Probe(stderr.to_string()));
     }
     
-    let info: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| WorkerError::Probe(format!("invalid ffprobe output: {}", e)))
+}
+
+/// Process video and extract metadata
+#[instrument(skip_all, fields(job_id = %job_id))]
+pub async fn process_video(video_path: &str, job_id: &str) -> Result<VideoInfo> {
+    info!("Processing video: {}", video_path);
+    
+    let video_info = parse_probe_output(&probe(video_path).await?)?;
+    
+    info!("Video info: {:?}", video_info);
+    
+    check_decodable(&video_info).await?;
+    
+    Ok(video_info)
+}
+
+/// Build VideoInfo from ffprobe output, refusing to invent values for missing data
+fn parse_probe_output(probe: &FfprobeOutput) -> Result<VideoInfo> {
+    let stream = probe.first_stream("video").ok_or(WorkerError::NoVideoStream)?;
+    let format = &probe.format;
+    
+    let width = stream.width.unwrap_or(0);
+    let height = stream.height.unwrap_or(0);
+    if width == 0 || height == 0 {
+        return Err(WorkerError::Probe("video stream has no dimensions".to_string()));
+    }
     
-    let stream = info["streams"][0].clone();
-    let format = info["format"].clone();
+    // Container duration is most reliable; some muxers only set it per stream
+    let duration_seconds = [&format.duration, &stream.duration]
+        .iter()
+        .filter_map(|d| d.as_deref())
+        .filter_map(|d| d.parse::<f64>().ok())
+        .find(|d| *d > 0.0)
+        .ok_or_else(|| WorkerError::Probe("duration unavailable".to_string()))?;
     
     // Parse frame rate (e.g., "30/1" -> 30.0)
-    let fps_str = stream["r_frame_rate"].as_str().unwrap_or("30/1");
-    let fps = parse_fps(fps_str)?;
-    
-    let video_info = VideoInfo {
-        duration_seconds: format["duration"]
-            .as_str()
-            .unwrap_or("0")
-            .parse()
-            .unwrap_or(0.0),
-        width: stream["width"].as_u64().unwrap_or(0) as u32,
-        height: stream["height"].as_u64().unwrap_or(0) as u32,
+    let fps_str = stream.r_frame_rate.as_deref().unwrap_or("30/1");
+    let mut fps = parse_fps(fps_str)?;
+    
+    // For VFR streams r_frame_rate is the finest timebase rate, often far
+    // above the real one; avg_frame_rate is "0/0" when unknown
+    let avg_fps = stream
+        .avg_frame_rate
+        .as_deref()
+        .filter(|f| !f.ends_with("/0"))
+        .and_then(|f| parse_fps(f).ok())
+        .filter(|f| *f > 0.0);
+    let variable_frame_rate = avg_fps.is_some_and(|avg| is_variable_frame_rate(fps, avg));
+    if let Some(avg) = avg_fps.filter(|_| variable_frame_rate) {
+        fps = avg;
+    }
+    
+    let audio_streams: Vec<AudioStreamInfo> = probe
+        .streams
+        .iter()
+        .filter(|s| s.codec_type.as_deref() == Some("audio"))
+        .map(|audio| AudioStreamInfo {
+            codec: audio.codec_name.clone().unwrap_or_else(|| "unknown".to_string()),
+            channels: audio.channels.unwrap_or(0),
+            sample_rate: audio.sample_rate.as_deref().and_then(|r| r.parse().ok()),
+            language: audio
+                .tags
+                .get("language")
+                .filter(|l| !l.is_empty() && *l != "und")
+                .map(|l| l.to_lowercase()),
+        })
+        .collect();
+    
+    Ok(VideoInfo {
+        duration_seconds,
+        width,
+        height,
         fps,
-        codec: stream["codec_name"].as_str().unwrap_or("unknown").to_string(),
+        codec: stream.codec_name.clone().unwrap_or_else(|| "unknown".to_string()),
+        pix_fmt: stream.pix_fmt.clone().unwrap_or_else(|| "unknown".to_string()),
+        variable_frame_rate,
+        container: format.format_name.clone(),
+        bit_rate: format.bit_rate.as_deref().and_then(|b| b.parse().ok()),
+        audio: audio_streams.first().cloned(),
+        audio_streams,
+        chapters: probe
+            .chapters
+            .iter()
+            .filter_map(|chapter| {
+                Some(Chapter {
+                    start: chapter.start_time.as_deref()?.parse().ok()?,
+                    end: chapter.end_time.as_deref()?.parse().ok()?,
+                    title: chapter.tags.get("title").cloned(),
+                })
+            })
+            .collect(),
+    })
+}
+
+/// Relative gap between nominal and average frame rate above which a stream
+/// counts as variable frame rate
+const VFR_TOLERANCE: f64 = 0.05;
+
+fn is_variable_frame_rate(nominal: f64, average: f64) -> bool {
+    (nominal - average).abs() > nominal.max(average) * VFR_TOLERANCE
+}
+
+/// Video codecs the local ffmpeg can decode, detected once per process
+static DECODABLE_CODECS: OnceCell<HashSet<String>> = OnceCell::const_new();
+
+/// Fail fast on input ffmpeg can't decode, which would otherwise yield an
+/// empty frame set much later.
+///
+/// The codec is checked against SUPPORTED_CODECS (comma separated) when set,
+/// else against `ffmpeg -codecs`. ffprobe only reports a pixel format when it
+/// has a decoder, so a missing one is treated as unsupported too.
+async fn check_decodable(info: &VideoInfo) -> Result<()> {
+    let allowed: HashSet<String> = match std::env::var("SUPPORTED_CODECS") {
+        Ok(list) if !list.trim().is_empty() => list
+            .split(',')
+            .map(|c| c.trim().to_lowercase())
+            .filter(|c| !c.is_empty())
+            .collect(),
+        _ => DECODABLE_CODECS.get_or_init(detect_decodable_codecs).await.clone(),
     };
     
-    info!("Video info: {:?}", video_info);
+    // Detection failed; don't block jobs on it
+    if allowed.is_empty() {
+        return Ok(());
+    }
     
-    Ok(video_info)
+    if !allowed.contains(&info.codec.to_lowercase()) {
+        return Err(WorkerError::UnsupportedFormat(format!(
+            "codec '{}' cannot be decoded by this deployment",
+            info.codec
+        )));
+    }
+    
+    if info.pix_fmt == "unknown" || info.pix_fmt.is_empty() {
+        return Err(WorkerError::UnsupportedFormat(format!(
+            "no pixel format reported for codec '{}'",
+            info.codec
+        )));
+    }
+    
+    Ok(())
+}
+
+async fn detect_decodable_codecs() -> HashSet<String> {
+    let output = tokio::process::Command::new(tools::ffmpeg())
+        .kill_on_drop(true)
+        .args(&["-hide_banner", "-codecs"])
+        .output()
+        .await;
+    
+    match output {
+        Ok(output) if output.status.success() => {
+            let codecs = parse_decodable_codecs(&String::from_utf8_lossy(&output.stdout));
+            info!("ffmpeg can decode {} video codecs", codecs.len());
+            codecs
+        }
+        _ => {
+            warn!("Could not list ffmpeg codecs, skipping codec check");
+            HashSet::new()
+        }
+    }
+}
+
+/// Pick decodable video codecs out of `ffmpeg -codecs`, whose rows look like
+/// ` DEV.LS h264                 H.264 / AVC / MPEG-4 AVC ...`
+fn parse_decodable_codecs(listing: &str) -> HashSet<String> {
+    listing
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let flags = parts.next()?;
+            let name = parts.next()?;
+            let flags: Vec<char> = flags.chars().collect();
+            (flags.len() == 6 && flags[0] == 'D' && flags[2] == 'V').then(|| name.to_string())
+        })
+        .collect()
 }
 
 fn parse_fps(fps_str: &str) -> Result<f64> {
     if fps_str.contains('/') {
         let parts: Vec<&str> = fps_str.split('/').collect();
         if parts.len() == 2 {
-            let num: f64 = parts[0].parse()?;
-            let den: f64 = parts[1].parse()?;
+            let num: f64 = parts[0]
+                .parse()
+                .map_err(|_| WorkerError::Probe(format!("invalid frame rate: {}", fps_str)))?;
+            let den: f64 = parts[1]
+                .parse()
+                .map_err(|_| WorkerError::Probe(format!("invalid frame rate: {}", fps_str)))?;
             if den != 0.0 {
                 return Ok(num / den);
             }
@@ -76,85 +533,573 @@ fn parse_fps(fps_str: &str) -> Result<f64> {
     Ok(fps_str.parse().unwrap_or(30.0))
 }
 
-/// Extract keyframes at scene changes
+/// Extract keyframes at scene changes, plus a frame every 2 seconds.
+///
+/// Few (or no) scene changes is normal, but the 2-second pass yields at least
+/// one frame for any decodable video, so an ffmpeg failure or an empty result
+/// is an error rather than "no frames". Clips shorter than that interval get
+/// a single midpoint frame instead.
+#[instrument(skip_all, fields(job_id = %job_id))]
 pub async fn extract_keyframes(
     video_path: &str, 
-    output_dir: &str, 
-    job_id: &str
+    job_dir: &str, 
+    job_id: &str,
+    duration: f64,
+    crop: Option<&CropRect>,
+    max_frames: usize,
+    encoding: &FrameEncoding,
 ) -> Result<Vec<FrameData>> {
     use std::time::Duration;
     
     info!("Extracting keyframes from {}", video_path);
     
-    let frames_dir = Path::new(output_dir).join(format!("{}_frames", job_id));
+    let frames_dir = Path::new(job_dir).join("frames");
     std::fs::create_dir_all(&frames_dir)?;
+    if encoding.thumb_dimension.is_some() {
+        std::fs::create_dir_all(frames_dir.join(THUMBS_DIR))?;
+    }
+    
+    // Crop away letterboxing before any other filter
+    let crop_filter = crop.map(|c| format!("{},", c.filter())).unwrap_or_default();
+    // Brightening only touches the frames that are kept, after selection
+    let scale_filter = format!("{}{}", dark_frame_filter(video_path, crop).await, encoding.scale_filter());
+    
+    if is_short_clip(duration) {
+        info!("{:.2}s clip is shorter than the frame interval, taking the midpoint", duration);
+        let filter = format!("{}{}null", crop_filter, scale_filter);
+        return extract_short_clip_frame(video_path, &frames_dir, duration, &filter, encoding)
+            .await
+            .map(|frame| vec![frame]);
+    }
     
     // Use ffmpeg scene detection to extract keyframes
     let scene_threshold = 0.3;
-    let output_pattern = frames_dir.join("frame_%04d.jpg");
+    let scene_filter = format!(
+        "{}select='gt(scene,{})',{}showinfo",
+        crop_filter, scene_threshold, scale_filter
+    );
     
-    let output = tokio::process::Command::new("ffmpeg")
-        .args(&[
-            "-i", video_path,
-            "-vf", &format!("select='gt(scene,\,{})',showinfo", scene_threshold),
-            "-vsync", "vfr",
-            "-frame_pts", "1",
-            "-q:v", "2",
-            output_pattern.to_str().unwrap(),
-        ])
-        .output()
-        .await
-        .context("Failed to execute ffmpeg for frame extraction")?;
+    let output = tools::output_retrying(
+        tokio::process::Command::new(tools::ffmpeg())
+            .kill_on_drop(true)
+            .args(&["-i", video_path])
+            .args(encoding.output_args(&scene_filter, &frames_dir, "scene_%04d", &["-vsync", "vfr"])),
+    )
+    .await
+    .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+    
+    if !output.status.success() {
+        return Err(WorkerError::FrameExtraction(format!(
+            "scene detection failed ({}): {}",
+            output.status,
+            stderr_tail(&output.stderr)
+        )));
+    }
+    stamp_frames(&frames_dir, "scene", "frame", &showinfo_timestamps(&output.stderr), encoding)?;
     
     // Also extract frames at regular intervals (every 2 seconds)
-    let regular_pattern = frames_dir.join("regular_%04d.jpg");
-    let _ = tokio::process::Command::new("ffmpeg")
-        .args(&[
-            "-i", video_path,
-            "-vf", "fps=1/2,showinfo",
-            "-frame_pts", "1",
-            "-q:v", "2",
-            regular_pattern.to_str().unwrap(),
-        ])
-        .output()
-        .await;
+    let regular_filter = format!("{}fps=1/2,{}showinfo", crop_filter, scale_filter);
+    let output = tools::output_retrying(
+        tokio::process::Command::new(tools::ffmpeg())
+            .kill_on_drop(true)
+            .args(&["-i", video_path])
+            .args(encoding.output_args(&regular_filter, &frames_dir, "interval_%04d", &["-vsync", "vfr"])),
+    )
+    .await
+    .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
     
-    // Collect all extracted frames
-    let mut frames = Vec::new();
-    let entries = std::fs::read_dir(&frames_dir)?;
-    
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-        
-        if let Some(ext) = path.extension() {
-            if ext == "jpg" {
-                // Extract timestamp from filename
-                let filename = path.file_stem().unwrap().to_string_lossy();
-                let timestamp = parse_timestamp(&filename).unwrap_or(0.0);
-                let is_keyframe = filename.starts_with("frame_");
-                
-                frames.push(FrameData {
-                    timestamp,
-                    frame_path: path.to_string_lossy().to_string(),
-                    ocr_text: None,
-                    is_keyframe,
-                });
-            }
-        }
+    if !output.status.success() {
+        return Err(WorkerError::FrameExtraction(format!(
+            "regular frame extraction failed ({}): {}",
+            output.status,
+            stderr_tail(&output.stderr)
+        )));
+    }
+    stamp_frames(&frames_dir, "interval", "regular", &showinfo_timestamps(&output.stderr), encoding)?;
+    
+    let mut frames = collect_frames(&frames_dir)?;
+    
+    if frames.is_empty() {
+        return Err(WorkerError::FrameExtraction(format!(
+            "ffmpeg exited cleanly but wrote no frames: {}",
+            stderr_tail(&output.stderr)
+        )));
     }
     
     // Sort by timestamp
     frames.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
     
+    let before = frames.len();
+    frames = dedupe_frames(frames, DEDUPE_DELTA_SECS);
+    if frames.len() < before {
+        info!("Removed {} regular frames duplicating a keyframe", before - frames.len());
+    }
+    
+    if frames.len() > max_frames {
+        let total = frames.len();
+        frames = limit_frames(frames, max_frames);
+        info!(
+            "Dropped {} of {} frames to stay under the {} frame cap",
+            total - frames.len(),
+            total,
+            max_frames
+        );
+    }
+    
     info!("Extracted {} frames", frames.len());
     
     Ok(frames)
 }
 
+/// `pts_time` of every frame showinfo logged, in output order
+fn showinfo_timestamps(stderr: &[u8]) -> Vec<f64> {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .filter(|line| line.contains("Parsed_showinfo"))
+        .filter_map(|line| {
+            line.split_whitespace()
+                .find_map(|field| field.strip_prefix("pts_time:"))?
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+/// Rename ffmpeg's sequentially numbered `{from}_0001`.. frames and their
+/// thumbnails to `{to}_<ms>`, using the pts_time showinfo logged for each.
+///
+/// Frame numbers and pts counts only map to seconds at a constant frame
+/// rate; the decoder's presentation time is right for VFR video too.
+fn stamp_frames(
+    frames_dir: &Path,
+    from: &str,
+    to: &str,
+    timestamps: &[f64],
+    encoding: &FrameEncoding,
+) -> Result<()> {
+    let thumbs_dir = frames_dir.join(THUMBS_DIR);
+    let mut unmatched = 0;
+    
+    for index in 1.. {
+        let name = encoding.file_name(&format!("{}_{:04}", from, index));
+        let path = frames_dir.join(&name);
+        if !path.exists() {
+            break;
+        }
+        let thumb = thumbs_dir.join(&name);
+        
+        match timestamps.get(index - 1) {
+            Some(timestamp) => {
+                let stamped = encoding.file_name(&format!("{}_{}", to, (timestamp * 1000.0).round() as u64));
+                std::fs::rename(&path, frames_dir.join(&stamped))?;
+                if thumb.exists() {
+                    std::fs::rename(&thumb, thumbs_dir.join(&stamped))?;
+                }
+            }
+            None => {
+                unmatched += 1;
+                let _ = std::fs::remove_file(&path);
+                let _ = std::fs::remove_file(&thumb);
+            }
+        }
+    }
+    
+    if unmatched > 0 {
+        warn!("Dropped {} {} frames without a showinfo timestamp", unmatched, from);
+    }
+    Ok(())
+}
+
+/// Clips shorter than the regular 2-second frame interval
+const SHORT_CLIP_SECS: f64 = 2.0;
+
+/// Whether a clip is too short for scene detection and interval sampling
+pub(crate) fn is_short_clip(duration: f64) -> bool {
+    duration < SHORT_CLIP_SECS
+}
+
+/// Timestamps to try for a short clip's single frame: the midpoint, then the
+/// very first frame in case seeking fails (e.g. GIF-sourced clips)
+fn short_clip_timestamps(duration: f64) -> [f64; 2] {
+    [duration / 2.0, 0.0]
+}
+
+/// The one frame kept for a clip shorter than the frame interval
+async fn extract_short_clip_frame(
+    video_path: &str,
+    frames_dir: &Path,
+    duration: f64,
+    filter: &str,
+    encoding: &FrameEncoding,
+) -> Result<FrameData> {
+    let mut last_error = String::new();
+    
+    for timestamp in short_clip_timestamps(duration) {
+        let stem = format!("midpoint_{}", (timestamp * 1000.0).round() as u64);
+        let frame_path = frames_dir.join(encoding.file_name(&stem));
+        let thumb_path = frames_dir.join(THUMBS_DIR).join(encoding.file_name(&stem));
+        let output = tokio::process::Command::new(tools::ffmpeg())
+            .kill_on_drop(true)
+            .args(&["-ss", &format!("{:.3}", timestamp), "-i", video_path])
+            .args(encoding.output_args(filter, frames_dir, &stem, &["-frames:v", "1", "-y"]))
+            .output()
+            .await
+            .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+        
+        if output.status.success() && frame_path.exists() {
+            return Ok(FrameData {
+                timestamp,
+                frame_path: frame_path.to_string_lossy().to_string(),
+                thumb_path: thumb_path.exists().then(|| thumb_path.to_string_lossy().to_string()),
+                ocr_text: None,
+                ocr_text_until: None,
+                ocr_boxes: None,
+                lang: None,
+                subtitle_text: None,
+                sharpness: None,
+                brightness: None,
+                is_keyframe: true,
+            });
+        }
+        last_error = stderr_tail(&output.stderr);
+    }
+    
+    Err(WorkerError::FrameExtraction(format!("no frame could be read from the clip: {}", last_error)))
+}
+
+/// Frames already written to `job_dir/frames` by an earlier run, in timestamp
+/// order, for re-running later stages without extracting again
+pub fn load_frames(job_dir: &str) -> Result<Vec<FrameData>> {
+    let frames_dir = Path::new(job_dir).join("frames");
+    if !frames_dir.is_dir() {
+        return Err(WorkerError::FrameExtraction(format!("no frames from an earlier run in {}", job_dir)));
+    }
+
+    let mut frames = collect_frames(&frames_dir)?;
+    if frames.is_empty() {
+        return Err(WorkerError::FrameExtraction(format!("no frames from an earlier run in {}", job_dir)));
+    }
+
+    frames.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    info!("Loaded {} frames from an earlier run", frames.len());
+    Ok(frames)
+}
+
+/// Every `.jpg` or `.png` in `frames_dir`, with timestamp and keyframe flag
+/// taken from the file name (`frame_<ms>`/`regular_<ms>`/`midpoint_<ms>` from
+/// extract_keyframes, `fast_<ms>` from extract_frames_fast)
+fn collect_frames(frames_dir: &Path) -> Result<Vec<FrameData>> {
+    let mut frames = Vec::new();
+    
+    for entry in std::fs::read_dir(frames_dir)? {
+        let path = entry?.path();
+        if FrameFormat::from_path(&path).is_none() {
+            continue;
+        }
+        
+        // Extract timestamp from filename
+        let filename = path.file_stem().unwrap().to_string_lossy();
+        let timestamp = parse_timestamp(&filename).unwrap_or(0.0) / 1000.0;
+        let is_keyframe = !filename.starts_with("regular_");
+        let thumb_path = path
+            .file_name()
+            .map(|name| frames_dir.join(THUMBS_DIR).join(name))
+            .filter(|thumb| thumb.exists());
+        
+        frames.push(FrameData {
+            timestamp,
+            frame_path: path.to_string_lossy().to_string(),
+            thumb_path: thumb_path.map(|thumb| thumb.to_string_lossy().to_string()),
+            ocr_text: None,
+            ocr_text_until: None,
+            ocr_boxes: None,
+            lang: None,
+            subtitle_text: None,
+            sharpness: None,
+            brightness: None,
+            is_keyframe,
+        });
+    }
+    
+    Ok(frames)
+}
+
+/// Last few lines of ffmpeg's stderr; with showinfo the full log is huge and
+/// the actual error comes at the end
+fn stderr_tail(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let lines: Vec<&str> = stderr.lines().filter(|l| !l.trim().is_empty()).collect();
+    lines[lines.len().saturating_sub(5)..].join("\n")
+}
+
+/// Drop regular-interval frames that land within `delta` seconds of a scene
+/// keyframe; the keyframe already covers that moment. Input must be sorted.
+pub fn dedupe_frames(frames: Vec<FrameData>, delta: f64) -> Vec<FrameData> {
+    let keyframe_times: Vec<f64> = frames
+        .iter()
+        .filter(|f| f.is_keyframe)
+        .map(|f| f.timestamp)
+        .collect();
+    
+    frames
+        .into_iter()
+        .filter(|frame| {
+            if frame.is_keyframe {
+                return true;
+            }
+            let duplicate = keyframe_times
+                .iter()
+                .any(|t| (t - frame.timestamp).abs() <= delta);
+            if duplicate {
+                remove_frame_files(frame);
+            }
+            !duplicate
+        })
+        .collect()
+}
+
+/// Trim to at most `max_frames`, keeping keyframes first and sampling evenly
+/// across the timeline so coverage is preserved. Dropped images are deleted.
+pub fn limit_frames(frames: Vec<FrameData>, max_frames: usize) -> Vec<FrameData> {
+    let (keyframes, regular): (Vec<_>, Vec<_>) = frames.into_iter().partition(|f| f.is_keyframe);
+    
+    let (mut kept, dropped) = if keyframes.len() >= max_frames {
+        let (kept, mut dropped) = sample_evenly(keyframes, max_frames);
+        dropped.extend(regular);
+        (kept, dropped)
+    } else {
+        let slots = max_frames - keyframes.len();
+        let (mut kept, dropped) = sample_evenly(regular, slots);
+        kept.extend(keyframes);
+        (kept, dropped)
+    };
+    
+    for frame in &dropped {
+        remove_frame_files(frame);
+    }
+    
+    kept.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    kept
+}
+
+/// Split into (`count` evenly spaced items, the rest)
+fn sample_evenly(items: Vec<FrameData>, count: usize) -> (Vec<FrameData>, Vec<FrameData>) {
+    let total = items.len();
+    if count >= total {
+        return (items, Vec::new());
+    }
+    
+    let picked: std::collections::HashSet<usize> = (0..count)
+        .map(|i| ((i as f64 + 0.5) * total as f64 / count as f64) as usize)
+        .collect();
+    
+    let mut kept = Vec::with_capacity(count);
+    let mut dropped = Vec::with_capacity(total - count);
+    for (i, item) in items.into_iter().enumerate() {
+        if picked.contains(&i) {
+            kept.push(item);
+        } else {
+            dropped.push(item);
+        }
+    }
+    
+    (kept, dropped)
+}
+
+/// Extract one frame at each of the given timestamps (seconds).
+///
+/// Seeks after decoding (`-ss` as an output option) so the frame matches the
+/// timestamp exactly, at the cost of decoding from the start of the file.
+#[instrument(skip_all, fields(job_id = %job_id))]
+pub async fn extract_frames_at(
+    video_path: &str,
+    job_dir: &str,
+    job_id: &str,
+    timestamps: &[f64],
+    encoding: &FrameEncoding,
+) -> Result<Vec<FrameData>> {
+    info!("Extracting {} frames at explicit timestamps from {}", timestamps.len(), video_path);
+    
+    let frames_dir = Path::new(job_dir).join("frames_at");
+    std::fs::create_dir_all(&frames_dir)?;
+    
+    let [codec_flag, codec_value] = encoding.codec_args();
+    let scale_filter = encoding.scale_filter();
+    let mut frames = Vec::new();
+    
+    for &timestamp in timestamps {
+        let stem = format!("at_{}", (timestamp * 1000.0).round() as u64);
+        let frame_path = frames_dir.join(encoding.file_name(&stem));
+        
+        let output = tokio::process::Command::new(tools::ffmpeg())
+            .kill_on_drop(true)
+            .args(&[
+                "-i", video_path,
+                "-ss", &format!("{:.3}", timestamp),
+                "-frames:v", "1",
+                "-vf", &format!("{}null", scale_filter),
+                &codec_flag, &codec_value,
+                "-y",
+                frame_path.to_str().unwrap(),
+            ])
+            .output()
+            .await
+            .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+        
+        if !output.status.success() || !frame_path.exists() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!("No frame extracted at {:.3}s: {}", timestamp, stderr);
+            continue;
+        }
+        
+        frames.push(FrameData {
+            timestamp,
+            frame_path: frame_path.to_string_lossy().to_string(),
+            thumb_path: None,
+            ocr_text: None,
+            ocr_text_until: None,
+            ocr_boxes: None,
+            lang: None,
+            subtitle_text: None,
+            sharpness: None,
+            brightness: None,
+            is_keyframe: false,
+        });
+    }
+    
+    info!("Extracted {}/{} requested frames", frames.len(), timestamps.len());
+    
+    Ok(frames)
+}
+
+/// Whether to sample frames by keyframe seeking instead of scene detection (FAST_FRAMES=true)
+pub fn fast_frames_enabled() -> bool {
+    std::env::var("FAST_FRAMES")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Shortest spacing between fast-path samples, matching the regular-frame interval
+const FAST_FRAME_INTERVAL_SECS: f64 = 2.0;
+
+/// Frames are shrunk to fit this box before quality scoring
+const QUALITY_SAMPLE_SIZE: u32 = 256;
+
+/// Sample up to `max_frames` evenly spaced frames using input-side seeking.
+///
+/// Much faster than extract_keyframes on long videos because ffmpeg jumps to
+/// the nearest preceding I-frame and decodes only that frame, instead of
+/// decoding the whole stream twice. The tradeoff is accuracy: each frame is
+/// the I-frame at or before its `timestamp`, up to one GOP (often 1-5s) early,
+/// and there is no scene detection, so quick text overlays can be missed.
+/// Samples are I-frames, so they are marked `is_keyframe`.
+#[instrument(skip_all, fields(job_id = %job_id))]
+pub async fn extract_frames_fast(
+    video_path: &str,
+    job_dir: &str,
+    job_id: &str,
+    duration: f64,
+    crop: Option<&CropRect>,
+    max_frames: usize,
+    encoding: &FrameEncoding,
+) -> Result<Vec<FrameData>> {
+    let frames_dir = Path::new(job_dir).join("frames");
+    std::fs::create_dir_all(&frames_dir)?;
+    
+    let count = ((duration / FAST_FRAME_INTERVAL_SECS).floor() as usize).clamp(1, max_frames.max(1));
+    let interval = duration / count as f64;
+    info!("Sampling {} frames every {:.1}s from {}", count, interval, video_path);
+    
+    let crop_filter = crop.map(|c| format!("{},", c.filter())).unwrap_or_default();
+    let filter = format!(
+        "{}{}{}null",
+        crop_filter,
+        dark_frame_filter(video_path, crop).await,
+        encoding.scale_filter()
+    );
+    let [codec_flag, codec_value] = encoding.codec_args();
+    let mut frames = Vec::with_capacity(count);
+    
+    for i in 0..count {
+        // Sample the middle of each slot so the first frame isn't a black intro
+        let timestamp = interval * (i as f64 + 0.5);
+        let stem = format!("fast_{}", (timestamp * 1000.0).round() as u64);
+        let frame_path = frames_dir.join(encoding.file_name(&stem));
+        
+        let output = tokio::process::Command::new(tools::ffmpeg())
+            .kill_on_drop(true)
+            .args(&[
+                "-skip_frame", "nokey",
+                "-noaccurate_seek",
+                "-ss", &format!("{:.3}", timestamp),
+                "-i", video_path,
+                "-frames:v", "1",
+                "-vf", &filter,
+                &codec_flag, &codec_value,
+                "-y",
+                frame_path.to_str().unwrap(),
+            ])
+            .output()
+            .await
+            .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+        
+        if !output.status.success() || !frame_path.exists() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!("No frame sampled at {:.3}s: {}", timestamp, stderr);
+            continue;
+        }
+        
+        frames.push(FrameData {
+            timestamp,
+            frame_path: frame_path.to_string_lossy().to_string(),
+            thumb_path: None,
+            ocr_text: None,
+            ocr_text_until: None,
+            ocr_boxes: None,
+            lang: None,
+            subtitle_text: None,
+            sharpness: None,
+            brightness: None,
+            is_keyframe: true,
+        });
+    }
+    
+    if frames.is_empty() {
+        return Err(WorkerError::FrameExtraction("no frames could be sampled".to_string()));
+    }
+    
+    // Neighbouring seeks can snap back to the same I-frame
+    let before = frames.len();
+    frames = dedupe_identical_files(frames);
+    if frames.len() < before {
+        info!("Dropped {} samples that landed on the same I-frame", before - frames.len());
+    }
+    
+    info!("Sampled {} frames", frames.len());
+    
+    Ok(frames)
+}
+
+/// Drop frames whose image bytes match the previous kept frame
+fn dedupe_identical_files(frames: Vec<FrameData>) -> Vec<FrameData> {
+    let mut kept: Vec<FrameData> = Vec::with_capacity(frames.len());
+    let mut previous: Option<Vec<u8>> = None;
+    
+    for frame in frames {
+        let bytes = std::fs::read(&frame.frame_path).ok();
+        if bytes.is_some() && bytes == previous {
+            let _ = std::fs::remove_file(&frame.frame_path);
+            continue;
+        }
+        previous = bytes;
+        kept.push(frame);
+    }
+    
+    kept
+}
+
 fn parse_timestamp(filename: &str) -> Option<f64> {
-    // Parse timestamp from frame_pts filename
-    // Format: frame_1234.jpg where 1234 is the frame number or timestamp
+    // Format: frame_1234.jpg where 1234 is the timestamp in milliseconds
     if let Some(underscore_pos) = filename.rfind('_') {
         let num_str = &filename[underscore_pos + 1..];
         num_str.parse::<f64>().ok()
@@ -163,12 +1108,1073 @@ fn parse_timestamp(filename: &str) -> Option<f64> {
     }
 }
 
-/// Frame data structure
+/// Frame data structure. Serialized with a `formatted_timestamp` field
+/// rendered from `timestamp`; on input that field is ignored.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(remote = "Self")]
 pub struct FrameData {
+    /// Seconds from the start of the video (or section)
     pub timestamp: f64,
     pub frame_path: String,
+    /// Thumbnail-size copy of the frame, when FRAME_THUMB_DIMENSION is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumb_path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ocr_text: Option<String>,
+    /// Timestamp of the last following frame that showed the same text;
+    /// those frames carry no ocr_text of their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_text_until: Option<f64>,
+    /// Word-level boxes, only when requested (more expensive)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_boxes: Option<Vec<OcrWord>>,
+    /// ISO 639-3 code of the OCR text, when there was enough text to tell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// Burned-in caption text from the subtitle region pass (OCR_SUBTITLES),
+    /// kept apart from the full-frame overlay text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtitle_text: Option<String>,
+    /// Laplacian variance of a downscaled copy; low values mean blur
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sharpness: Option<f64>,
+    /// Mean luma of a downscaled copy, 0 (black) to 1 (white)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<f64>,
     pub is_keyframe: bool,
-}
\ No newline at end of file
+}
+
+impl FrameData {
+    /// `timestamp` as `HH:MM:SS.mmm`, for display
+    pub fn formatted_timestamp(&self) -> String {
+        format_timestamp(self.timestamp)
+    }
+}
+
+impl Serialize for FrameData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Labeled<'a> {
+            #[serde(flatten, with = "FrameData")]
+            frame: &'a FrameData,
+            formatted_timestamp: String,
+        }
+        
+        Labeled { frame: self, formatted_timestamp: self.formatted_timestamp() }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FrameData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        FrameData::deserialize(deserializer)
+    }
+}
+
+/// How to pick the representative thumbnail for a video
+#[derive(Debug, Clone, Copy)]
+pub enum ThumbnailStrategy {
+    /// Sharpest extracted keyframe by Laplacian variance
+    Sharpest,
+    /// Fresh frame at a percentage (0-100) of the duration
+    AtPercent(f64),
+}
+
+impl ThumbnailStrategy {
+    /// Read THUMBNAIL_STRATEGY: "sharpest" (default) or "percent:<0-100>"
+    pub fn from_env() -> Self {
+        std::env::var("THUMBNAIL_STRATEGY")
+            .ok()
+            .and_then(|v| Self::parse(&v))
+            .unwrap_or(ThumbnailStrategy::Sharpest)
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_lowercase();
+        if value == "sharpest" {
+            return Some(ThumbnailStrategy::Sharpest);
+        }
+        let percent: f64 = value.strip_prefix("percent:")?.parse().ok()?;
+        Some(ThumbnailStrategy::AtPercent(percent.clamp(0.0, 100.0)))
+    }
+}
+
+/// Pick or extract a single hero image for the video and return its path
+#[instrument(skip_all, fields(job_id = %job_id))]
+pub async fn extract_thumbnail(
+    video_path: &str,
+    job_dir: &str,
+    job_id: &str,
+    video_info: &VideoInfo,
+    frames: &[FrameData],
+    strategy: ThumbnailStrategy,
+    crop: Option<&CropRect>,
+) -> Result<String> {
+    match strategy {
+        ThumbnailStrategy::Sharpest if !frames.is_empty() => {
+            // Prefer scene keyframes; fall back to regular frames if there are none
+            let mut candidates: Vec<String> = frames
+                .iter()
+                .filter(|f| f.is_keyframe)
+                .map(|f| f.frame_path.clone())
+                .collect();
+            if candidates.is_empty() {
+                candidates = frames.iter().map(|f| f.frame_path.clone()).collect();
+            }
+
+            let sharpest = tokio::task::spawn_blocking(move || pick_sharpest(&candidates))
+                .await
+                .map_err(|e| WorkerError::FrameExtraction(format!("thumbnail scoring task failed: {}", e)))??;
+
+            info!("Selected thumbnail {}", sharpest);
+            Ok(sharpest)
+        }
+        ThumbnailStrategy::Sharpest => {
+            warn!("No frames to score, falling back to frame at 50%");
+            extract_frame_at_percent(video_path, job_dir, video_info, 50.0, crop).await
+        }
+        ThumbnailStrategy::AtPercent(percent) => {
+            extract_frame_at_percent(video_path, job_dir, video_info, percent, crop).await
+        }
+    }
+}
+
+fn pick_sharpest(paths: &[String]) -> Result<String> {
+    let mut best: Option<(f64, &String)> = None;
+
+    for path in paths {
+        let score = match image::open(path) {
+            Ok(img) => laplacian_variance(&img.to_luma8()),
+            Err(e) => {
+                warn!("Failed to load {} for sharpness scoring: {}", path, e);
+                continue;
+            }
+        };
+
+        if best.map_or(true, |(s, _)| score > s) {
+            best = Some((score, path));
+        }
+    }
+
+    best.map(|(_, path)| path.clone())
+        .ok_or_else(|| WorkerError::FrameExtraction("no readable frames to pick a thumbnail from".to_string()))
+}
+
+/// Variance of the 3x3 Laplacian; higher means more edges, i.e. sharper
+fn laplacian_variance(img: &image::GrayImage) -> f64 {
+    let (width, height) = img.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let px = |x: u32, y: u32| img.get_pixel(x, y)[0] as f64;
+    let mut sum = 0.0;
+    let mut sum_sq = 0.0;
+    let mut count = 0.0;
+
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let lap = px(x, y - 1) + px(x - 1, y) + px(x + 1, y) + px(x, y + 1) - 4.0 * px(x, y);
+            sum += lap;
+            sum_sq += lap * lap;
+            count += 1.0;
+        }
+    }
+
+    let mean = sum / count;
+    sum_sq / count - mean * mean
+}
+
+/// Fill in sharpness and brightness for every frame. Frames that can't be
+/// read keep None; scoring never fails the job.
+pub async fn score_frames(frames: &mut [FrameData]) {
+    let paths: Vec<String> = frames.iter().map(|f| f.frame_path.clone()).collect();
+    let scores = tokio::task::spawn_blocking(move || {
+        paths
+            .iter()
+            .map(|path| match frame_quality(path) {
+                Ok(score) => Some(score),
+                Err(e) => {
+                    warn!("Failed to load {} for quality scoring: {}", path, e);
+                    None
+                }
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+    
+    match scores {
+        Ok(scores) => {
+            for (frame, score) in frames.iter_mut().zip(scores) {
+                if let Some((sharpness, brightness)) = score {
+                    frame.sharpness = Some(sharpness);
+                    frame.brightness = Some(brightness);
+                }
+            }
+        }
+        Err(e) => warn!("Frame quality scoring task failed: {}", e),
+    }
+}
+
+/// (sharpness, brightness) of the image at `path`, measured on a downscaled copy
+fn frame_quality(path: &str) -> image::ImageResult<(f64, f64)> {
+    let gray = image::open(path)?
+        .thumbnail(QUALITY_SAMPLE_SIZE, QUALITY_SAMPLE_SIZE)
+        .to_luma8();
+    
+    let pixels = gray.as_raw();
+    let brightness = if pixels.is_empty() {
+        0.0
+    } else {
+        pixels.iter().map(|&p| p as f64).sum::<f64>() / pixels.len() as f64 / 255.0
+    };
+    
+    Ok((laplacian_variance(&gray), brightness))
+}
+
+async fn extract_frame_at_percent(
+    video_path: &str,
+    job_dir: &str,
+    video_info: &VideoInfo,
+    percent: f64,
+    crop: Option<&CropRect>,
+) -> Result<String> {
+    let timestamp = video_info.duration_seconds * percent / 100.0;
+    let output_path = Path::new(job_dir).join("thumbnail.jpg");
+
+    let mut args = vec![
+        "-ss".to_string(), format!("{:.3}", timestamp),
+        "-i".to_string(), video_path.to_string(),
+    ];
+    if let Some(crop) = crop {
+        args.push("-vf".to_string());
+        args.push(crop.filter());
+    }
+
+    let output = tokio::process::Command::new(tools::ffmpeg())
+        .kill_on_drop(true)
+        .args(&args)
+        .args(&[
+            "-frames:v", "1",
+            "-q:v", "2",
+            "-y",
+            output_path.to_str().unwrap(),
+        ])
+        .output()
+        .await
+        .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+
+    if !output.status.success() || !output_path.exists() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(WorkerError::FrameExtraction(format!("thumbnail extraction failed: {}", stderr)));
+    }
+
+    info!("Extracted thumbnail at {:.2}s", timestamp);
+
+    Ok(output_path.to_string_lossy().to_string())
+}
+
+/// Container for the looping preview clip
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewFormat {
+    Gif,
+    Webp,
+    Mp4,
+}
+
+impl PreviewFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "gif" => Some(PreviewFormat::Gif),
+            "webp" => Some(PreviewFormat::Webp),
+            "mp4" => Some(PreviewFormat::Mp4),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PreviewFormat::Gif => "gif",
+            PreviewFormat::Webp => "webp",
+            PreviewFormat::Mp4 => "mp4",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            PreviewFormat::Gif => "image/gif",
+            PreviewFormat::Webp => "image/webp",
+            PreviewFormat::Mp4 => "video/mp4",
+        }
+    }
+}
+
+/// Frame rate of the preview; enough for motion at a fraction of the size
+const PREVIEW_FPS: u32 = 12;
+
+/// Preview width in pixels; height follows the aspect ratio
+const PREVIEW_WIDTH: u32 = 320;
+
+/// Settings for the animated preview, which is off unless PREVIEW_FORMAT is set
+#[derive(Debug, Clone, Copy)]
+pub struct PreviewOptions {
+    pub format: PreviewFormat,
+    pub duration_secs: f64,
+}
+
+impl PreviewOptions {
+    /// Read PREVIEW_FORMAT (gif, webp, or mp4) and PREVIEW_DURATION_SECS
+    /// (default 3); None when no format is set
+    pub fn from_env() -> Option<Self> {
+        let name = std::env::var("PREVIEW_FORMAT").ok().filter(|v| !v.trim().is_empty())?;
+        let Some(format) = PreviewFormat::parse(&name) else {
+            warn!("Unknown PREVIEW_FORMAT '{}' (expected gif, webp, or mp4), skipping preview", name);
+            return None;
+        };
+        let duration_secs = std::env::var("PREVIEW_DURATION_SECS")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|v| v.is_finite() && *v > 0.0)
+            .unwrap_or(3.0);
+
+        Some(Self { format, duration_secs })
+    }
+}
+
+/// Start of the most interesting `clip` seconds: the window holding the most
+/// scene changes, weighted by how sharp its frames are. Without frames the
+/// clip is centred in the video.
+fn preview_start(frames: &[FrameData], duration: f64, clip: f64) -> f64 {
+    let latest = (duration - clip).max(0.0);
+    let max_sharpness = frames
+        .iter()
+        .filter_map(|f| f.sharpness)
+        .fold(0.0, f64::max);
+    let score = |start: f64| -> f64 {
+        frames
+            .iter()
+            .filter(|f| f.timestamp >= start && f.timestamp < start + clip)
+            .map(|f| {
+                let scene = if f.is_keyframe { 1.0 } else { 0.0 };
+                let sharpness = match (f.sharpness, max_sharpness > 0.0) {
+                    (Some(s), true) => s / max_sharpness,
+                    _ => 0.0,
+                };
+                scene + sharpness
+            })
+            .sum()
+    };
+
+    frames
+        .iter()
+        .map(|f| f.timestamp.min(latest))
+        .max_by(|a, b| score(*a).total_cmp(&score(*b)))
+        .unwrap_or(latest / 2.0)
+}
+
+/// Render a short looping preview from the most interesting part of the
+/// video and return its path (`preview.{gif,webp,mp4}` in the job dir)
+#[instrument(skip_all, fields(job_id = %job_id))]
+pub async fn extract_preview(
+    video_path: &str,
+    job_dir: &str,
+    job_id: &str,
+    video_info: &VideoInfo,
+    frames: &[FrameData],
+    options: PreviewOptions,
+    crop: Option<&CropRect>,
+) -> Result<String> {
+    let clip = options.duration_secs.min(video_info.duration_seconds);
+    let start = preview_start(frames, video_info.duration_seconds, clip);
+    let output_path = Path::new(job_dir).join(format!("preview.{}", options.format.extension()));
+
+    let mut filter = String::new();
+    if let Some(crop) = crop {
+        filter.push_str(&crop.filter());
+        filter.push(',');
+    }
+    filter.push_str(&format!("fps={},scale={}:-2:flags=lanczos", PREVIEW_FPS, PREVIEW_WIDTH));
+
+    let mut args = vec![
+        "-ss".to_string(), format!("{:.3}", start),
+        "-t".to_string(), format!("{:.3}", clip),
+        "-i".to_string(), video_path.to_string(),
+        "-an".to_string(),
+    ];
+    match options.format {
+        PreviewFormat::Gif => {
+            // A palette built from the clip itself avoids GIF's default banding
+            args.push("-filter_complex".to_string());
+            args.push(format!("{},split[a][b];[a]palettegen[p];[b][p]paletteuse", filter));
+            args.extend(["-loop".to_string(), "0".to_string()]);
+        }
+        PreviewFormat::Webp => {
+            args.extend(["-vf".to_string(), filter]);
+            args.extend([
+                "-c:v".to_string(), "libwebp".to_string(),
+                "-quality".to_string(), "70".to_string(),
+                "-loop".to_string(), "0".to_string(),
+            ]);
+        }
+        PreviewFormat::Mp4 => {
+            // Looping is left to the player (<video loop>)
+            args.extend(["-vf".to_string(), filter]);
+            args.extend([
+                "-c:v".to_string(), "libx264".to_string(),
+                "-pix_fmt".to_string(), "yuv420p".to_string(),
+                "-movflags".to_string(), "+faststart".to_string(),
+            ]);
+        }
+    }
+
+    let output = tokio::process::Command::new(tools::ffmpeg())
+        .kill_on_drop(true)
+        .args(&args)
+        .arg("-y")
+        .arg(&output_path)
+        .output()
+        .await
+        .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+
+    if !output.status.success() || !output_path.exists() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(WorkerError::FrameExtraction(format!("preview generation failed: {}", stderr)));
+    }
+
+    info!("Generated {:.1}s preview from {:.2}s", clip, start);
+
+    Ok(output_path.to_string_lossy().to_string())
+}
+
+/// Content rectangle left after removing black borders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CropRect {
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl CropRect {
+    /// ffmpeg `crop` filter expression for this rectangle
+    pub fn filter(&self) -> String {
+        format!("crop={}:{}:{}:{}", self.width, self.height, self.x, self.y)
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(':').map(|p| p.parse::<u32>().ok());
+        Some(CropRect {
+            width: parts.next()??,
+            height: parts.next()??,
+            x: parts.next()??,
+            y: parts.next()??,
+        })
+    }
+}
+
+/// Whether letterbox cropping is enabled (CROP_BORDERS=true)
+pub fn crop_borders_enabled() -> bool {
+    std::env::var("CROP_BORDERS")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Detect letterboxing/pillarboxing with ffmpeg's cropdetect.
+///
+/// Returns None when the detected content area is the full frame.
+pub async fn detect_crop(video_path: &str, video_info: &VideoInfo) -> Result<Option<CropRect>> {
+    info!("Detecting black borders in {}", video_path);
+
+    // Sample at 2fps; cropdetect only needs a handful of frames to settle
+    let output = tokio::process::Command::new(tools::ffmpeg())
+        .kill_on_drop(true)
+        .args(&[
+            "-i", video_path,
+            "-vf", "fps=2,cropdetect=limit=24:round=2:reset=0",
+            "-an",
+            "-f", "null",
+            "-",
+        ])
+        .output()
+        .await
+        .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(WorkerError::FrameExtraction(format!("crop detection failed: {}", stderr)));
+    }
+
+    // Take the most frequently reported rectangle so a few dark frames don't win
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut counts: std::collections::HashMap<CropRect, usize> = std::collections::HashMap::new();
+    for line in stderr.lines() {
+        if let Some(pos) = line.rfind("crop=") {
+            let value = line[pos + 5..].split_whitespace().next().unwrap_or("");
+            if let Some(rect) = CropRect::parse(value) {
+                *counts.entry(rect).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let rect = match counts.into_iter().max_by_key(|(_, n)| *n) {
+        Some((rect, _)) => rect,
+        None => return Ok(None),
+    };
+
+    if rect.width == 0 || rect.height == 0 {
+        return Ok(None);
+    }
+    if rect.width >= video_info.width && rect.height >= video_info.height {
+        return Ok(None);
+    }
+
+    info!("Detected content area {:?}", rect);
+
+    Ok(Some(rect))
+}
+
+/// Default average luma (0-1) below which a video counts as dark
+const DEFAULT_DARK_THRESHOLD: f64 = 0.25;
+
+/// Default gamma applied to dark videos; above 1 lifts shadows and midtones
+/// while leaving black and white in place
+const DEFAULT_DARK_GAMMA: f64 = 1.6;
+
+/// Brightening for dark footage before frames are written.
+///
+/// Lifting the gamma makes text and food in underexposed videos legible to
+/// Tesseract and in thumbnails, but it also amplifies sensor noise and
+/// compression blocking and washes out intentionally moody shots, and
+/// measuring brightness costs an extra decode pass. So it is off unless
+/// DARK_FRAME_BOOST is set, and only applies below the threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DarkFrameBoost {
+    /// Average luma (0-1) below which the video is brightened
+    pub threshold: f64,
+    pub gamma: f64,
+}
+
+impl DarkFrameBoost {
+    /// Read DARK_FRAME_BOOST, DARK_FRAME_THRESHOLD and DARK_FRAME_GAMMA;
+    /// None while DARK_FRAME_BOOST is off
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("DARK_FRAME_BOOST")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+
+        let threshold = std::env::var("DARK_FRAME_THRESHOLD")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|t| *t > 0.0 && *t < 1.0)
+            .unwrap_or(DEFAULT_DARK_THRESHOLD);
+        let gamma = std::env::var("DARK_FRAME_GAMMA")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+            // eq accepts 0.1-10; below 1 would darken
+            .filter(|g| *g > 1.0 && *g <= 10.0)
+            .unwrap_or(DEFAULT_DARK_GAMMA);
+        Some(Self { threshold, gamma })
+    }
+
+    /// eq filter (with trailing comma) for a video of `luma` average
+    /// brightness; empty if it is bright enough
+    fn filter(&self, luma: f64) -> String {
+        if luma >= self.threshold {
+            return String::new();
+        }
+        format!("eq=gamma={:.2},", self.gamma)
+    }
+}
+
+/// Filter brightening `video_path` when DARK_FRAME_BOOST is on and the
+/// video is dark; empty otherwise, including when measuring fails
+async fn dark_frame_filter(video_path: &str, crop: Option<&CropRect>) -> String {
+    let Some(boost) = DarkFrameBoost::from_env() else {
+        return String::new();
+    };
+
+    match measure_brightness(video_path, crop).await {
+        Ok(Some(luma)) => {
+            let filter = boost.filter(luma);
+            if filter.is_empty() {
+                info!("Average brightness {:.2}, frames left as is", luma);
+            } else {
+                info!(
+                    "Average brightness {:.2} is below {:.2}, brightening frames with gamma {:.2}",
+                    luma, boost.threshold, boost.gamma
+                );
+            }
+            filter
+        }
+        Ok(None) => String::new(),
+        Err(e) => {
+            warn!("Brightness measurement failed, frames left as is: {}", e);
+            String::new()
+        }
+    }
+}
+
+/// Average luma of `video_path` (inside `crop`, so letterbox bars don't
+/// count) from signalstats on a frame every 2 seconds, 0 (black) to 1 (white)
+async fn measure_brightness(video_path: &str, crop: Option<&CropRect>) -> Result<Option<f64>> {
+    let crop_filter = crop.map(|c| format!("{},", c.filter())).unwrap_or_default();
+    // format=gray keeps YAVG on a 0-255 scale whatever the source bit depth
+    let filter = format!(
+        "{}fps=1/2,scale=160:-2,format=gray,signalstats,metadata=print:key=lavfi.signalstats.YAVG",
+        crop_filter
+    );
+
+    let output = tokio::process::Command::new(tools::ffmpeg())
+        .kill_on_drop(true)
+        .args(&["-i", video_path, "-vf", &filter, "-an", "-f", "null", "-"])
+        .output()
+        .await
+        .map_err(|e| WorkerError::FrameExtraction(format!("failed to execute ffmpeg: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(WorkerError::FrameExtraction(format!(
+            "brightness measurement failed: {}",
+            stderr_tail(&output.stderr)
+        )));
+    }
+
+    Ok(average_luma(&String::from_utf8_lossy(&output.stderr)))
+}
+
+/// Mean of the YAVG values signalstats printed, scaled to 0-1
+fn average_luma(stderr: &str) -> Option<f64> {
+    let values: Vec<f64> = stderr
+        .lines()
+        .filter_map(|line| line.split("lavfi.signalstats.YAVG=").nth(1))
+        .filter_map(|value| value.trim().parse().ok())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64 / 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_probe(info: serde_json::Value) -> Result<VideoInfo> {
+        parse_probe_output(&serde_json::from_value(info).unwrap())
+    }
+
+    #[test]
+    fn probe_without_streams_is_no_video_stream() {
+        let info = json!({ "streams": [], "format": { "duration": "12.5" } });
+        assert!(matches!(parse_probe(info), Err(WorkerError::NoVideoStream)));
+
+        let info = json!({ "format": { "duration": "12.5" } });
+        assert!(matches!(parse_probe(info), Err(WorkerError::NoVideoStream)));
+    }
+
+    #[test]
+    fn probe_falls_back_to_stream_duration() {
+        let info = json!({
+            "streams": [{
+                "codec_type": "video",
+                "width": 1080,
+                "height": 1920,
+                "r_frame_rate": "30/1",
+                "codec_name": "h264",
+                "duration": "8.0"
+            }],
+            "format": {}
+        });
+        let video_info = parse_probe(info).unwrap();
+        assert_eq!(video_info.duration_seconds, 8.0);
+        assert_eq!(video_info.width, 1080);
+        assert_eq!(video_info.fps, 30.0);
+        assert!(!video_info.variable_frame_rate);
+    }
+
+    #[test]
+    fn probe_reads_container_audio_and_chapters() {
+        let info = json!({
+            "streams": [
+                {
+                    "index": 0,
+                    "codec_type": "audio",
+                    "codec_name": "aac",
+                    "sample_rate": "44100",
+                    "channels": 2,
+                    "tags": { "language": "eng" }
+                },
+                {
+                    "index": 1,
+                    "codec_type": "video",
+                    "codec_name": "h264",
+                    "width": 720,
+                    "height": 1280,
+                    "pix_fmt": "yuv420p",
+                    "r_frame_rate": "30/1",
+                    "avg_frame_rate": "30/1"
+                }
+            ],
+            "chapters": [
+                { "id": 0, "start_time": "0.000000", "end_time": "42.500000", "tags": { "title": "Dough" } },
+                { "id": 1, "start_time": "42.500000", "end_time": "90.000000" }
+            ],
+            "format": {
+                "format_name": "mov,mp4,m4a,3gp,3g2,mj2",
+                "duration": "90.000000",
+                "bit_rate": "1843210",
+                "tags": { "major_brand": "isom" }
+            }
+        });
+        let video_info = parse_probe(info).unwrap();
+        assert_eq!(video_info.width, 720);
+        assert_eq!(video_info.codec, "h264");
+        assert_eq!(video_info.container.as_deref(), Some("mov,mp4,m4a,3gp,3g2,mj2"));
+        assert_eq!(video_info.bit_rate, Some(1_843_210));
+
+        let audio = video_info.audio.unwrap();
+        assert_eq!(audio.codec, "aac");
+        assert_eq!(audio.channels, 2);
+        assert_eq!(audio.sample_rate, Some(44_100));
+        assert_eq!(audio.language.as_deref(), Some("eng"));
+        assert_eq!(video_info.audio_streams.len(), 1);
+
+        assert_eq!(video_info.chapters.len(), 2);
+        assert_eq!(video_info.chapters[0].title.as_deref(), Some("Dough"));
+        assert_eq!(video_info.chapters[1].start, 42.5);
+        assert_eq!(video_info.chapters[1].title, None);
+    }
+
+    #[test]
+    fn probe_reports_average_rate_for_vfr_streams() {
+        // ffprobe output for an iPhone screen recording
+        let info = json!({
+            "streams": [{
+                "codec_type": "video",
+                "width": 886,
+                "height": 1920,
+                "r_frame_rate": "60/1",
+                "avg_frame_rate": "20955/752",
+                "codec_name": "h264",
+                "duration": "25.066667"
+            }],
+            "format": { "duration": "25.066667" }
+        });
+        let video_info = parse_probe(info).unwrap();
+        assert!(video_info.variable_frame_rate);
+        assert!((video_info.fps - 27.866).abs() < 0.01);
+
+        let cfr = json!({
+            "streams": [{
+                "codec_type": "video",
+                "width": 1080,
+                "height": 1920,
+                "r_frame_rate": "30000/1001",
+                "avg_frame_rate": "29.97",
+                "duration": "8.0"
+            }],
+            "format": {}
+        });
+        let video_info = parse_probe(cfr).unwrap();
+        assert!(!video_info.variable_frame_rate);
+        assert!((video_info.fps - 29.97).abs() < 0.01);
+    }
+
+    #[test]
+    fn probe_ignores_unknown_average_rate() {
+        let info = json!({
+            "streams": [{
+                "codec_type": "video",
+                "width": 1080,
+                "height": 1920,
+                "r_frame_rate": "25/1",
+                "avg_frame_rate": "0/0",
+                "duration": "8.0"
+            }],
+            "format": {}
+        });
+        let video_info = parse_probe(info).unwrap();
+        assert!(!video_info.variable_frame_rate);
+        assert_eq!(video_info.fps, 25.0);
+    }
+
+    #[test]
+    fn showinfo_timestamps_follow_pts_not_frame_numbers() {
+        // Scene detection over a VFR clip: gaps between frames are uneven and
+        // pts is in a 1/600 timebase, so neither n nor pts is in seconds
+        let stderr = b"\
+[Parsed_showinfo_2 @ 0x55d0c8a0e5c0] config in time_base: 1/600, frame_rate: 60/1
+[Parsed_showinfo_2 @ 0x55d0c8a0e5c0] n:   0 pts:    712 pts_time:1.18667 duration:     20 pos:   120831 fmt:yuvj420p
+[Parsed_showinfo_2 @ 0x55d0c8a0e5c0]   color_range:pc color_space:bt709
+[Parsed_showinfo_2 @ 0x55d0c8a0e5c0] n:   1 pts:   2405 pts_time:4.00833 duration:     31 pos:   498112 fmt:yuvj420p
+[Parsed_showinfo_2 @ 0x55d0c8a0e5c0] n:   2 pts:   9011 pts_time:15.0183 duration:     10 pos:  1904331 fmt:yuvj420p
+frame=    3 fps=0.0 q=2.0 Lsize=N/A time=00:00:15.03 bitrate=N/A speed=31.2x
+";
+        assert_eq!(showinfo_timestamps(stderr), [1.18667, 4.00833, 15.0183]);
+    }
+
+    #[test]
+    fn stamp_frames_names_frames_by_presentation_time() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(THUMBS_DIR)).unwrap();
+        for name in ["scene_0001.jpg", "scene_0002.jpg", "scene_0003.jpg"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        std::fs::write(dir.path().join(THUMBS_DIR).join("scene_0001.jpg"), b"").unwrap();
+
+        stamp_frames(dir.path(), "scene", "frame", &[1.18667, 4.00833], &FrameEncoding::default()).unwrap();
+
+        assert!(dir.path().join("frame_1187.jpg").exists());
+        assert!(dir.path().join(THUMBS_DIR).join("frame_1187.jpg").exists());
+        assert!(dir.path().join("frame_4008.jpg").exists());
+        assert!(!dir.path().join("scene_0003.jpg").exists());
+
+        let mut frames = collect_frames(dir.path()).unwrap();
+        frames.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].timestamp, 1.187);
+        assert_eq!(frames[1].timestamp, 4.008);
+        assert!(frames.iter().all(|f| f.is_keyframe));
+    }
+
+    #[test]
+    fn average_luma_reads_signalstats_output() {
+        let stderr = "\
+[Parsed_metadata_4 @ 0x5581c0a3e040] frame:0    pts:0       pts_time:0
+[Parsed_metadata_4 @ 0x5581c0a3e040] lavfi.signalstats.YAVG=30.6
+[Parsed_metadata_4 @ 0x5581c0a3e040] frame:1    pts:1       pts_time:2
+[Parsed_metadata_4 @ 0x5581c0a3e040] lavfi.signalstats.YAVG=20.4
+frame=    2 fps=0.0 q=-0.0 Lsize=N/A time=00:00:04.00 bitrate=N/A speed=40.1x
+";
+        let luma = average_luma(stderr).unwrap();
+        assert!((luma - 0.1).abs() < 1e-9);
+        assert_eq!(average_luma("frame=    0 fps=0.0"), None);
+    }
+
+    #[test]
+    fn dark_frame_boost_only_brightens_below_threshold() {
+        let boost = DarkFrameBoost {
+            threshold: 0.25,
+            gamma: 1.6,
+        };
+        assert_eq!(boost.filter(0.1), "eq=gamma=1.60,");
+        assert_eq!(boost.filter(0.4), "");
+    }
+
+    #[test]
+    fn probe_without_any_duration_is_an_error() {
+        let info = json!({
+            "streams": [{ "codec_type": "video", "width": 1080, "height": 1920, "r_frame_rate": "30/1" }],
+            "format": {}
+        });
+        assert!(matches!(parse_probe(info), Err(WorkerError::Probe(_))));
+    }
+
+    #[test]
+    fn decodable_codecs_only_include_video_decoders() {
+        let listing = "Codecs:\n D..... = Decoding supported\n -------\n \
+            DEV.LS h264                 H.264 / AVC\n \
+            .EV.L. av1                  Alliance for Open Media AV1\n \
+            DEA.L. aac                  AAC (Advanced Audio Coding)\n";
+        let codecs = parse_decodable_codecs(listing);
+        assert!(codecs.contains("h264"));
+        assert!(!codecs.contains("av1"));
+        assert!(!codecs.contains("aac"));
+        assert_eq!(codecs.len(), 1);
+    }
+
+    #[test]
+    fn stderr_tail_keeps_the_last_lines() {
+        let log = b"line 1\nline 2\n\nline 3\nline 4\nline 5\nline 6\n\n";
+        assert_eq!(stderr_tail(log), "line 2\nline 3\nline 4\nline 5\nline 6");
+        assert_eq!(stderr_tail(b""), "");
+    }
+
+    #[test]
+    fn frame_quality_scores_blur_below_sharp_detail() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkerboard = image::GrayImage::from_fn(512, 512, |x, y| {
+            image::Luma([if (x / 8 + y / 8) % 2 == 0 { 255 } else { 0 }])
+        });
+        let sharp_path = dir.path().join("sharp.png");
+        let blurry_path = dir.path().join("blurry.png");
+        checkerboard.save(&sharp_path).unwrap();
+        image::imageops::blur(&checkerboard, 6.0).save(&blurry_path).unwrap();
+
+        let (sharp, sharp_brightness) = frame_quality(sharp_path.to_str().unwrap()).unwrap();
+        let (blurry, _) = frame_quality(blurry_path.to_str().unwrap()).unwrap();
+        assert!(sharp > blurry * 10.0, "sharp {} vs blurry {}", sharp, blurry);
+        assert!((sharp_brightness - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn frame_quality_brightness_spans_black_to_white() {
+        let dir = tempfile::tempdir().unwrap();
+        let black = dir.path().join("black.png");
+        let white = dir.path().join("white.png");
+        image::GrayImage::from_pixel(64, 64, image::Luma([0])).save(&black).unwrap();
+        image::GrayImage::from_pixel(64, 64, image::Luma([255])).save(&white).unwrap();
+
+        assert_eq!(frame_quality(black.to_str().unwrap()).unwrap(), (0.0, 0.0));
+        assert_eq!(frame_quality(white.to_str().unwrap()).unwrap(), (0.0, 1.0));
+    }
+
+    #[test]
+    fn half_second_clip_takes_the_midpoint_frame() {
+        assert!(is_short_clip(0.5));
+        assert!(!is_short_clip(2.0));
+        assert_eq!(short_clip_timestamps(0.5), [0.25, 0.0]);
+    }
+
+    #[test]
+    fn probe_accepts_a_half_second_gif_clip() {
+        let info = json!({
+            "streams": [{
+                "codec_type": "video",
+                "width": 480,
+                "height": 480,
+                "r_frame_rate": "0/0",
+                "codec_name": "gif",
+                "duration": "0.500000"
+            }],
+            "format": {}
+        });
+        let video_info = parse_probe(info).unwrap();
+        assert_eq!(video_info.duration_seconds, 0.5);
+        assert_eq!(video_info.fps, 30.0);
+    }
+
+    #[test]
+    fn collect_frames_reads_png_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("frame_12000.png"), b"").unwrap();
+        std::fs::write(dir.path().join("fast_1500.png"), b"").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"").unwrap();
+
+        let mut frames = collect_frames(dir.path()).unwrap();
+        frames.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].timestamp, 1.5);
+        assert!(frames[0].frame_path.ends_with("fast_1500.png"));
+        assert!(frames.iter().all(|f| f.is_keyframe));
+    }
+
+    #[test]
+    fn frame_format_picks_encoder_options() {
+        assert_eq!(FrameFormat::parse(" PNG "), Some(FrameFormat::Png));
+        assert_eq!(FrameFormat::parse("jpg"), Some(FrameFormat::Jpeg));
+        assert_eq!(FrameFormat::parse("webp"), None);
+
+        let png = FrameEncoding {
+            format: FrameFormat::Png,
+            ..FrameEncoding::default()
+        };
+        assert_eq!(png.file_name("frame_%04d"), "frame_%04d.png");
+        assert_eq!(png.codec_args()[0], "-compression_level");
+        assert_eq!(FrameEncoding::default().codec_args(), ["-q:v".to_string(), "2".to_string()]);
+    }
+
+    #[test]
+    fn collect_frames_reads_midpoint_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("midpoint_250.jpg"), b"").unwrap();
+
+        let frames = collect_frames(dir.path()).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].timestamp, 0.25);
+        assert!(frames[0].is_keyframe);
+    }
+
+    #[test]
+    fn output_args_split_one_decode_into_full_and_thumb() {
+        let frames_dir = Path::new("/job/frames");
+        let plain = FrameEncoding::default().output_args("fps=1/2", frames_dir, "regular_%04d", &["-frame_pts", "1"]);
+        assert_eq!(
+            plain,
+            ["-vf", "fps=1/2", "-frame_pts", "1", "-q:v", "2", "/job/frames/regular_%04d.jpg"]
+        );
+
+        let encoding = FrameEncoding {
+            thumb_dimension: Some(320),
+            ..FrameEncoding::default()
+        };
+        let split = encoding.output_args("fps=1/2", frames_dir, "regular_%04d", &["-frame_pts", "1"]);
+        assert_eq!(split[0], "-filter_complex");
+        assert!(split[1].starts_with("fps=1/2,split=2[full][small];[small]scale='min(iw,320)'"));
+        assert_eq!(
+            split[2..],
+            [
+                "-map", "[full]", "-frame_pts", "1", "-q:v", "2", "/job/frames/regular_%04d.jpg",
+                "-map", "[thumb]", "-frame_pts", "1", "-q:v", "2", "/job/frames/thumbs/regular_%04d.jpg",
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_frames_pairs_frames_with_thumbs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join(THUMBS_DIR)).unwrap();
+        std::fs::write(dir.path().join("frame_0012.jpg"), b"").unwrap();
+        std::fs::write(dir.path().join(THUMBS_DIR).join("frame_0012.jpg"), b"").unwrap();
+        std::fs::write(dir.path().join("regular_0040.jpg"), b"").unwrap();
+
+        let mut frames = collect_frames(dir.path()).unwrap();
+        frames.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].thumb_path.as_deref().is_some_and(|p| p.ends_with("thumbs/frame_0012.jpg")));
+        assert_eq!(frames[1].thumb_path, None);
+    }
+
+    #[test]
+    fn preview_starts_at_the_busiest_sharp_window() {
+        let frame = |timestamp: f64, is_keyframe: bool, sharpness: f64| -> FrameData {
+            serde_json::from_value(json!({
+                "timestamp": timestamp,
+                "frame_path": format!("frame_{}.jpg", timestamp),
+                "is_keyframe": is_keyframe,
+                "sharpness": sharpness,
+            }))
+            .unwrap()
+        };
+        let frames = vec![
+            frame(1.0, false, 50.0),
+            frame(10.0, true, 80.0),
+            frame(11.0, true, 100.0),
+            frame(12.5, true, 20.0),
+            frame(20.0, true, 10.0),
+        ];
+
+        assert_eq!(preview_start(&frames, 30.0, 3.0), 10.0);
+        // Windows are kept inside the video
+        assert_eq!(preview_start(&frames[4..], 21.0, 3.0), 18.0);
+        assert_eq!(preview_start(&[], 30.0, 3.0), 13.5);
+        assert_eq!(PreviewFormat::parse(" WebP "), Some(PreviewFormat::Webp));
+    }
+
+    #[test]
+    fn frames_serialize_their_formatted_timestamp() {
+        let frame = FrameData {
+            timestamp: 75.04,
+            frame_path: "frame_75040.jpg".to_string(),
+            thumb_path: None,
+            ocr_text: None,
+            ocr_text_until: None,
+            ocr_boxes: None,
+            lang: None,
+            subtitle_text: None,
+            sharpness: None,
+            brightness: None,
+            is_keyframe: true,
+        };
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!(json["timestamp"], json!(75.04));
+        assert_eq!(json["formatted_timestamp"], "00:01:15.040");
+
+        // Rendered from the timestamp, not read back
+        let mut decoded: FrameData = serde_json::from_value(json).unwrap();
+        decoded.timestamp = 3.5;
+        assert_eq!(decoded.formatted_timestamp(), "00:00:03.500");
+    }
+}
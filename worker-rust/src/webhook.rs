@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::json;
+use sha2::Sha256;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Header carrying the hex HMAC-SHA256 of the request body
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// Pushes job results to an HTTP endpoint on completion
+pub struct Webhook {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+}
+
+impl Webhook {
+    /// Build from WEBHOOK_URL / WEBHOOK_SECRET / WEBHOOK_TIMEOUT_SECS.
+    /// Returns None when WEBHOOK_URL is unset.
+    pub fn from_env() -> Result<Option<Self>> {
+        let url = match std::env::var("WEBHOOK_URL") {
+            Ok(url) if !url.is_empty() => url,
+            _ => return Ok(None),
+        };
+
+        let secret = std::env::var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty());
+        if secret.is_none() {
+            warn!("WEBHOOK_SECRET not set, webhook requests will be unsigned");
+        }
+
+        let timeout_secs: u64 = std::env::var("WEBHOOK_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10);
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(timeout_secs))
+            .build()
+            .context("Failed to build webhook HTTP client")?;
+
+        info!("Webhook delivery enabled: {}", url);
+
+        Ok(Some(Self { client, url, secret }))
+    }
+
+    /// POST the video data for a job, naming the earlier job whose result was
+    /// reused when there is one. Delivery failures are logged, never returned.
+    pub async fn notify<T: Serialize + ?Sized>(
+        &self,
+        job_id: &str,
+        status: &str,
+        video_data: &T,
+        duplicate_of: Option<&str>,
+    ) {
+        let mut payload = json!({
+            "job_id": job_id,
+            "status": status,
+            "video_data": video_data,
+        });
+        if let Some(prior_id) = duplicate_of {
+            payload["duplicate_of"] = json!(prior_id);
+        }
+        let body = payload.to_string();
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self.send(&body).await {
+                Ok(()) => {
+                    info!("Webhook delivered for job {}", job_id);
+                    return;
+                }
+                Err(e) => {
+                    warn!(
+                        "Webhook attempt {}/{} for job {} failed: {}",
+                        attempt, MAX_ATTEMPTS, job_id, e
+                    );
+                    if attempt < MAX_ATTEMPTS {
+                        tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+                    }
+                }
+            }
+        }
+
+        warn!("Giving up on webhook delivery for job {}", job_id);
+    }
+
+    async fn send(&self, body: &str) -> Result<()> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, body)));
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("webhook returned {}", status);
+        }
+
+        Ok(())
+    }
+}
+
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
@@ -1,22 +1,179 @@
-use anyhow::{Context, Result};
-use redis::aio::Connection;
+use anyhow::{anyhow, Context, Result};
+use redis::aio::{Connection, ConnectionManager};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::time::Duration;
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
 use tokio::time::timeout;
-use tracing::{error, info, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, instrument, warn};
 use uuid::Uuid;
 
-use crate::audio;
-use crate::download;
-use crate::ocr;
-use crate::video;
+use crate::config::Config;
+#[cfg(feature = "postgres")]
+use crate::db::ResultStore;
+use crate::dedup::Dedup;
+use crate::download::{self, Section};
+use crate::error::WorkerError;
+use crate::fingerprint::ContentDedup;
+use crate::manifest;
+use crate::pipeline;
+use crate::preflight;
+use crate::redis_conn;
+use crate::result::{AiJob, ProcessResult};
+use crate::stage::{Stage, StageMask};
+#[cfg(feature = "s3")]
+use crate::storage::ObjectStore;
+use crate::transport::{PayloadFormat, RedisResultSink, ResultSink};
+use crate::webhook::Webhook;
+
+/// Times a transiently failing job is requeued before it is dead-lettered
+pub(crate) const DEFAULT_MAX_JOB_RETRIES: u64 = 2;
+
+/// Stream that permanently failed jobs are moved to
+pub(crate) const DEAD_LETTER_STREAM: &str = "queue:video_dead_letter";
+
+/// Per-job processing lock lifetime. Must outlast the slowest job so a
+/// reclaimed message can't be picked up while the original is still running.
+const JOB_LOCK_TTL_SECS: u64 = 3600;
+
+/// Statuses meaning the video stage already finished for a job
+const DONE_STATUSES: &[&str] = &["ai_processing", "completed"];
+
+/// Tries at the final XADD to the AI queue before the message is parked
+const ENQUEUE_ATTEMPTS: u32 = 3;
+
+/// How long a parked AI queue message waits for the job's next attempt
+const PARKED_AI_JOB_TTL_SECS: u64 = 86_400;
+
+/// How long a `job:{id}` record created under CREATE_MISSING_JOBS is kept
+const CREATED_JOB_TTL_SECS: u64 = 7 * 86_400;
+
+/// Jobs remembered as already warned about a missing record; the set is
+/// cleared when it fills, which at worst repeats a warning
+const MAX_WARNED_JOBS: usize = 1024;
+
+/// Limit on each AI queue XADD
+const ENQUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// IDLE_EXIT_SECONDS: how long `run` waits without receiving a job before
+/// returning, so autoscaled deployments can scale to zero. Unset or 0 waits
+/// forever.
+fn idle_exit_after() -> Option<Duration> {
+    std::env::var("IDLE_EXIT_SECONDS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// How long `run` has gone without work, for IDLE_EXIT_SECONDS
+struct IdleTimer {
+    limit: Option<Duration>,
+    since: Instant,
+}
+
+impl IdleTimer {
+    fn new(limit: Option<Duration>, now: Instant) -> Self {
+        Self { limit, since: now }
+    }
+    
+    /// Start counting again: a message arrived or a job finished, or the
+    /// worker was paused for a reason other than an empty queue
+    fn reset(&mut self, now: Instant) {
+        self.since = now;
+    }
+    
+    /// The limit, once it has passed without a reset
+    fn expired(&self, now: Instant) -> Option<Duration> {
+        self.limit.filter(|limit| now.duration_since(self.since) >= *limit)
+    }
+}
+
+/// How often `run` looks for stale pending messages between jobs
+const RECLAIM_INTERVAL: Duration = Duration::from_secs(60);
+
+/// RECLAIM_IDLE_SECS: how long a delivered message may stay unacknowledged
+/// before another consumer takes it over (default 600). Only messages whose
+/// job isn't locked get processed again, so this can be shorter than a job.
+fn reclaim_idle_after() -> Duration {
+    std::env::var("RECLAIM_IDLE_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(600))
+}
+
+/// How long each XREADGROUP waits for a job, and how many it takes at once
+#[derive(Debug, Clone, Copy)]
+struct ReadOptions {
+    block_ms: u64,
+    count: u64,
+}
+
+impl ReadOptions {
+    /// Read READ_BLOCK_MS (default 5000) and READ_COUNT (default 1). A
+    /// shorter block makes shutdown and idle exit react faster at the cost
+    /// of more idle round trips to Redis.
+    fn from_env() -> Result<Self> {
+        let positive = |name: &str, default: u64| -> Result<u64> {
+            match std::env::var(name).ok().filter(|v| !v.trim().is_empty()) {
+                None => Ok(default),
+                Some(v) => match v.trim().parse::<u64>() {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(anyhow!("{} must be a positive integer, got {:?}", name, v)),
+                },
+            }
+        };
+        Ok(Self {
+            block_ms: positive("READ_BLOCK_MS", 5000)?,
+            count: positive("READ_COUNT", 1)?,
+        })
+    }
+}
+
+/// How often an in-flight job checks for `cancel:{job_id}`
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 /// Video worker that processes jobs from Redis queue
 pub struct VideoWorker {
     redis_client: redis::Client,
+    /// Multiplexed connection shared by all non-blocking commands
+    conn: ConnectionManager,
+    /// Publishes finished jobs to `queue:ai_processing`
+    ai_queue: RedisResultSink,
     group_name: String,
     consumer_name: String,
+    webhook: Option<Webhook>,
+    /// Reuses results across jobs for the same URL (DEDUP_RESULTS)
+    dedup: Option<Dedup>,
+    /// Flags videos whose frames match an earlier job's (CONTENT_DEDUP)
+    content_dedup: Option<ContentDedup>,
+    /// Retry limit, frame cap, and output directory
+    config: Config,
+    read: ReadOptions,
+    #[cfg(feature = "s3")]
+    storage: Option<ObjectStore>,
+    #[cfg(feature = "postgres")]
+    results_db: Option<ResultStore>,
+    /// Cancelled on shutdown; each job runs under a child of this token
+    shutdown: CancellationToken,
+}
+
+/// One stream entry as Redis returns it: id and field/value pairs
+type StreamEntry = (String, Vec<(String, String)>);
+
+/// A delivered job message: where to ack it and what it asks for
+#[derive(Debug, Clone, Copy)]
+struct JobMessage<'a> {
+    stream_name: &'a str,
+    message_id: &'a str,
+    job_data: &'a serde_json::Value,
+    job_id: &'a str,
+    url: &'a str,
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,12 +184,20 @@ struct QueueJob {
 }
 
 impl VideoWorker {
-    pub async fn new(redis_url: &str, group_name: &str, consumer_name: Option<&str>) -> Result<Self> {
-        let redis_client = redis::Client::open(redis_url).context("Failed to connect to Redis")?;
+    pub async fn new(config: &Config, group_name: &str, consumer_name: Option<&str>) -> Result<Self> {
+        preflight::preflight().await?;
+        
+        let redis_client = redis_conn::open(&config.redis_url)?;
+        redis_conn::verify(&redis_client).await?;
+        
+        // Shared, auto-reconnecting connection for everything except the
+        // blocking XREADGROUP, which gets its own connection in run()
+        let mut conn = ConnectionManager::new(redis_client.clone())
+            .await
+            .context("Failed to open Redis connection manager")?;
         
         // Create consumer group if it doesn't exist
-        let mut conn = redis_client.get_async_connection().await?;
-        let _: Result<(), _> = redis::cmd("XGROUP")
+        let created: redis::RedisResult<()> = redis::cmd("XGROUP")
             .arg("CREATE")
             .arg("queue:video_processing")
             .arg(group_name)
@@ -40,11 +205,29 @@ impl VideoWorker {
             .arg("MKSTREAM")
             .query_async(&mut conn)
             .await;
+        match created {
+            Ok(()) => info!("Created consumer group {}", group_name),
+            // Another worker (or a previous run) already created it
+            Err(e) if e.code() == Some("BUSYGROUP") => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create consumer group {}", group_name));
+            }
+        }
         
         let consumer_name = consumer_name
             .map(|s| s.to_string())
             .unwrap_or_else(|| format!("consumer-{}", Uuid::new_v4()));
         
+        let read = ReadOptions::from_env()?;
+        let webhook = Webhook::from_env()?;
+        let dedup = Dedup::from_env();
+        let content_dedup = ContentDedup::from_env();
+        #[cfg(feature = "s3")]
+        let storage = ObjectStore::from_env()?;
+        #[cfg(feature = "postgres")]
+        let results_db = ResultStore::from_env().await?;
+        let ai_queue_format = PayloadFormat::from_env()?;
+        
         info!(
             "Video worker initialized: group={}, consumer={}",
             group_name, consumer_name
@@ -52,60 +235,223 @@ impl VideoWorker {
         
         Ok(Self {
             redis_client,
+            ai_queue: RedisResultSink::new(conn.clone(), ai_queue_format),
+            conn,
             group_name: group_name.to_string(),
             consumer_name,
+            webhook,
+            dedup,
+            content_dedup,
+            config: config.clone(),
+            read,
+            #[cfg(feature = "s3")]
+            storage,
+            #[cfg(feature = "postgres")]
+            results_db,
+            shutdown: CancellationToken::new(),
         })
     }
     
     pub async fn run(&self) -> Result<()> {
         info!("Video worker started, waiting for jobs...");
         
-        let output_dir = std::env::var("OUTPUT_DIR").unwrap_or_else(|_| "/tmp/videos".to_string());
+        let output_dir = self.config.output_dir_or("/tmp/videos");
         std::fs::create_dir_all(&output_dir)?;
         
-        loop {
-            match self.process_next_job(&output_dir).await {
-                Ok(true) => {
-                    // Job processed successfully
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                info!("Shutdown requested, cancelling in-flight work...");
+                shutdown.cancel();
+            }
+        });
+        
+        // XREADGROUP BLOCK would stall every other command on a multiplexed
+        // connection, so reads use a dedicated one, reopened after errors
+        let mut reader: Option<Connection> = None;
+        let mut breaker = redis_conn::CircuitBreaker::from_env();
+        let mut disk = preflight::DiskGuard::from_env();
+        let mut idle = IdleTimer::new(idle_exit_after(), Instant::now());
+        let reclaim_idle = reclaim_idle_after();
+        let mut last_reclaim: Option<Instant> = None;
+        
+        while !self.shutdown.is_cancelled() {
+            // Checked only between jobs, so a job in flight always finishes
+            if let Some(limit) = idle.expired(Instant::now()) {
+                info!("No jobs for {}s, exiting (IDLE_EXIT_SECONDS)", limit.as_secs());
+                break;
+            }
+            
+            // Leave jobs queued (for workers with room) rather than fail them
+            if !disk.has_room(std::path::Path::new(&output_dir)) {
+                self.pause(preflight::DISK_RECHECK_INTERVAL).await;
+                // Jobs may be waiting; time spent full isn't time without work
+                idle.reset(Instant::now());
+                continue;
+            }
+            
+            // First pass at startup, for jobs a stopped or crashed worker left
+            if last_reclaim.map_or(true, |at| at.elapsed() >= RECLAIM_INTERVAL) {
+                last_reclaim = Some(Instant::now());
+                match self.reclaim_stale(reclaim_idle, &output_dir).await {
+                    Ok(0) => {}
+                    Ok(count) => {
+                        info!("Reclaimed {} stale job message(s)", count);
+                        idle.reset(Instant::now());
+                    }
+                    Err(e) => warn!("Failed to reclaim stale job messages: {}", e),
+                }
+            }
+            
+            if reader.is_none() {
+                match self.redis_client.get_async_connection().await {
+                    Ok(conn) => reader = Some(conn),
+                    Err(e) => {
+                        error!("Failed to open Redis stream connection: {}", e);
+                        let delay = breaker.record_failure();
+                        self.pause(delay).await;
+                        continue;
+                    }
                 }
-                Ok(false) => {
-                    // No job available, wait a bit
-                    tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            let Some(stream_conn) = reader.as_mut() else { continue };
+            
+            match self.read_next_batch(stream_conn).await {
+                // An empty read already waited out the XREADGROUP BLOCK, so
+                // poll again straight away
+                Ok(None) => breaker.record_success(),
+                Ok(Some((stream_name, messages))) => {
+                    breaker.record_success();
+                    // Counts as activity whether or not the jobs succeed
+                    idle.reset(Instant::now());
+                    self.process_batch(&stream_name, &messages, &output_dir).await;
+                    idle.reset(Instant::now());
                 }
                 Err(e) => {
                     error!("Error processing job: {}", e);
-                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    reader = None;
+                    let delay = if is_redis_error(&e) {
+                        breaker.record_failure()
+                    } else {
+                        redis_conn::with_jitter(Duration::from_secs(5))
+                    };
+                    self.pause(delay).await;
                 }
             }
         }
+        
+        info!("Video worker stopped");
+        
+        Ok(())
+    }
+    
+    /// Sleep for `delay`, waking early on shutdown
+    async fn pause(&self, delay: Duration) {
+        tokio::select! {
+            _ = self.shutdown.cancelled() => {}
+            _ = tokio::time::sleep(delay) => {}
+        }
     }
     
-    async fn process_next_job(&self, output_dir: &str) -> Result<bool> {
-        let mut conn = self.redis_client.get_async_connection().await?;
-        
-        // Read from stream
-        let result: Option<(String, Vec<(String, Vec<(String, String)>)>)> = redis::cmd("XREADGROUP")
+    /// Claim up to READ_COUNT new messages, or None once the block times out
+    async fn read_next_batch(&self, stream_conn: &mut Connection) -> Result<Option<(String, Vec<StreamEntry>)>> {
+        // Reply is one [stream, messages] pair per requested stream, or nil on timeout
+        let result: Option<Vec<(String, Vec<StreamEntry>)>> = redis::cmd("XREADGROUP")
             .arg("GROUP")
             .arg(&self.group_name)
             .arg(&self.consumer_name)
             .arg("COUNT")
-            .arg(1)
+            .arg(self.read.count)
             .arg("BLOCK")
-            .arg(5000) // 5 second timeout
+            .arg(self.read.block_ms)
             .arg("STREAMS")
             .arg("queue:video_processing")
             .arg(">")
-            .query_async(&mut conn)
-            .await
-            .ok()
-            .flatten();
+            .query_async(stream_conn)
+            .await?;
         
-        let (stream_name, messages) = match result {
-            Some((stream, msgs)) if !msgs.is_empty() => (stream, msgs),
-            _ => return Ok(false), // No job available
-        };
+        Ok(result
+            .and_then(|streams| streams.into_iter().next())
+            .filter(|(_, messages)| !messages.is_empty()))
+    }
+    
+    /// Process messages one at a time; the rest of a READ_COUNT batch is
+    /// already claimed by this consumer and waits its turn. A message that
+    /// fails, or is still waiting at shutdown, stays pending for reclaim_stale
+    /// instead of holding up the others. Returns how many were attempted.
+    async fn process_batch(
+        &self,
+        stream_name: &str,
+        messages: &[StreamEntry],
+        output_dir: &str,
+    ) -> usize {
+        let mut attempted = 0;
+        for (message_id, fields) in messages {
+            if self.shutdown.is_cancelled() {
+                info!("Shutting down, leaving {} claimed message(s) pending", messages.len() - attempted);
+                break;
+            }
+            attempted += 1;
+            if let Err(e) = self.process_message(stream_name, message_id, fields, output_dir).await {
+                error!("Error processing message {}: {}", message_id, e);
+            }
+        }
+        attempted
+    }
+    
+    /// XAUTOCLAIM messages delivered to any consumer in the group but left
+    /// unacknowledged for at least `min_idle`, and process them: jobs
+    /// interrupted by a shutdown or crash, messages that failed with an
+    /// error, and batch messages whose consumer went away. Returns how many
+    /// were attempted.
+    async fn reclaim_stale(&self, min_idle: Duration, output_dir: &str) -> Result<usize> {
+        let mut conn = self.conn.clone();
+        let mut cursor = "0-0".to_string();
+        let mut reclaimed = 0;
         
-        let (message_id, fields) = &messages[0];
+        loop {
+            // Reply is [next cursor, [[id, fields], ...]], plus since Redis 7
+            // the ids of pending entries that were deleted from the stream
+            let reply: Vec<redis::Value> = redis::cmd("XAUTOCLAIM")
+                .arg("queue:video_processing")
+                .arg(&self.group_name)
+                .arg(&self.consumer_name)
+                .arg(min_idle.as_millis() as u64)
+                .arg(&cursor)
+                .arg("COUNT")
+                .arg(self.read.count)
+                .query_async(&mut conn)
+                .await?;
+            let (Some(next), Some(entries)) = (reply.first(), reply.get(1)) else {
+                return Err(anyhow!("Unexpected XAUTOCLAIM reply: {:?}", reply));
+            };
+            cursor = redis::from_redis_value(next)?;
+            let entries: Vec<StreamEntry> = redis::from_redis_value(entries)?;
+            
+            // Redis 6.2 still returns deleted entries, without fields
+            let (live, deleted): (Vec<_>, Vec<_>) = entries.into_iter().partition(|(_, fields)| !fields.is_empty());
+            for (message_id, _) in &deleted {
+                self.ack_message(&mut conn, "queue:video_processing", message_id).await?;
+            }
+            if !live.is_empty() {
+                info!("Reclaiming {} message(s) unacknowledged for over {}s", live.len(), min_idle.as_secs());
+                reclaimed += self.process_batch("queue:video_processing", &live, output_dir).await;
+            }
+            
+            if self.shutdown.is_cancelled() || cursor == "0-0" {
+                return Ok(reclaimed);
+            }
+        }
+    }
+    
+    async fn process_message(
+        &self,
+        stream_name: &str,
+        message_id: &str,
+        fields: &[(String, String)],
+        output_dir: &str,
+    ) -> Result<()> {
+        let mut conn = self.conn.clone();
         
         // Parse job data
         let job_data: serde_json::Value = fields
@@ -133,179 +479,1181 @@ impl VideoWorker {
             .as_str()
             .context("URL not found")?;
         
+        // Re-delivered jobs that already finished just need acking
+        if let Some(status) = self.job_status(&mut conn, job_id).await? {
+            if DONE_STATUSES.contains(&status.as_str()) {
+                info!("Job {} already {}, skipping", job_id, status);
+                self.ack_message(&mut conn, stream_name, message_id).await?;
+                return Ok(());
+            }
+        }
+        
+        if !self.acquire_job_lock(&mut conn, job_id).await? {
+            // Leave the message pending; whoever holds the lock will ack it
+            info!("Job {} is being processed by another worker, skipping", job_id);
+            return Ok(());
+        }
+        
         info!("Processing job {}: {}", job_id, url);
         
-        // Update job status
-        self.update_job_status(&mut conn, job_id, "downloading", 10).await?;
+        let cancel = self.shutdown.child_token();
+        let poller = self.spawn_cancel_poller(job_id, cancel.clone());
         
-        // Step 1: Download video
-        let video_result = download::download_video(url, &output_dir, job_id).await;
+        let message = JobMessage {
+            stream_name,
+            message_id,
+            job_data: &job_data,
+            job_id,
+            url,
+        };
+        let outcome = pipeline::cancellable(&cancel, self.run_job(&mut conn, &message, output_dir)).await;
         
-        match video_result {
-            Ok(video_path) => {
-                self.update_job_status(&mut conn, job_id, "processing_video", 25).await?;
-                
-                // Step 2: Process video metadata
-                let video_info = match video::process_video(&video_path, &output_dir, job_id).await {
-                    Ok(info) => info,
-                    Err(e) => {
-                        warn!("Failed to extract video metadata: {}", e);
-                        self.fail_job(&mut conn, job_id, &format!("Video processing failed: {}", e)).await?;
-                        self.ack_message(&mut conn, &stream_name, message_id).await?;
-                        return Ok(true);
-                    }
-                };
-                
-                // Step 3: Extract frames
-                self.update_job_status(&mut conn, job_id, "extracting_ocr", 40).await?;
-                let frames = match video::extract_keyframes(&video_path, &output_dir, job_id).await {
-                    Ok(f) => f,
-                    Err(e) => {
-                        warn!("Failed to extract frames: {}", e);
-                        Vec::new()
-                    }
-                };
-                
-                // Step 4: OCR on frames
-                let frames_with_ocr = match ocr::process_frames(frames).await {
-                    Ok(f) => f,
-                    Err(e) => {
-                        warn!("OCR processing failed: {}", e);
-                        Vec::new()
-                    }
-                };
-                
-                // Step 5: Extract audio
-                self.update_job_status(&mut conn, job_id, "transcribing_audio", 60).await?;
-                let audio_path = audio::extract_audio(&video_path, &output_dir, job_id).await.ok();
-                
-                // Step 6: Transcribe audio
-                let transcription = if let Some(ref path) = audio_path {
-                    audio::transcribe_audio(path).await.unwrap_or_default()
+        poller.abort();
+        
+        if let Err(e) = self.release_job_lock(&mut conn, job_id).await {
+            warn!("Failed to release lock for job {}: {}", job_id, e);
+        }
+        
+        match outcome {
+            Err(e) if matches!(e.downcast_ref::<WorkerError>(), Some(WorkerError::Cancelled)) => {
+                if self.shutdown.is_cancelled() {
+                    // Leave the message pending; reclaim_stale in this or
+                    // another worker runs the job again once it goes idle
+                    warn!("Job {} interrupted by shutdown", job_id);
+                    self.update_job_status(&mut conn, job_id, "pending", 0).await?;
                 } else {
-                    String::new()
-                };
-                
-                // Step 7: Queue for AI processing
-                self.update_job_status(&mut conn, job_id, "ai_processing", 80).await?;
-                
-                let video_data = json!({
-                    "job_id": job_id,
-                    "video_path": video_path,
-                    "duration_seconds": video_info.duration_seconds,
-                    "resolution": {
-                        "width": video_info.width,
-                        "height": video_info.height,
-                    },
-                    "fps": video_info.fps,
-                    "frames": frames_with_ocr,
-                    "audio_path": audio_path,
-                    "transcription": transcription,
-                });
+                    info!("Job {} cancelled", job_id);
+                    self.update_job_status(&mut conn, job_id, "cancelled", 0).await?;
+                    self.ack_message(&mut conn, stream_name, message_id).await?;
+                }
                 
-                // Send to AI queue
-                redis::cmd("XADD")
-                    .arg("queue:ai_processing")
-                    .arg("*")
-                    .arg("job_id")
-                    .arg(job_id)
-                    .arg("video_data")
-                    .arg(video_data.to_string())
+                let _: () = redis::cmd("DEL")
+                    .arg(format!("cancel:{}", job_id))
                     .query_async(&mut conn)
                     .await?;
+            }
+            other => other?,
+        }
+        
+        Ok(())
+    }
+    
+    /// Poll `cancel:{job_id}` and cancel the token once it's set
+    fn spawn_cancel_poller(&self, job_id: &str, cancel: CancellationToken) -> tokio::task::JoinHandle<()> {
+        let mut conn = self.conn.clone();
+        let key = format!("cancel:{}", job_id);
+        
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(CANCEL_POLL_INTERVAL).await;
                 
-                // Acknowledge message
-                self.ack_message(&mut conn, &stream_name, message_id).await?;
+                let requested = redis::cmd("EXISTS")
+                    .arg(&key)
+                    .query_async::<_, bool>(&mut conn)
+                    .await
+                    .unwrap_or(false);
                 
-                info!("Job {} sent to AI processing queue", job_id);
+                if requested {
+                    cancel.cancel();
+                    return;
+                }
+            }
+        })
+    }
+    
+    async fn job_status(&self, conn: &mut ConnectionManager, job_id: &str) -> Result<Option<String>> {
+        let data: Option<String> = redis::cmd("GET")
+            .arg(format!("job:{}", job_id))
+            .query_async(conn)
+            .await?;
+        
+        Ok(data
+            .and_then(|d| serde_json::from_str::<serde_json::Value>(&d).ok())
+            .and_then(|job| job["status"].as_str().map(|s| s.to_string())))
+    }
+    
+    async fn acquire_job_lock(&self, conn: &mut ConnectionManager, job_id: &str) -> Result<bool> {
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(format!("lock:job:{}", job_id))
+            .arg(&self.consumer_name)
+            .arg("NX")
+            .arg("EX")
+            .arg(JOB_LOCK_TTL_SECS)
+            .query_async(conn)
+            .await?;
+        
+        Ok(acquired.is_some())
+    }
+    
+    async fn release_job_lock(&self, conn: &mut ConnectionManager, job_id: &str) -> Result<()> {
+        // Only delete the lock if we still own it
+        let script = redis::Script::new(
+            r#"
+            if redis.call('get', KEYS[1]) == ARGV[1] then
+                return redis.call('del', KEYS[1])
+            end
+            return 0
+        "#,
+        );
+        
+        let _: i32 = script
+            .key(format!("lock:job:{}", job_id))
+            .arg(&self.consumer_name)
+            .invoke_async(conn)
+            .await?;
+        
+        Ok(())
+    }
+    
+    /// Run the pipeline for one job; the span ties all stage logs to the job_id
+    #[instrument(skip_all, fields(job_id = %message.job_id))]
+    async fn run_job(&self, conn: &mut ConnectionManager, message: &JobMessage<'_>, output_dir: &str) -> Result<()> {
+        let JobMessage { stream_name, message_id, job_data, job_id, url } = *message;
+        let probe_only = job_data["probe_only"].as_bool().unwrap_or(false);
+        let parsed = StageMask::from_job(job_data)
+            .and_then(|stages| Ok((stages, Section::from_job(job_data)?)));
+        let (stages, section) = match parsed {
+            Ok(parsed) => parsed,
+            Err(reason) => {
+                let e = WorkerError::InvalidJob(reason);
+                error!("Rejecting job {}: {}", job_id, e);
+                self.handle_job_error(conn, stream_name, message_id, job_data, Stage::Downloading, &e)
+                    .await?;
+                return Ok(());
+            }
+        };
+        
+        // An earlier attempt finished the work but couldn't reach the AI queue
+        if let Some(ai_job) = load_parked_ai_job(conn, job_id).await {
+            info!("Job {}: Publishing the result parked by an earlier attempt", job_id);
+            // A duplicate's result is already recorded under the job it came from
+            #[cfg(feature = "postgres")]
+            if ai_job.duplicate_of.is_none() {
+                if let Err(e) = self.record_result(&ai_job).await {
+                    return self
+                        .handle_job_error(conn, stream_name, message_id, job_data, Stage::Enqueue, &e)
+                        .await;
+                }
+            }
+            if let Err(e) = self.enqueue_ai_job(&ai_job).await {
+                let e = WorkerError::Enqueue(format!("{:#}", e));
+                return self
+                    .handle_job_error(conn, stream_name, message_id, job_data, Stage::Enqueue, &e)
+                    .await;
             }
+            let removed: redis::RedisResult<i64> =
+                redis::cmd("DEL").arg(parked_ai_job_key(job_id)).query_async(conn).await;
+            if let Err(e) = removed {
+                warn!("Failed to remove parked AI queue message for job {}: {}", job_id, e);
+            }
+            self.update_job_status(conn, job_id, Stage::Enqueue.status(), Stage::Enqueue.progress().into())
+                .await?;
+            return self.finish_published(conn, message, &ai_job, section).await;
+        }
+        
+        // A partial re-run is explicitly asking for fresh output, and results
+        // are keyed by URL alone, so a section can't reuse the whole video's
+        if let (Some(dedup), false, true, None) = (&self.dedup, probe_only, stages.is_all(), section) {
+            match dedup.lookup(conn, url).await {
+                Ok(Some((prior_id, video_data))) if prior_id != job_id => {
+                    return self
+                        .complete_duplicate(conn, message, &prior_id, video_data)
+                        .await;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Duplicate lookup failed for job {}: {}", job_id, e),
+            }
+        }
+        
+        let reporter = StageReporter::spawn(self.conn.clone(), job_id);
+        let progress = |stage: Stage, percent: u8| reporter.report(stage, percent);
+        
+        if probe_only {
+            // Metadata only; streams the probe when the platform allows it
+            let result = match pipeline::probe(url, output_dir, job_id, &progress).await {
+                Ok(result) => result,
+                Err(e) => {
+                    return self
+                        .fail_fetch(conn, message, output_dir, &reporter, &e)
+                        .await;
+                }
+            };
+            reporter.finish().await;
+            
+            let probe_data = serde_json::to_value(&result)?;
+            self.complete_probe_job(conn, job_id, &probe_data).await?;
+            self.ack_message(conn, stream_name, message_id).await?;
+            
+            info!("Job {} probe-only run complete", job_id);
+            
+            if let Some(webhook) = &self.webhook {
+                webhook.notify(job_id, "completed", &probe_data, None).await;
+            }
+            return Ok(());
+        }
+        
+        // Steps 1-2: Download and probe
+        let (video, video_info) = match pipeline::fetch(url, output_dir, job_id, stages, section, &progress, &pipeline::ignore_events).await {
+            Ok(fetched) => fetched,
             Err(e) => {
-                error!("Failed to download video for job {}: {}", job_id, e);
-                self.fail_job(&mut conn, job_id, &format!("Download failed: {}", e)).await?;
-                self.ack_message(&mut conn, &stream_name, message_id).await?;
+                return self
+                    .fail_fetch(conn, message, output_dir, &reporter, &e)
+                    .await;
+            }
+        };
+        
+        // Steps 3-6: Frames, OCR, audio, transcription
+        let ctx = pipeline::JobContext {
+            job_id,
+            output_dir,
+            config: &self.config,
+            stages,
+            progress: &progress,
+            events: &pipeline::ignore_events,
+        };
+        let mut result = pipeline::analyze(&ctx, &video, video_info).await;
+        
+        // Before the upload replaces frame paths with URLs. A section's frames
+        // are only part of a video, so they neither match nor get recorded.
+        if let (Some(content_dedup), None) = (&self.content_dedup, section) {
+            match content_dedup.check_and_record(conn, job_id, &result.frames).await {
+                Ok(Some(found)) => {
+                    info!(
+                        "Job {}: Frames match job {} ({:.0}% similar), flagging as a duplicate",
+                        job_id,
+                        found.job_id,
+                        found.similarity * 100.0
+                    );
+                    result.content_match_of = Some(found.job_id);
+                    result.content_match_similarity = Some(found.similarity);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Content fingerprinting failed for job {}: {}", job_id, e),
+            }
+        }
+        
+        // Kept with the artifacts so a later partial re-run can reuse it
+        let result_path = pipeline::result_path(output_dir, job_id);
+        match serde_json::to_vec_pretty(&result) {
+            Ok(json) => {
+                if let Err(e) = pipeline::write_atomic(&result_path, &json) {
+                    warn!("Failed to write {:?} for job {}: {}", result_path, job_id, e);
+                }
             }
+            Err(e) => warn!("Failed to serialize result for job {}: {}", job_id, e),
         }
         
-        Ok(true)
+        if manifest::manifest_enabled() {
+            match manifest::write_manifest(&pipeline::job_dir(output_dir, job_id), job_id).await {
+                Ok(path) => {
+                    info!("Manifest for job {} saved to {:?}", job_id, path);
+                    #[cfg(feature = "s3")]
+                    if let Some(storage) = &self.storage {
+                        if let Err(e) = storage.upload_file(job_id, &path, "application/json").await {
+                            warn!("Failed to upload manifest for job {}: {}", job_id, e);
+                        }
+                    }
+                }
+                Err(e) => warn!("Failed to write manifest for job {}: {}", job_id, e),
+            }
+        }
+        
+        // Step 7: Queue for AI processing
+        progress(Stage::Enqueue, Stage::Enqueue.progress());
+        reporter.finish().await;
+        
+        #[cfg(feature = "s3")]
+        if let Some(storage) = &self.storage {
+            storage.upload_assets(&mut result).await;
+        }
+        
+        let ai_job = AiJob::new(job_id, url, result);
+        
+        #[cfg(feature = "s3")]
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage
+                .upload_bytes(job_id, "result.json", &serde_json::to_vec(&ai_job.video_data)?, "application/json")
+                .await
+            {
+                warn!("Failed to upload results for job {}: {}", job_id, e);
+            }
+        }
+        
+        #[cfg(feature = "postgres")]
+        if let Err(e) = self.record_result(&ai_job).await {
+            return self
+                .handle_job_error(conn, stream_name, message_id, job_data, Stage::Enqueue, &e)
+                .await;
+        }
+        
+        // Send to AI queue; a failure is retried like any transient error,
+        // and the retry publishes the parked message instead of starting over
+        if let Err(e) = self.enqueue_ai_job(&ai_job).await {
+            let e = WorkerError::Enqueue(format!("{:#}", e));
+            return self
+                .handle_job_error(conn, stream_name, message_id, job_data, Stage::Enqueue, &e)
+                .await;
+        }
+        
+        self.finish_published(conn, message, &ai_job, section).await
     }
     
-    async fn update_job_status(
+    /// Record a finished result in Postgres. Runs before publishing, so a
+    /// strict failure (the only error returned) retries or dead-letters a job
+    /// the AI worker never saw. The row is upserted, so a retry that gets
+    /// further just refreshes it.
+    #[cfg(feature = "postgres")]
+    async fn record_result(&self, ai_job: &AiJob) -> std::result::Result<(), WorkerError> {
+        let Some(db) = &self.results_db else {
+            return Ok(());
+        };
+        if let Err(e) = db.record(&ai_job.source_url, Stage::Enqueue.status(), &ai_job.video_data).await {
+            if db.strict {
+                return Err(e);
+            }
+            warn!("Failed to record job {} in Postgres: {}", ai_job.job_id, e);
+        }
+        Ok(())
+    }
+    
+    /// Bookkeeping once a job's result is on the AI queue, the same whether
+    /// it was just produced, reused from a duplicate, or replayed from a
+    /// message an earlier attempt parked
+    async fn finish_published(
         &self,
-        conn: &mut Connection,
-        job_id: &str,
-        status: &str,
-        progress: i32,
+        conn: &mut ConnectionManager,
+        message: &JobMessage<'_>,
+        ai_job: &AiJob,
+        section: Option<Section>,
     ) -> Result<()> {
-        let job_key = format!("job:{}", job_id);
-        
-        let script = r#"
-            local job = redis.call('get', KEYS[1])
-            if job then
-                local data = cjson.decode(job)
-                data.status = ARGV[1]
-                data.progress = tonumber(ARGV[2])
-                data.updated_at = ARGV[3]
-                redis.call('set', KEYS[1], cjson.encode(data))
-                return 1
-            end
-            return 0
-        "#;
+        let JobMessage { stream_name, message_id, job_id, url, .. } = *message;
         
-        let now = chrono::Utc::now().to_rfc3339();
+        if let (Some(dedup), true) = (&self.dedup, records_for_dedup(ai_job, section)) {
+            if let Err(e) = dedup.record(conn, url, job_id, &ai_job.video_data).await {
+                warn!("Failed to record job {} for deduplication: {}", job_id, e);
+            }
+        }
         
-        // Use regular get/set since Lua cjson might not be available
-        let job_data: Option<String> = redis::cmd("GET")
-            .arg(&job_key)
-            .query_async(conn)
-            .await?;
+        // Acknowledge message
+        self.ack_message(conn, stream_name, message_id).await?;
         
-        if let Some(data) = job_data {
-            let mut job: serde_json::Value = serde_json::from_str(&data)?;
-            job["status"] = json!(status);
-            job["progress"] = json!(progress);
-            job["updated_at"] = json!(now);
-            
-            redis::cmd("SET")
-                .arg(&job_key)
-                .arg(job.to_string())
-                .query_async(conn)
-                .await?;
+        info!("Job {} sent to AI processing queue", job_id);
+        
+        if let Some(webhook) = &self.webhook {
+            webhook
+                .notify(job_id, "ai_processing", &ai_job.video_data, ai_job.duplicate_of.as_deref())
+                .await;
         }
         
         Ok(())
     }
     
-    async fn fail_job(&self, conn: &mut Connection, job_id: &str, error: &str) -> Result<()> {
-        self.update_job_status(conn, job_id, "failed", 0).await?;
+    /// Publish to the AI queue, retrying with a timeout per attempt. If every
+    /// attempt fails the message is parked in Redis, so the job's retry (see
+    /// handle_job_error) publishes it without processing the video again.
+    async fn enqueue_ai_job(&self, ai_job: &AiJob) -> Result<()> {
+        let job_id = &ai_job.job_id;
+        let mut last_error = anyhow!("no enqueue attempts made");
         
-        let job_key = format!("job:{}", job_id);
-        let job_data: Option<String> = redis::cmd("GET")
-            .arg(&job_key)
-            .query_async(conn)
-            .await?;
+        for attempt in 1..=ENQUEUE_ATTEMPTS {
+            last_error = match timeout(ENQUEUE_TIMEOUT, self.ai_queue.publish(ai_job)).await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(e)) => e,
+                Err(_) => anyhow!("timed out after {:?}", ENQUEUE_TIMEOUT),
+            };
+            warn!(
+                "Job {}: AI queue enqueue attempt {}/{} failed: {}",
+                job_id, attempt, ENQUEUE_ATTEMPTS, last_error
+            );
+            if attempt < ENQUEUE_ATTEMPTS {
+                tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+            }
+        }
         
-        if let Some(data) = job_data {
-            let mut job: serde_json::Value = serde_json::from_str(&data)?;
-            job["error_message"] = json!(error);
+        let key = parked_ai_job_key(job_id);
+        let parked = async {
+            let _: () = redis::cmd("SET")
+                .arg(&key)
+                .arg(serde_json::to_string(ai_job)?)
+                .arg("EX")
+                .arg(PARKED_AI_JOB_TTL_SECS)
+                .query_async(&mut self.conn.clone())
+                .await?;
+            Ok::<(), anyhow::Error>(())
+        }
+        .await;
+        match parked {
+            Ok(()) => error!("Job {}: Parked AI queue message at {} for the next attempt", job_id, key),
+            Err(e) => error!("Job {}: Failed to park AI queue message: {}", job_id, e),
+        }
+        
+        Err(last_error.context(format!("Failed to enqueue job {} for AI processing", job_id)))
+    }
+    
+    /// Finish a job by re-sending an earlier job's result for the same video.
+    /// Asset paths in the result still point at the earlier job's files.
+    async fn complete_duplicate(
+        &self,
+        conn: &mut ConnectionManager,
+        message: &JobMessage<'_>,
+        prior_id: &str,
+        mut video_data: ProcessResult,
+    ) -> Result<()> {
+        let JobMessage { stream_name, message_id, job_data, job_id, url } = *message;
+        info!("Job {} duplicates completed job {}, reusing its result", job_id, prior_id);
+        
+        video_data.job_id = job_id.to_string();
+        let mut ai_job = AiJob::new(job_id, url, video_data);
+        ai_job.duplicate_of = Some(prior_id.to_string());
+        
+        if let Err(e) = self.enqueue_ai_job(&ai_job).await {
+            let e = WorkerError::Enqueue(format!("{:#}", e));
+            return self
+                .handle_job_error(conn, stream_name, message_id, job_data, Stage::Enqueue, &e)
+                .await;
+        }
+        self.update_job(
+            conn,
+            job_id,
+            json!({
+                "status": Stage::Enqueue.status(),
+                "progress": Stage::Enqueue.progress(),
+                "stage": Stage::Enqueue.name(),
+                "duplicate_of": prior_id,
+            }),
+        )
+        .await?;
+        self.finish_published(conn, message, &ai_job, None).await
+    }
+    
+    /// Record a download/probe failure, dropping partial downloads unless the
+    /// job will be retried and can resume them
+    async fn fail_fetch(
+        &self,
+        conn: &mut ConnectionManager,
+        message: &JobMessage<'_>,
+        output_dir: &str,
+        reporter: &StageReporter,
+        error: &WorkerError,
+    ) -> Result<()> {
+        let JobMessage { stream_name, message_id, job_data, job_id, .. } = *message;
+        let stage = reporter.finish().await.unwrap_or(Stage::Downloading);
+        error!("Failed to fetch video for job {}: {}", job_id, error);
+        if !will_retry(job_data, error, self.config.max_job_retries) {
+            download::remove_partial_downloads(&pipeline::job_dir(output_dir, job_id));
+        }
+        self.handle_job_error(conn, stream_name, message_id, job_data, stage, error)
+            .await
+    }
+    
+    /// Retry transient failures by re-enqueuing; dead-letter everything else
+    async fn handle_job_error(
+        &self,
+        conn: &mut ConnectionManager,
+        stream: &str,
+        message_id: &str,
+        job_data: &serde_json::Value,
+        stage: Stage,
+        error: &WorkerError,
+    ) -> Result<()> {
+        let job_id = job_data["job_id"].as_str().unwrap_or_default();
+        let attempt = attempt_number(job_data);
+        
+        if will_retry(job_data, error, self.config.max_job_retries) {
+            warn!(
+                "Job {} failed transiently at {} (attempt {}/{}), requeueing: {}",
+                job_id, stage.name(), attempt, self.config.max_job_retries + 1, error
+            );
+            
+            let mut retry = job_data.clone();
+            retry["attempt"] = json!(attempt);
+            
+            let _: String = redis::cmd("XADD")
+                .arg("queue:video_processing")
+                .arg("*")
+                .arg("job_id")
+                .arg(job_id)
+                .arg("data")
+                .arg(retry.to_string())
+                .query_async(conn)
+                .await?;
             
-            redis::cmd("SET")
-                .arg(&job_key)
-                .arg(job.to_string())
+            self.update_job(
+                conn,
+                job_id,
+                json!({
+                    "status": "pending",
+                    "progress": 0,
+                    "attempts": attempt,
+                    "last_error": error.to_string(),
+                }),
+            )
+            .await?;
+        } else {
+            error!(
+                "Job {} failed permanently at {} ({}): {}",
+                job_id, stage.name(), error.kind(), error
+            );
+            
+            let _: String = redis::cmd("XADD")
+                .arg(DEAD_LETTER_STREAM)
+                .arg("*")
+                .arg("job_id")
+                .arg(job_id)
+                .arg("data")
+                .arg(job_data.to_string())
+                .arg("stage")
+                .arg(stage.name())
+                .arg("error_kind")
+                .arg(error.kind())
+                .arg("error")
+                .arg(error.to_string())
+                .arg("failed_at")
+                .arg(chrono::Utc::now().to_rfc3339())
                 .query_async(conn)
                 .await?;
+            
+            self.fail_job(conn, job_id, stage, error, attempt).await?;
         }
         
-        Ok(())
+        self.ack_message(conn, stream, message_id).await
+    }
+    
+    async fn update_job_status(
+        &self,
+        conn: &mut ConnectionManager,
+        job_id: &str,
+        status: &str,
+        progress: i32,
+    ) -> Result<()> {
+        self.update_job(conn, job_id, json!({ "status": status, "progress": progress }))
+            .await
     }
     
-    async fn ack_message(&self, conn: &mut Connection, stream: &str, id: &str) -> Result<()> {
-        redis::cmd("XACK")
+    /// Merge `fields` into `job:{id}` and bump updated_at; see update_job_fields
+    async fn update_job(
+        &self,
+        conn: &mut ConnectionManager,
+        job_id: &str,
+        fields: serde_json::Value,
+    ) -> Result<()> {
+        update_job_fields(conn, job_id, fields).await
+    }
+    
+    /// Probe-only jobs never reach the AI worker, so finish them here
+    async fn complete_probe_job(
+        &self,
+        conn: &mut ConnectionManager,
+        job_id: &str,
+        probe_data: &serde_json::Value,
+    ) -> Result<()> {
+        self.update_job(
+            conn,
+            job_id,
+            json!({
+                "status": "completed",
+                "progress": 100,
+                "probe_result": probe_data,
+            }),
+        )
+        .await
+    }
+    
+    async fn fail_job(
+        &self,
+        conn: &mut ConnectionManager,
+        job_id: &str,
+        stage: Stage,
+        error: &WorkerError,
+        attempts: u64,
+    ) -> Result<()> {
+        self.update_job(
+            conn,
+            job_id,
+            json!({
+                "status": "failed",
+                "progress": 0,
+                "failed_stage": stage.name(),
+                "error_kind": error.kind(),
+                "error_message": error.to_string(),
+                "attempts": attempts,
+            }),
+        )
+        .await
+    }
+    
+    /// XACK `id`. Redis acks nothing, without an error, when the message
+    /// isn't pending for this group: already acked by another consumer (the
+    /// job ran twice), or `stream` isn't the key it was read from.
+    async fn ack_message(&self, conn: &mut ConnectionManager, stream: &str, id: &str) -> Result<()> {
+        let acked: i64 = redis::cmd("XACK")
             .arg(stream)
             .arg(&self.group_name)
             .arg(id)
             .query_async(conn)
             .await?;
         
+        if acked == 0 {
+            warn!(
+                "XACK of message {} on {} (group {}) acknowledged nothing; it was not pending, \
+                 so it was already acked elsewhere or read from a different stream",
+                id, stream, self.group_name
+            );
+        }
+        
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Whether `error` came from talking to Redis rather than from the job
+fn is_redis_error(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<redis::RedisError>().is_some()
+        || matches!(error.downcast_ref::<WorkerError>(), Some(WorkerError::Redis(_)))
+}
+
+/// Where an AI queue message that couldn't be published is kept. In Redis
+/// rather than the job directory, since the retry may run on another worker.
+fn parked_ai_job_key(job_id: &str) -> String {
+    format!("parked_ai_job:{}", job_id)
+}
+
+/// Whether a published result becomes the one reused for its URL. A section
+/// covers only part of the video, and a duplicate's result is already
+/// recorded under the job it came from.
+fn records_for_dedup(ai_job: &AiJob, section: Option<Section>) -> bool {
+    ai_job.duplicate_of.is_none() && section.is_none()
+}
+
+/// The AI queue message an earlier attempt parked, if any
+async fn load_parked_ai_job(conn: &mut ConnectionManager, job_id: &str) -> Option<AiJob> {
+    let key = parked_ai_job_key(job_id);
+    let raw: Option<String> = match redis::cmd("GET").arg(&key).query_async(conn).await {
+        Ok(raw) => raw,
+        Err(e) => {
+            warn!("Failed to check {} for a parked AI queue message: {}", key, e);
+            None
+        }
+    };
+    match serde_json::from_str(&raw?) {
+        Ok(ai_job) => Some(ai_job),
+        Err(e) => {
+            warn!("Ignoring unreadable parked AI queue message {}: {}", key, e);
+            None
+        }
+    }
+}
+
+/// 1-based attempt number of the run that just finished
+fn attempt_number(job_data: &serde_json::Value) -> u64 {
+    job_data["attempt"].as_u64().unwrap_or(0) + 1
+}
+
+/// Whether handle_job_error will requeue this failure rather than dead-letter it.
+/// Only transient errors (downloads, Redis, I/O) are retried.
+fn will_retry(job_data: &serde_json::Value, error: &WorkerError, max_retries: u64) -> bool {
+    error.is_transient() && attempt_number(job_data) <= max_retries
+}
+
+/// CREATE_MISSING_JOBS: create a minimal `job:{id}` record when a status
+/// update finds none, for producers that enqueue without writing one
+fn create_missing_jobs() -> bool {
+    std::env::var("CREATE_MISSING_JOBS")
+        .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Apply `fields` to a job record: null removes the field, anything else
+/// overwrites it, and `updated_at` is bumped
+fn merge_job_fields(job: &mut serde_json::Value, fields: &serde_json::Value) {
+    if let (Some(job), Some(fields)) = (job.as_object_mut(), fields.as_object()) {
+        for (k, v) in fields {
+            if v.is_null() {
+                job.remove(k);
+            } else {
+                job.insert(k.clone(), v.clone());
+            }
+        }
+        job.insert("updated_at".to_string(), json!(chrono::Utc::now().to_rfc3339()));
+    }
+}
+
+/// Whether `job_id` has not been warned about a missing record yet; a job
+/// updates its status about eight times, and once is enough to say so
+fn first_missing_record(job_id: &str) -> bool {
+    static WARNED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    
+    let mut warned = WARNED
+        .get_or_init(|| Mutex::new(HashSet::new()))
+        .lock()
+        .unwrap();
+    if warned.len() >= MAX_WARNED_JOBS {
+        warned.clear();
+    }
+    warned.insert(job_id.to_string())
+}
+
+/// Merge `fields` into `job:{id}` and bump updated_at; null values remove the
+/// field, and the record keeps any expiry it has. A missing record is created
+/// with a CREATED_JOB_TTL_SECS expiry when CREATE_MISSING_JOBS is set and
+/// otherwise skipped with a warning (once per job). Status changes are also
+/// published for live UIs when JOB_EVENTS is set.
+async fn update_job_fields(
+    conn: &mut ConnectionManager,
+    job_id: &str,
+    fields: serde_json::Value,
+) -> Result<()> {
+    let job_key = format!("job:{}", job_id);
+    
+    // Use regular get/set since Lua cjson might not be available
+    let job_data: Option<String> = redis::cmd("GET")
+        .arg(&job_key)
+        .query_async(conn)
+        .await?;
+    
+    let created = job_data.is_none();
+    let mut job: serde_json::Value = match job_data {
+        Some(data) => serde_json::from_str(&data)?,
+        None if create_missing_jobs() => {
+            info!("Job {} has no {} record, creating one", job_id, job_key);
+            json!({ "job_id": job_id, "created_at": chrono::Utc::now().to_rfc3339() })
+        }
+        None if first_missing_record(job_id) => {
+            warn!(
+                "Job {} has no {} record, dropping its status updates; was it enqueued without one? \
+                 Set CREATE_MISSING_JOBS=true to create it",
+                job_id, job_key
+            );
+            return Ok(());
+        }
+        None => {
+            debug!("Job {} has no {} record, dropping update {}", job_id, job_key, fields);
+            return Ok(());
+        }
+    };
+    merge_job_fields(&mut job, &fields);
+    
+    let mut set = redis::cmd("SET");
+    set.arg(&job_key).arg(job.to_string());
+    if created {
+        set.arg("EX").arg(CREATED_JOB_TTL_SECS);
+    } else {
+        set.arg("KEEPTTL");
+    }
+    let _: () = set.query_async(conn).await?;
+    
+    publish_job_event(conn, job_id, &fields).await;
+    
+    Ok(())
+}
+
+/// Pub/sub channel for `job_id`'s status events from JOB_EVENTS: "global"
+/// publishes every job to `job_events`, "per_job" to `events:{job_id}`.
+/// Unset publishes nothing.
+fn job_events_channel(job_id: &str) -> Option<String> {
+    match std::env::var("JOB_EVENTS").unwrap_or_default().trim().to_lowercase().as_str() {
+        "global" => Some("job_events".to_string()),
+        "per_job" | "per-job" => Some(format!("events:{}", job_id)),
+        _ => None,
+    }
+}
+
+/// PUBLISH a status change. Best-effort: a subscriber that misses one can
+/// still read `job:{id}`, so failures are only logged.
+async fn publish_job_event(conn: &mut ConnectionManager, job_id: &str, fields: &serde_json::Value) {
+    let (Some(channel), Some(status)) = (job_events_channel(job_id), fields.get("status")) else {
+        return;
+    };
+    let event = json!({
+        "job_id": job_id,
+        "status": status,
+        "stage": fields.get("stage").or_else(|| fields.get("failed_stage")),
+        "progress": fields.get("progress"),
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    });
+    
+    let published: redis::RedisResult<i64> = redis::cmd("PUBLISH")
+        .arg(&channel)
+        .arg(event.to_string())
+        .query_async(conn)
+        .await;
+    if let Err(e) = published {
+        warn!("Failed to publish event for job {} to {}: {}", job_id, channel, e);
+    }
+}
+
+/// Which dead-lettered jobs `requeue_dead_letters` replays
+#[derive(Debug, Clone, Default)]
+pub struct RequeueFilter {
+    /// Only failures of this kind (WorkerError::kind, e.g. "download")
+    pub error_kind: Option<String>,
+    pub job_id: Option<String>,
+    /// Stop after this many jobs
+    pub limit: Option<usize>,
+}
+
+/// A job found on the dead-letter stream
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub entry_id: String,
+    pub job_id: String,
+    pub error_kind: String,
+    pub error: String,
+}
+
+/// Entries read from the dead-letter stream per XRANGE
+const DEAD_LETTER_PAGE: usize = 100;
+
+/// Move dead-lettered jobs matching `filter` back onto
+/// `queue:video_processing`, oldest first, with their attempt count reset
+/// and the failure fields cleared from `job:{id}`. Each replayed entry is
+/// removed from the dead-letter stream so it can't be requeued twice. With
+/// `dry_run`, only reports what would be requeued.
+pub async fn requeue_dead_letters(config: &Config, filter: &RequeueFilter, dry_run: bool) -> Result<Vec<DeadLetter>> {
+    let client = redis_conn::open(&config.redis_url)?;
+    redis_conn::verify(&client).await?;
+    let mut conn = ConnectionManager::new(client)
+        .await
+        .context("Failed to open Redis connection manager")?;
+
+    let limit = filter.limit.unwrap_or(usize::MAX);
+    let mut matched = Vec::new();
+    let mut start = "-".to_string();
+
+    'pages: while matched.len() < limit {
+        let page: Vec<(String, Vec<(String, String)>)> = redis::cmd("XRANGE")
+            .arg(DEAD_LETTER_STREAM)
+            .arg(&start)
+            .arg("+")
+            .arg("COUNT")
+            .arg(DEAD_LETTER_PAGE)
+            .query_async(&mut conn)
+            .await?;
+        let Some((last_id, _)) = page.last() else { break };
+        // Exclusive start, so the next page begins after this one
+        start = format!("({}", last_id);
+        let exhausted = page.len() < DEAD_LETTER_PAGE;
+
+        for (entry_id, fields) in page {
+            let field = |name: &str| {
+                fields
+                    .iter()
+                    .find(|(k, _)| k == name)
+                    .map(|(_, v)| v.clone())
+                    .unwrap_or_default()
+            };
+            let entry = DeadLetter {
+                entry_id,
+                job_id: field("job_id"),
+                error_kind: field("error_kind"),
+                error: field("error"),
+            };
+            if filter.job_id.as_ref().is_some_and(|id| *id != entry.job_id)
+                || filter.error_kind.as_ref().is_some_and(|kind| *kind != entry.error_kind)
+            {
+                continue;
+            }
+
+            if !dry_run {
+                let mut job_data: serde_json::Value = match serde_json::from_str(&field("data")) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        warn!("Skipping dead letter {} for job {}: invalid job data: {}", entry.entry_id, entry.job_id, e);
+                        continue;
+                    }
+                };
+                if let Some(job) = job_data.as_object_mut() {
+                    job.remove("attempt");
+                }
+
+                // Reset the record before the job is visible on the queue, so
+                // a worker that picks it up straight away isn't overwritten
+                update_job_fields(
+                    &mut conn,
+                    &entry.job_id,
+                    json!({
+                        "status": "pending",
+                        "progress": 0,
+                        "attempts": 0,
+                        "failed_stage": null,
+                        "error_kind": null,
+                        "error_message": null,
+                        "last_error": null,
+                    }),
+                )
+                .await?;
+                let _: String = redis::cmd("XADD")
+                    .arg("queue:video_processing")
+                    .arg("*")
+                    .arg("job_id")
+                    .arg(&entry.job_id)
+                    .arg("data")
+                    .arg(job_data.to_string())
+                    .query_async(&mut conn)
+                    .await?;
+                let _: i64 = redis::cmd("XDEL")
+                    .arg(DEAD_LETTER_STREAM)
+                    .arg(&entry.entry_id)
+                    .query_async(&mut conn)
+                    .await?;
+                info!("Requeued job {} ({})", entry.job_id, entry.error_kind);
+            }
+
+            matched.push(entry);
+            if matched.len() >= limit {
+                break 'pages;
+            }
+        }
+
+        if exhausted {
+            break;
+        }
+    }
+
+    Ok(matched)
+}
+
+/// Snapshot of the queues and job records, for the stats command
+#[derive(Debug, Clone, Default)]
+pub struct QueueStats {
+    /// Entries on `queue:video_processing`
+    pub queue_length: u64,
+    /// Delivered but unacknowledged entries in the consumer group; None
+    /// when the group hasn't been created yet
+    pub pending: Option<u64>,
+    /// Unacknowledged entries per consumer, most first
+    pub pending_by_consumer: Vec<(String, u64)>,
+    /// Entries on `queue:video_dead_letter`
+    pub dead_letters: u64,
+    /// `job:*` records by status, most common first
+    pub jobs_by_status: Vec<(String, u64)>,
+}
+
+/// Keys fetched per SCAN/MGET round when counting job records
+const STATS_SCAN_COUNT: usize = 500;
+
+/// XPENDING summary form: [count, smallest id, largest id, [[consumer, count], ...]]
+type PendingSummary = (u64, Option<String>, Option<String>, Option<Vec<(String, String)>>);
+
+/// Read queue lengths, `group`'s pending entries, and job counts by status.
+/// Only read commands are sent, so this is safe against a live deployment.
+pub async fn queue_stats(config: &Config, group: &str) -> Result<QueueStats> {
+    let client = redis_conn::open(&config.redis_url)?;
+    redis_conn::verify(&client).await?;
+    let mut conn = ConnectionManager::new(client)
+        .await
+        .context("Failed to open Redis connection manager")?;
+    
+    let queue_length: u64 = redis::cmd("XLEN")
+        .arg("queue:video_processing")
+        .query_async(&mut conn)
+        .await?;
+    let dead_letters: u64 = redis::cmd("XLEN")
+        .arg(DEAD_LETTER_STREAM)
+        .query_async(&mut conn)
+        .await?;
+    
+    let summary: redis::RedisResult<PendingSummary> = redis::cmd("XPENDING")
+        .arg("queue:video_processing")
+        .arg(group)
+        .query_async(&mut conn)
+        .await;
+    let (pending, mut pending_by_consumer) = match summary {
+        Ok((count, _, _, consumers)) => {
+            let consumers = consumers
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, count)| (name, count.parse().unwrap_or(0)))
+                .collect();
+            (Some(count), consumers)
+        }
+        Err(e) if e.code() == Some("NOGROUP") => (None, Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    pending_by_consumer.sort_by(|a: &(String, u64), b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    
+    let mut by_status: std::collections::HashMap<String, u64> = std::collections::HashMap::new();
+    let mut cursor = 0u64;
+    loop {
+        let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg("job:*")
+            .arg("COUNT")
+            .arg(STATS_SCAN_COUNT)
+            .query_async(&mut conn)
+            .await?;
+        if !keys.is_empty() {
+            let values: Vec<Option<String>> = redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+            // Expired between SCAN and MGET: not counted
+            for value in values.into_iter().flatten() {
+                let status = serde_json::from_str::<serde_json::Value>(&value)
+                    .ok()
+                    .and_then(|job| job["status"].as_str().map(|s| s.to_string()))
+                    .unwrap_or_else(|| "unknown".to_string());
+                *by_status.entry(status).or_insert(0) += 1;
+            }
+        }
+        
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+    let mut jobs_by_status: Vec<(String, u64)> = by_status.into_iter().collect();
+    jobs_by_status.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    
+    Ok(QueueStats { queue_length, pending, pending_by_consumer, dead_letters, jobs_by_status })
+}
+
+/// Pipeline progress sink for queued jobs.
+///
+/// The pipeline reports stages synchronously, so updates are queued and
+/// written to `job:{id}` in order by a background task. Call `finish` before
+/// any final status write so a late stage update can't overwrite it.
+struct StageReporter {
+    current: std::sync::Mutex<Option<Stage>>,
+    tx: mpsc::UnboundedSender<StageUpdate>,
+}
+
+enum StageUpdate {
+    Enter(Stage),
+    /// Answered once every earlier update has been written
+    Flush(oneshot::Sender<()>),
+}
+
+impl StageReporter {
+    fn spawn(mut conn: ConnectionManager, job_id: &str) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let job_id = job_id.to_string();
+        
+        // Ends once the reporter is dropped and the queue is drained
+        tokio::spawn(async move {
+            while let Some(update) = rx.recv().await {
+                let stage = match update {
+                    StageUpdate::Enter(stage) => stage,
+                    StageUpdate::Flush(done) => {
+                        let _ = done.send(());
+                        continue;
+                    }
+                };
+                
+                info!("Job {}: entering {} stage", job_id, stage.name());
+                
+                let fields = json!({
+                    "status": stage.status(),
+                    "progress": stage.progress(),
+                    "stage": stage.name(),
+                });
+                if let Err(e) = update_job_fields(&mut conn, &job_id, fields).await {
+                    warn!("Failed to record stage for job {}: {}", job_id, e);
+                }
+            }
+        });
+        
+        Self {
+            current: std::sync::Mutex::new(None),
+            tx,
+        }
+    }
+    
+    fn report(&self, stage: Stage, _percent: u8) {
+        *self.current.lock().unwrap() = Some(stage);
+        let _ = self.tx.send(StageUpdate::Enter(stage));
+    }
+    
+    /// Wait for queued updates to be written; returns the last stage reported
+    async fn finish(&self) -> Option<Stage> {
+        let (done, written) = oneshot::channel();
+        if self.tx.send(StageUpdate::Flush(done)).is_ok() {
+            let _ = written.await;
+        }
+        
+        *self.current.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn failed_enqueue_is_requeued_until_retries_run_out() {
+        let error = WorkerError::Enqueue("timed out after 5s".to_string());
+        
+        // First and second runs are retried, so the parked message is replayed
+        assert!(will_retry(&json!({ "job_id": "abc" }), &error, DEFAULT_MAX_JOB_RETRIES));
+        assert!(will_retry(&json!({ "job_id": "abc", "attempt": 1 }), &error, DEFAULT_MAX_JOB_RETRIES));
+        // The last allowed run is dead-lettered instead
+        assert!(!will_retry(&json!({ "job_id": "abc", "attempt": 2 }), &error, DEFAULT_MAX_JOB_RETRIES));
+    }
+    
+    #[test]
+    fn replayed_parked_job_is_recorded_for_dedup() {
+        let video_info = serde_json::from_value(json!({
+            "duration_seconds": 12.0,
+            "width": 720,
+            "height": 1280,
+            "fps": 30.0,
+            "codec": "h264",
+        }))
+        .unwrap();
+        let result = ProcessResult::new("abc", "video.mp4", video_info, Vec::new(), None, Default::default());
+        let ai_job = AiJob::new("abc", "https://example.com/r/1", result);
+        
+        // Parked and read back the way enqueue_ai_job and load_parked_ai_job do
+        let parked: AiJob = serde_json::from_str(&serde_json::to_string(&ai_job).unwrap()).unwrap();
+        assert!(records_for_dedup(&parked, None));
+        assert_eq!(parked.source_url, "https://example.com/r/1");
+        
+        // Part of the video, or a result reused from another job
+        assert!(!records_for_dedup(&parked, Some(Section { start: 0.0, end: 5.0 })));
+        let mut duplicate = parked;
+        duplicate.duplicate_of = Some("prior".to_string());
+        assert!(!records_for_dedup(&duplicate, None));
+    }
+    
+    #[test]
+    fn idle_timer_expires_only_after_the_limit_without_a_reset() {
+        let start = Instant::now();
+        let mut idle = IdleTimer::new(Some(Duration::from_secs(60)), start);
+        
+        assert_eq!(idle.expired(start + Duration::from_secs(59)), None);
+        assert_eq!(idle.expired(start + Duration::from_secs(60)), Some(Duration::from_secs(60)));
+        
+        // A message read (or a disk pause ending) restarts the count
+        idle.reset(start + Duration::from_secs(50));
+        assert_eq!(idle.expired(start + Duration::from_secs(100)), None);
+        assert!(idle.expired(start + Duration::from_secs(110)).is_some());
+    }
+    
+    #[test]
+    fn idle_timer_without_a_limit_never_expires() {
+        let start = Instant::now();
+        let idle = IdleTimer::new(None, start);
+        assert_eq!(idle.expired(start + Duration::from_secs(86_400)), None);
+    }
+    
+    #[test]
+    fn a_missing_record_is_reported_once_per_job() {
+        assert!(first_missing_record("missing-record-a"));
+        assert!(!first_missing_record("missing-record-a"));
+        assert!(first_missing_record("missing-record-b"));
+    }
+    
+    #[test]
+    fn null_fields_are_removed_from_the_job_record() {
+        let mut job = json!({
+            "job_id": "abc",
+            "status": "failed",
+            "error_kind": "download",
+            "error_message": "HTTP 403",
+        });
+        merge_job_fields(&mut job, &json!({ "status": "pending", "error_kind": null, "error_message": null }));
+        
+        assert_eq!(job["status"], "pending");
+        assert_eq!(job["job_id"], "abc");
+        assert!(job.get("error_kind").is_none());
+        assert!(job.get("error_message").is_none());
+        assert!(job["updated_at"].is_string());
+    }
+}
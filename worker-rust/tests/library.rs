@@ -0,0 +1,34 @@
+//! Exercises the crate through its public facade, the way an embedding
+//! service would.
+
+use serde_json::json;
+use worker_rust::{AiJob, ProcessResult, StageMask, Transcript};
+
+#[test]
+fn stage_list_from_a_queued_job() {
+    let mask = StageMask::from_job(&json!({ "url": "https://example.com/r/1", "stages": ["ocr", "transcribe"] })).unwrap();
+    assert!(mask.ocr && mask.transcribe);
+    assert!(!mask.download && !mask.frames && !mask.audio);
+
+    assert!(StageMask::from_job(&json!({ "url": "https://example.com/r/1" })).unwrap().is_all());
+    assert!(StageMask::parse("ocr,upscale").is_err());
+}
+
+#[test]
+fn ai_job_survives_a_json_round_trip() {
+    let video_info = serde_json::from_value(json!({
+        "duration_seconds": 12.0,
+        "width": 720,
+        "height": 1280,
+        "fps": 30.0,
+        "codec": "h264",
+    }))
+    .unwrap();
+    let result = ProcessResult::new("job-1", "video.mp4", video_info, Vec::new(), None, Transcript::default());
+    let job = AiJob::new("job-1", "https://example.com/r/1", result);
+    let decoded: AiJob = serde_json::from_str(&serde_json::to_string(&job).unwrap()).unwrap();
+
+    assert_eq!(decoded.job_id, "job-1");
+    assert_eq!(decoded.source_url, "https://example.com/r/1");
+    assert_eq!(decoded.video_data.job_id, "job-1");
+}